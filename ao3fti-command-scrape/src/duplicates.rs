@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use ao3fti_common::Conf;
+use ao3fti_indexer::{DuplicatesQuery, IndexServer};
+
+/// Prints every cluster of indexed works whose text is at least `min_similarity` alike.
+#[tracing::instrument(skip(conf), err)]
+pub async fn run(conf: Arc<Conf>, min_similarity: f32) -> Result<(), ao3fti_common::Report> {
+    let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
+    let index = IndexServer::new(&conf)?;
+
+    let duplicates = ao3fti_indexer::duplicates(index, DuplicatesQuery { min_similarity })?;
+
+    for cluster in &duplicates.clusters {
+        println!(
+            "{} works, {:.0}% alike",
+            cluster.ids.len(),
+            cluster.similarity * 100.0
+        );
+
+        for id in &cluster.ids {
+            let story = ao3fti_queries::get_story(pool.clone(), *id).await?;
+
+            println!("  {}/works/{} {}", conf.base_url, id, story.name);
+        }
+    }
+
+    tracing::info!(clusters = duplicates.clusters.len(), "found duplicates");
+
+    Ok(())
+}
@@ -1,108 +1,386 @@
+pub mod duplicates;
+mod limits;
 mod query;
+pub mod sanitize;
+pub mod search;
+pub mod series;
+mod slice;
+pub mod tags;
+pub mod warc;
 
-use std::{fmt::Write as _, sync::Arc};
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
 
 use ao3fti_common::{
     channel::{self, Sender},
     err,
-    models::Rating,
+    models::{Rating, WorkRelation},
     Conf, Context as _, Report, Uri,
 };
 use ao3fti_indexer::StoryData;
-use ao3fti_queries::{Info, Meta, PgTransaction, Pool};
-use futures::future::TryFutureExt as _;
+use ao3fti_queries::{AuthorInfo, Info, Meta, Pool, RelationInfo, SeriesInfo};
+use futures::{
+    future::TryFutureExt as _,
+    stream::{StreamExt as _, TryStreamExt as _},
+};
+use time::{Date, Month};
 use tracing::{Instrument as _, Span};
 
+use crate::limits::Budget;
+pub use crate::{
+    limits::{parse_date, Limits},
+    slice::{Slice, SliceBy},
+};
+
+/// Scrapes and indexes every work of a search or listing page, following its pagination.
+///
+/// Only the path and query of `url` are used, the scheme and host always come from
+/// `conf.base_url`. With a `slice` the search is crawled in date windows, to get past the
+/// point where AO3 stops paginating.
 #[tracing::instrument(skip(conf, url), err)]
-pub async fn run(conf: Arc<Conf>, url: &str) -> Result<(), ao3fti_common::Report> {
+pub async fn run(
+    conf: Arc<Conf>,
+    url: &str,
+    slice: Option<Slice>,
+    limits: Limits,
+) -> Result<(), ao3fti_common::Report> {
+    Box::pin(start(conf, url, None, slice, limits)).await
+}
+
+/// Where scraped works are stored, a dry run has nowhere.
+struct Store<'s> {
+    pool: Pool,
+    line_sender: &'s Sender<StoryData>,
+    crawl_id: i64,
+}
+
+/// Where a crawl starts and how much of it to scrape.
+struct Crawl<'c> {
+    base_url: &'c Uri,
+    start_url: Uri,
+    slice: Option<Slice>,
+    budget: Budget,
+}
+
+/// Records the crawl along with the `search` filters it was built from, then runs it.
+async fn start(
+    conf: Arc<Conf>,
+    url: &str,
+    search: Option<&str>,
+    slice: Option<Slice>,
+    limits: Limits,
+) -> Result<(), ao3fti_common::Report> {
+    let base_url = Uri::try_from(conf.base_url.as_str())
+        .with_context(|| format!("with url, at line {}: `{}`", line!(), conf.base_url))?;
+    let start_url =
+        Uri::try_from(url).with_context(|| format!("with url, at line {}: `{}`", line!(), url))?;
+    let start_url = rebuild_url(&base_url, &start_url)?;
+
+    let crawl = Crawl {
+        base_url: &base_url,
+        start_url,
+        slice,
+        budget: Budget::new(limits),
+    };
+
+    if limits.dry_run {
+        tracing::info!("dry run, nothing will be stored");
+
+        return Box::pin(crawl_all(&conf, None, crawl)).await;
+    }
+
     let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
 
     let (line_sender, line_receiver) = channel::bounded(10_000);
 
     let background_worker = tokio::task::spawn_blocking({
         let span = Span::current();
+        let conf = conf.clone();
 
         move || span.in_scope(|| ao3fti_indexer::index(conf.clone(), line_receiver))
     })
     .map_err(Report::from);
 
-    #[tracing::instrument(skip(pool, url, line_sender), err)]
+    #[tracing::instrument(skip(conf, pool, search, line_sender, crawl), err)]
     async fn inner(
+        conf: &Conf,
         pool: Pool,
-        url: &str,
+        search: Option<&str>,
         line_sender: Sender<StoryData>,
+        crawl: Crawl<'_>,
     ) -> Result<(), ao3fti_common::Report> {
-        let base_url = Uri::try_from(url)
-            .with_context(|| format!("with url, at line {}: `{}`", line!(), url))?;
+        let crawl_id =
+            ao3fti_queries::insert_crawl(pool.clone(), &crawl.start_url.to_string(), search)
+                .await?;
 
-        tracing::info!("starting scrape of search page");
+        tracing::info!(crawl_id = %crawl_id, "recorded crawl");
 
-        let mut url = Some(base_url.clone());
-        let mut page_index = 1;
+        let store = Store {
+            pool,
+            line_sender: &line_sender,
+            crawl_id,
+        };
 
-        while let Some(url_ref) = &url {
-            let span =
-                tracing::debug_span!("search page loop", page_index = page_index).or_current();
+        Box::pin(crawl_all(conf, Some(&store), crawl)).await
+    }
 
-            tracing::info!(url = %url_ref.to_string(), "scraping search page");
+    // TODO(txuritan): make this be only one Result
+    tracing::debug!("starting background indexer and scraper");
+    let (res, _) = tokio::try_join!(
+        background_worker,
+        inner(&conf, pool, search, line_sender, crawl)
+    )?;
+    res?;
 
-            url = scrape_page(pool.clone(), &line_sender, &base_url, url_ref)
-                .instrument(span.clone())
-                .await?
-                .map(|url| rebuild_url(&base_url, &url))
-                .transpose()?;
+    Ok(())
+}
 
-            page_index += 1;
+async fn crawl_all(
+    conf: &Conf,
+    store: Option<&Store<'_>>,
+    crawl: Crawl<'_>,
+) -> Result<(), ao3fti_common::Report> {
+    let Crawl {
+        base_url,
+        start_url,
+        slice,
+        mut budget,
+    } = crawl;
+
+    match slice {
+        Some(slice) => {
+            slice::crawl_sliced(conf, store, base_url, &mut budget, &start_url, slice).await
+        }
+        None => self::crawl(conf, store, base_url, &mut budget, start_url, None).await,
+    }
+}
 
-            ao3fti_common::utils::sleep()
-                .instrument(span.clone())
-                .await?;
+/// Scrapes every page of a search, `html` is the already fetched first page if there is one.
+#[tracing::instrument(skip(conf, store, base_url, budget, url, html), err)]
+async fn crawl(
+    conf: &Conf,
+    store: Option<&Store<'_>>,
+    base_url: &Uri,
+    budget: &mut Budget,
+    url: Uri,
+    mut html: Option<String>,
+) -> Result<(), ao3fti_common::Report> {
+    tracing::info!("starting scrape of search page");
+
+    let mut url = Some(url);
+    let mut page_index = 1;
+
+    while let Some(url_ref) = &url {
+        let span = tracing::debug_span!("search page loop", page_index = page_index).or_current();
+
+        if !budget.take_page() {
+            tracing::info!(parent: &span, limits = ?budget.limits, "reached the crawl limits");
+
+            break;
         }
 
-        Ok(())
-    }
+        tracing::info!(url = %url_ref.to_string(), "scraping search page");
 
-    // TODO(txuritan): make this be only one Result
-    tracing::debug!("starting background indexer and scraper");
-    let (res, _) = tokio::try_join!(background_worker, inner(pool, url, line_sender))?;
-    let _ = res?;
+        let page_html = match html.take() {
+            Some(html) => html,
+            None => {
+                ao3fti_common::utils::req(conf, url_ref)
+                    .instrument(span.clone())
+                    .await?
+            }
+        };
+
+        url = scrape_page(conf, store, base_url, budget, url_ref, &page_html)
+            .instrument(span.clone())
+            .await?
+            .map(|url| rebuild_url(base_url, &url))
+            .transpose()?;
+
+        page_index += 1;
+
+        ao3fti_common::utils::sleep(conf)
+            .instrument(span.clone())
+            .await?;
+    }
 
     Ok(())
 }
 
-#[tracing::instrument(skip(pool, line_sender, base_url, page_url), err)]
+/// Scrapes the works of a search page, or prints them on a dry run. Returns the next page,
+/// unless the work limit was reached.
+#[tracing::instrument(skip(conf, store, base_url, budget, page_url, html), err)]
 async fn scrape_page(
-    pool: Pool,
-    line_sender: &channel::Sender<StoryData>,
+    conf: &Conf,
+    store: Option<&Store<'_>>,
     base_url: &Uri,
+    budget: &mut Budget,
     page_url: &Uri,
+    html: &str,
 ) -> Result<Option<Uri>, ao3fti_common::Report> {
+    let doc = query::Document::try_from(html)?;
+
+    let mut works = Vec::new();
+    let mut limited = false;
+
+    for work in get_page_works(base_url, page_url, &doc)? {
+        if budget.too_old(work.updated) {
+            tracing::debug!(url = %work.url.to_string(), "skipping story updated before --since");
+
+            continue;
+        }
+
+        if !budget.take_work() {
+            tracing::info!(limits = ?budget.limits, "reached the crawl limits");
+
+            limited = true;
+
+            break;
+        }
+
+        match store {
+            Some(_) => works.push(work.url),
+            None => println!("{}", work.url),
+        }
+    }
+
+    if let Some(store) = store {
+        scrape_works(conf, store, base_url, works).await?;
+    }
+
+    if limited {
+        return Ok(None);
+    }
+
+    get_next_page(page_url, &doc)
+}
+
+/// Fetches up to `conf.concurrency` stories at once, then stores them one at a time, each in
+/// its own transaction, in the order they were listed so a crawl always ends up the same.
+#[tracing::instrument(skip(conf, store, base_url, story_urls), err)]
+async fn scrape_works(
+    conf: &Conf,
+    store: &Store<'_>,
+    base_url: &Uri,
+    story_urls: Vec<Uri>,
+) -> Result<(), ao3fti_common::Report> {
+    let mut stories = futures::stream::iter(story_urls.into_iter().enumerate())
+        .map(|(story_index, story_url)| {
+            let span = tracing::debug_span!("story", story_index = story_index).or_current();
+
+            async move {
+                tracing::info!("working on story");
+
+                fetch_story(conf, store.pool.clone(), base_url, &story_url).await
+            }
+            .instrument(span)
+        })
+        .buffered(conf.concurrency.max(1));
+
+    while let Some((story_id, story)) = stories.try_next().await? {
+        store_story(store, story_id, story).await?;
+    }
+
+    Ok(())
+}
+
+/// Links a story to the crawl and stores it, in one transaction, then hands it to the indexer.
+/// Without a `story` it was already stored and is only linked.
+#[tracing::instrument(skip(store, story), err)]
+async fn store_story(
+    store: &Store<'_>,
+    story_id: usize,
+    story: Option<FetchedStory>,
+) -> Result<(), ao3fti_common::Report> {
+    let mut trans = store.pool.begin().await?;
+
+    ao3fti_queries::insert_crawl_story(&mut trans, store.crawl_id, story_id).await?;
+
+    let story = match story {
+        Some(story) => story,
+        None => {
+            trans.commit().await?;
+
+            return Ok(());
+        }
+    };
+
+    tracing::trace!("inserting story into database");
+    ao3fti_queries::insert_story(&mut trans, story_id, story.info, story.meta).await?;
+    ao3fti_queries::insert_story_relations(&mut trans, &story.relations).await?;
+
+    trans.commit().await?;
+
+    store
+        .line_sender
+        .send(story.data)
+        .context("error sending chapter to indexer")?;
+
+    Ok(())
+}
+
+/// Gets the total number of works of a search from its `1 - 20 of 1,234 Works` heading, a
+/// search that fits on one page only has `4 Works`.
+fn get_page_total(doc: &query::Document) -> Result<Option<usize>, ao3fti_common::Report> {
+    static TOTAL_SELECTOR: &str = "html > body > #outer > #inner > #main > h2.heading";
+
+    let text = match doc
+        .select(TOTAL_SELECTOR)?
+        .into_iter()
+        .next()
+        .and_then(|element| element.text())
+    {
+        Some(text) => text,
+        None => return Ok(None),
+    };
+    let text = text.trim();
+
+    let total = match text.split_once(" - ") {
+        Some((_, rest)) => rest.split_once(" of ").map_or(rest, |(_, total)| total),
+        None => text,
+    };
+
+    Ok(total
+        .split_whitespace()
+        .next()
+        .and_then(|total| total.replace(',', "").parse::<usize>().ok()))
+}
+
+/// A work as it's listed on a search page.
+#[derive(Clone, Debug)]
+struct PageWork {
+    url: Uri,
+    /// When the work was last updated, going by its blurb.
+    updated: Option<Date>,
+}
+
+/// Gets every work listed on a search page, skipping restricted works as they can't be viewed
+/// without an account.
+fn get_page_works(
+    base_url: &Uri,
+    page_url: &Uri,
+    doc: &query::Document,
+) -> Result<Vec<PageWork>, ao3fti_common::Report> {
     static LIST_SELECTOR: &str = "html > body > #outer > #inner > #main > ol.work.index.group > li";
     static INFO_SELECTOR: &str = ".header.module > h4.heading > a";
-    static NEXT_SELECTOR: &str =
-        "html > body > #outer > #inner > #main > ol.pagination.actions > li > a[rel=next]";
-    static RESTRICTED_SELECTOR: &str = "div.header.module > h4.heading > img[alt=(Restricted)])";
+    static RESTRICTED_SELECTOR: &str = "div.header.module > h4.heading > img[alt=\"(Restricted)\"]";
+    static DATE_SELECTOR: &str = "div.header.module > p.datetime";
 
-    let html = ao3fti_common::utils::req(page_url).await?;
+    let mut works = Vec::new();
 
-    let doc = query::Document::try_from(html.as_str())?;
-
-    for (story_index, story_element) in doc.select(LIST_SELECTOR).into_iter().enumerate() {
-        tracing::info!(story_index = story_index, "working on story with index of");
-        let restricted = story_element.select(RESTRICTED_SELECTOR);
+    for story_element in doc.select(LIST_SELECTOR)? {
+        let restricted = story_element.select(RESTRICTED_SELECTOR)?;
         if !restricted.is_empty() {
             continue;
         }
 
-        ao3fti_common::utils::sleep()
-            .instrument(Span::current())
-            .await?;
-
         let story_link_element = story_element
-            .select(INFO_SELECTOR)
+            .select(INFO_SELECTOR)?
             .into_iter()
             .next()
-            .ok_or_else(|| err!("unable to find a story url on page `{}`", page_url))?;
+            .ok_or_else(|| err!("selector `{}` matched nothing", INFO_SELECTOR))
+            .with_context(|| format!("unable to find a story url on page `{}`", page_url))?;
 
         let story_link = story_link_element
             .attr("href")
@@ -111,18 +389,50 @@ async fn scrape_page(
 
         let story_url = Uri::try_from(story_link.as_str())
             .with_context(|| format!("with url, at line {}: `{}`", line!(), story_link))?;
-        let story_url = rebuild_url(base_url, &story_url)?;
-
-        let mut trans = pool.begin().await?;
 
-        scrape_story(&mut trans, line_sender, base_url, &story_url).await?;
+        let updated = story_element
+            .select(DATE_SELECTOR)?
+            .into_iter()
+            .next()
+            .and_then(|element| element.text())
+            .and_then(|text| parse_blurb_date(text.trim()));
 
-        trans.commit().await?;
+        works.push(PageWork {
+            url: rebuild_url(base_url, &story_url)?,
+            updated,
+        });
     }
 
-    Ok(match doc.select(NEXT_SELECTOR).into_iter().last() {
+    Ok(works)
+}
+
+/// Parses the `01 Jan 2020` dates of work blurbs.
+fn parse_blurb_date(text: &str) -> Option<Date> {
+    static MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = text.split_whitespace();
+
+    let day = parts.next()?.parse::<u8>().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|name| *name == month)?;
+    let year = parts.next()?.parse::<i32>().ok()?;
+
+    Date::from_calendar_date(year, Month::try_from(month as u8 + 1).ok()?, day).ok()
+}
+
+/// Gets the link to the next search page, if this isn't the last one.
+fn get_next_page(
+    page_url: &Uri,
+    doc: &query::Document,
+) -> Result<Option<Uri>, ao3fti_common::Report> {
+    static NEXT_SELECTOR: &str =
+        "html > body > #outer > #inner > #main > ol.pagination.actions > li > a[rel=next]";
+
+    Ok(match doc.select(NEXT_SELECTOR)?.into_iter().last() {
         Some(element) => {
-            if element.text().unwrap() == "Next →" {
+            if element.text().as_deref() == Some("Next →") {
                 element
                     .attr("href")
                     .map(|url| {
@@ -139,149 +449,302 @@ async fn scrape_page(
     })
 }
 
-#[tracing::instrument(skip(trans, line_sender, base_url, story_url), fields(story_url = %story_url.to_string()), err)]
-async fn scrape_story(
-    trans: &mut PgTransaction<'_>,
-    line_sender: &channel::Sender<StoryData>,
+/// A story that has been fetched and parsed, but not stored yet.
+struct FetchedStory {
+    info: Info,
+    meta: Meta,
+    relations: Vec<RelationInfo>,
+    /// What gets indexed.
+    data: StoryData,
+}
+
+/// The numbers from a work page's stats, which AO3 leaves out when they're zero.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Stats {
+    words: u64,
+    chapters: u64,
+    /// `None` while the author hasn't said how many chapters there will be.
+    chapters_total: Option<u64>,
+    comments: u64,
+    kudos: u64,
+    bookmarks: u64,
+    hits: u64,
+}
+
+/// Fetches and parses a story, unless it's already stored.
+#[tracing::instrument(skip(conf, pool, base_url, story_url), fields(story_url = %story_url.to_string()), err)]
+async fn fetch_story(
+    conf: &Conf,
+    pool: Pool,
     base_url: &Uri,
     story_url: &Uri,
-) -> Result<(), ao3fti_common::Report> {
-    static CHAPTERS_SELECTOR: &str = "#chapters > .userstuff";
+) -> Result<(usize, Option<FetchedStory>), ao3fti_common::Report> {
+    let story_id = get_story_id(story_url)?;
 
-    let story_id = story_url
-        .path()
-        .split('/')
-        .filter(|s| !s.is_empty())
-        .nth(1)
-        .ok_or_else(|| err!("No story ID found in URL"))?;
-    let story_id = story_id.parse::<usize>()?;
-
-    if ao3fti_queries::check_story_if_exists(trans, story_id).await? {
+    if ao3fti_queries::check_story_if_exists(pool, story_id).await? {
         tracing::warn!("story already exists");
 
-        return Ok(());
+        return Ok((story_id, None));
     }
 
     tracing::info!(url = %story_url.to_string(), "scraping story");
 
-    let download_url = get_download_url(story_url).await?;
+    ao3fti_common::utils::sleep(conf)
+        .instrument(Span::current())
+        .await?;
+
+    let story_html = ao3fti_common::utils::req(conf, story_url).await?;
+
+    let story_doc = query::Document::try_from(story_html.as_str())?;
+
+    let download_url = match get_download_url(story_url, &story_doc) {
+        Ok(download_url) => download_url,
+        Err(err) => {
+            tokio::fs::write("./output.html", &story_html)
+                .await
+                .with_context(|| {
+                    format!(
+                        "unable to write out for error debugging url: `{}`",
+                        story_url
+                    )
+                })?;
+
+            return Err(err);
+        }
+    };
     let download_url = Uri::try_from(download_url.as_str())
         .with_context(|| format!("with url, at line {}: `{}`", line!(), download_url))?;
     let download_url = rebuild_url(base_url, &download_url)?;
 
-    ao3fti_common::utils::sleep()
+    ao3fti_common::utils::sleep(conf)
         .instrument(Span::current())
         .await?;
 
-    let download_html = ao3fti_common::utils::req(&download_url).await?;
+    let download_html = ao3fti_common::utils::req(conf, &download_url).await?;
 
-    let download_doc = query::Document::try_from(download_html.as_str())?;
+    let story = parse_story(
+        &conf.base_url,
+        story_id,
+        story_url,
+        &story_doc,
+        &download_html,
+    )?;
 
-    let mut content_buffer = String::with_capacity(download_html.len());
-    fn write(buffer: &mut String, typ: &str, entry: &str) -> Result<(), ao3fti_common::Report> {
-        if entry.contains(char::is_whitespace) {
-            writeln!(buffer, "{}:\"{}\"", typ, entry)?;
-        } else {
-            writeln!(buffer, "{}:{}", typ, entry)?;
-        }
+    Ok((story_id, Some(story)))
+}
 
-        Ok(())
-    }
+fn get_story_id(story_url: &Uri) -> Result<usize, ao3fti_common::Report> {
+    let story_id = story_url
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .nth(1)
+        .ok_or_else(|| err!("No story ID found in URL"))?;
 
-    tracing::trace!("writing story head information to story buffer");
-    let info = get_story_info(story_url, &download_doc)?;
-    write(&mut content_buffer, "title", &info.name)?;
-    for entry in &info.authors {
-        write(&mut content_buffer, "author", entry)?;
-    }
-    write(&mut content_buffer, "summary", &info.summary)?;
-
-    tracing::trace!("writing story meta information to story buffer");
-    let meta = get_story_meta(&download_doc);
-    for (typ, entries) in [
-        ("category", &meta.categories),
-        ("origin", &meta.origins),
-        ("warning", &meta.warnings),
-        ("pairing", &meta.pairings),
-        ("character", &meta.characters),
-        ("tag", &meta.generals),
-    ] {
-        for entry in entries {
-            write(&mut content_buffer, typ, entry)?;
-        }
-    }
+    Ok(story_id.parse::<usize>()?)
+}
 
-    tracing::trace!("inserting story into database");
-    if ao3fti_queries::insert_story(trans, story_id, info, meta).await? {
-        return Ok(());
-    }
+/// Parses a story from its work page and download, into what gets stored and indexed.
+fn parse_story(
+    base_url: &str,
+    story_id: usize,
+    story_url: &Uri,
+    story_doc: &query::Document,
+    download_html: &str,
+) -> Result<FetchedStory, ao3fti_common::Report> {
+    static CHAPTERS_SELECTOR: &str = "#chapters > .userstuff";
+    static PARAGRAPHS_SELECTOR: &str = "p";
+
+    let download_doc = query::Document::try_from(download_html)?;
+
+    tracing::trace!("parsing story information");
+    let info = get_story_info(base_url, story_url, &download_doc)?;
+    let meta = get_story_meta(&download_doc)?;
+    let stats = get_story_stats(story_url, story_doc)?;
+    let language = get_story_language(story_doc)?;
+    let relations = get_story_relations(story_id, story_doc)?;
 
-    writeln!(&mut content_buffer)?;
+    let mut contents = Vec::new();
 
     for (chapter_id, chapter) in download_doc
-        .select(CHAPTERS_SELECTOR)
+        .select(CHAPTERS_SELECTOR)?
         .into_iter()
         .enumerate()
     {
         tracing::debug!(story_id = %story_id, chapter_number = %chapter_id, "indexing chapter");
 
-        let chapter_content = chapter.text().unwrap();
-        content_buffer.push_str(&chapter_content);
+        // only the text of a chapter is kept, unlike summaries there's no markup to sanitize
+        let mut paragraphs = chapter
+            .select(PARAGRAPHS_SELECTOR)?
+            .into_iter()
+            .filter_map(|paragraph| paragraph.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>();
+
+        // chapters written without paragraphs are one long one
+        if paragraphs.is_empty() {
+            paragraphs.extend(
+                chapter
+                    .text()
+                    .map(|text| text.trim().to_string())
+                    .filter(|text| !text.is_empty()),
+            );
+        }
+
+        contents.push(paragraphs);
     }
 
-    line_sender
-        .send(StoryData {
-            id: story_id,
-            contents: content_buffer,
-        })
-        .context("error sending chapter to indexer")?;
+    let data = StoryData {
+        id: story_id,
+        language,
+        title: info.name.clone(),
+        authors: info
+            .authors
+            .iter()
+            .flat_map(|author| {
+                let user = (author.user != author.pseud).then(|| author.user.clone());
+
+                std::iter::once(author.pseud.clone()).chain(user)
+            })
+            .collect(),
+        summary: html_text(&info.summary)?,
+        fandoms: meta.origins.clone(),
+        ships: meta.pairings.clone(),
+        characters: meta.characters.clone(),
+        tags: meta.generals.clone(),
+        warnings: meta.warnings.clone(),
+        categories: meta.categories.clone(),
+        series: meta.series.iter().map(|part| part.name.clone()).collect(),
+        rating: meta.rating.clone(),
+        words: stats.words,
+        chapters: stats.chapters,
+        complete: stats.chapters_total == Some(stats.chapters),
+        comments: stats.comments,
+        kudos: stats.kudos,
+        bookmarks: stats.bookmarks,
+        hits: stats.hits,
+        contents,
+    };
 
-    Ok(())
+    Ok(FetchedStory {
+        info,
+        meta,
+        relations,
+        data,
+    })
+}
+
+/// Gets the text of a fragment of HTML, for summaries which are stored as HTML.
+fn html_text(html: &str) -> Result<String, ao3fti_common::Report> {
+    let doc = query::Document::try_from(html)?;
+
+    Ok(doc
+        .select("body")?
+        .into_iter()
+        .next()
+        .and_then(|body| body.text())
+        .unwrap_or_default())
+}
+
+/// Gets the stats of a work page, the same for single and multi chapter works but wrapped
+/// differently.
+fn get_story_stats(story_url: &Uri, doc: &query::Document) -> Result<Stats, ao3fti_common::Report> {
+    static STATS_SELECTOR: &str =
+        "html > body > #outer > #inner > #main dl.work.meta.group > dd.stats > dl.stats > dd";
+
+    let elements = doc.select(STATS_SELECTOR)?;
+    if elements.is_empty() {
+        return Err(err!("selector `{}` matched nothing", STATS_SELECTOR))
+            .with_context(|| format!("unable to scrape stats for `{}`", story_url));
+    }
+
+    let count = |text: &str| {
+        text.trim()
+            .replace(',', "")
+            .parse::<u64>()
+            .with_context(|| format!("invalid stat `{}` for `{}`", text.trim(), story_url))
+    };
+
+    let mut stats = Stats::default();
+
+    for element in elements {
+        let (class, text) = match (element.attr("class"), element.text()) {
+            (Some(class), Some(text)) => (class, text),
+            _ => continue,
+        };
+
+        match class.as_str() {
+            "words" => stats.words = count(&text)?,
+            // `2/10`, or `2/?` when there's no telling
+            "chapters" => {
+                let (chapters, total) = text
+                    .split_once('/')
+                    .ok_or_else(|| err!("invalid chapters `{}` for `{}`", text, story_url))?;
+
+                stats.chapters = count(chapters)?;
+                stats.chapters_total = match total.trim() {
+                    "?" => None,
+                    total => Some(count(total)?),
+                };
+            }
+            "comments" => stats.comments = count(&text)?,
+            "kudos" => stats.kudos = count(&text)?,
+            "bookmarks" => stats.bookmarks = count(&text)?,
+            "hits" => stats.hits = count(&text)?,
+            _ => {}
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Gets the language code of a work page, which AO3 puts in the `lang` of its language name.
+fn get_story_language(doc: &query::Document) -> Result<Option<String>, ao3fti_common::Report> {
+    static LANGUAGE_SELECTOR: &str =
+        "html > body > #outer > #inner > #main dl.work.meta.group > dd.language";
+
+    Ok(doc
+        .select(LANGUAGE_SELECTOR)?
+        .into_iter()
+        .next()
+        .and_then(|element| element.attr("lang"))
+        .map(|lang| lang.trim().to_string())
+        .filter(|lang| !lang.is_empty()))
 }
 
-#[tracing::instrument(skip(story_url), err)]
-async fn get_download_url(story_url: &Uri) -> Result<String, ao3fti_common::Report> {
+#[tracing::instrument(skip(story_url, doc), err)]
+fn get_download_url(
+    story_url: &Uri,
+    doc: &query::Document,
+) -> Result<String, ao3fti_common::Report> {
     static STORY_MULTI_DOWNLOAD_BUTTON: &str =
         "html > body > #outer > #inner > #main > .work > .navigation.actions > .download > ul > li > a";
     static STORY_SINGLE_DOWNLOAD_BUTTON: &str =
         "html > body > #outer > #inner > #main > .work.navigation.actions > .download > ul > li > a";
 
-    let story_html = ao3fti_common::utils::req(story_url).await?;
-
-    let doc = query::Document::try_from(story_html.as_str())?;
-
     let download_elements = {
-        let temp = doc.select(STORY_MULTI_DOWNLOAD_BUTTON);
+        let temp = doc.select(STORY_MULTI_DOWNLOAD_BUTTON)?;
 
         if temp.is_empty() {
-            doc.select(STORY_SINGLE_DOWNLOAD_BUTTON)
+            doc.select(STORY_SINGLE_DOWNLOAD_BUTTON)?
         } else {
             temp
         }
     };
 
-    let download_element = {
-        let this = download_elements.into_iter().last();
-
-        match this {
-            Some(v) => Ok(v),
-            None => {
-                tokio::fs::write("./output.html", &story_html)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "unable to write out for error debugging url: `{}`",
-                            story_url
-                        )
-                    })?;
-
-                Err(err!(
-                    "Unable to select the download link for `{}`",
-                    story_url
-                ))
-            }
-        }
-    }?;
+    let download_element = download_elements
+        .into_iter()
+        .last()
+        .ok_or_else(|| {
+            err!(
+                "selectors `{}` and `{}` matched nothing",
+                STORY_MULTI_DOWNLOAD_BUTTON,
+                STORY_SINGLE_DOWNLOAD_BUTTON
+            )
+        })
+        .with_context(|| format!("unable to select the download link for `{}`", story_url))?;
 
     let href = download_element
         .attr("href")
@@ -290,14 +753,83 @@ async fn get_download_url(story_url: &Uri) -> Result<String, ao3fti_common::Repo
     Ok(href)
 }
 
+/// Collects the inspiration and translation links of a work page, stored in the direction of
+/// `story_id` being inspired by, or a translation of, `related_id`.
+fn get_story_relations(
+    story_id: usize,
+    doc: &query::Document,
+) -> Result<Vec<RelationInfo>, ao3fti_common::Report> {
+    static STORY_ASSOCIATIONS: &str = "#workskin .preface .notes ul.associations > li";
+    static STORY_CHILDREN: &str = "#children > ul > li";
+
+    fn linked_work(element: &query::Element) -> Result<Option<usize>, query::SelectorError> {
+        Ok(element
+            .select("a")?
+            .into_iter()
+            .filter_map(|link| link.attr("href"))
+            .find(|href| href.contains("/works/"))
+            .and_then(|href| path_id(&href)))
+    }
+
+    let mut relations = Vec::new();
+
+    for association in doc.select(STORY_ASSOCIATIONS)? {
+        let text = match association.text() {
+            Some(mut text) => {
+                string_trim(&mut text);
+
+                text
+            }
+            None => continue,
+        };
+
+        let linked_id = match linked_work(&association)? {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let (from_id, to_id, relation) = if text.starts_with("Inspired by") {
+            (story_id, linked_id, WorkRelation::InspiredBy)
+        } else if text.starts_with("A translation of") {
+            (story_id, linked_id, WorkRelation::TranslationOf)
+        } else if text.starts_with("Translation into") {
+            (linked_id, story_id, WorkRelation::TranslationOf)
+        } else {
+            continue;
+        };
+
+        relations.push(RelationInfo {
+            story_id: from_id,
+            related_id: to_id,
+            relation,
+        });
+    }
+
+    for child in doc.select(STORY_CHILDREN)? {
+        if let Some(child_id) = linked_work(&child)? {
+            relations.push(RelationInfo {
+                story_id: child_id,
+                related_id: story_id,
+                relation: WorkRelation::InspiredBy,
+            });
+        }
+    }
+
+    Ok(relations)
+}
+
 #[tracing::instrument(skip(story_url, doc), err)]
-fn get_story_info(story_url: &Uri, doc: &query::Document) -> Result<Info, ao3fti_common::Report> {
+fn get_story_info(
+    base_url: &str,
+    story_url: &Uri,
+    doc: &query::Document,
+) -> Result<Info, ao3fti_common::Report> {
     static STORY_NAME: &str = "html > body > #preface > .meta > h1";
     static STORY_AUTHOR: &str = "html > body > #preface > .meta > .byline > a[rel=\"author\"]";
     static STORY_SUMMARY: &str = "html > body > #preface > .meta > blockquote";
 
     let name = doc
-        .select(STORY_NAME)
+        .select(STORY_NAME)?
         .into_iter()
         .next()
         .and_then(|element| element.text())
@@ -306,28 +838,32 @@ fn get_story_info(story_url: &Uri, doc: &query::Document) -> Result<Info, ao3fti
 
             name
         })
-        .ok_or_else(|| err!("unable to scrape name"))
+        .ok_or_else(|| err!("selector `{}` matched nothing", STORY_NAME))
+        .context("unable to scrape name")
         .with_context(|| format!("with url, at line {}: `{}`", line!(), story_url))?;
 
     let authors = doc
-        .select(STORY_AUTHOR)
+        .select(STORY_AUTHOR)?
         .into_iter()
         .map(|element| {
             element.text().map(|mut name| {
                 string_trim(&mut name);
 
-                name
+                get_author(base_url, &name, element.attr("href").as_deref())
             })
         })
-        .collect::<Option<Vec<String>>>()
+        .collect::<Option<Vec<AuthorInfo>>>()
         .ok_or_else(|| err!("unable to scrape authors"))
         .with_context(|| format!("with url, at line {}: `{}`", line!(), story_url))?;
 
     let summary = doc
-        .select(STORY_SUMMARY)
+        .select(STORY_SUMMARY)?
         .into_iter()
         .next()
         .and_then(|element| element.inner_html())
+        .map(|summary| sanitize::sanitize(&summary))
+        .transpose()
+        .with_context(|| format!("with url, at line {}: `{}`", line!(), story_url))?
         .unwrap_or_else(|| String::from("<p></p>"));
 
     Ok(Info {
@@ -337,7 +873,48 @@ fn get_story_info(story_url: &Uri, doc: &query::Document) -> Result<Info, ao3fti
     })
 }
 
-fn get_story_meta(doc: &query::Document) -> Meta {
+/// Splits an author link into its user and pseud, the text reads `pseud (user)` unless the
+/// pseud is the user's default one, the link is `/users/<user>/pseuds/<pseud>`.
+fn get_author(base_url: &str, text: &str, href: Option<&str>) -> AuthorInfo {
+    let (pseud, text_user) = match text
+        .strip_suffix(')')
+        .and_then(|text| text.rsplit_once(" ("))
+    {
+        Some((pseud, user)) => (pseud, Some(user)),
+        None => (text, None),
+    };
+
+    let href_user = href
+        .and_then(|href| Uri::try_from(href).ok())
+        .and_then(|url| {
+            let mut segments = url.path().split('/').filter(|s| !s.is_empty());
+
+            match (segments.next(), segments.next()) {
+                (Some("users"), Some(user)) => Some(user.to_string()),
+                _ => None,
+            }
+        });
+
+    let user = href_user
+        .or_else(|| text_user.map(String::from))
+        .unwrap_or_else(|| pseud.to_string());
+
+    let user_url = format!("{}/users/{}", base_url, user);
+    let pseud_url = match href {
+        Some(href) if href.starts_with('/') => format!("{}{}", base_url, href),
+        Some(href) => href.to_string(),
+        None => format!("{}/pseuds/{}", user_url, pseud),
+    };
+
+    AuthorInfo {
+        user,
+        user_url,
+        pseud: pseud.to_string(),
+        pseud_url,
+    }
+}
+
+fn get_story_meta(doc: &query::Document) -> Result<Meta, ao3fti_common::Report> {
     static META_TAGS_DT: &str = "html > body > #preface > .meta > .tags > dt";
     static META_TAGS_DF: &str = "html > body > #preface > .meta > .tags > dd";
 
@@ -352,10 +929,17 @@ fn get_story_meta(doc: &query::Document) -> Meta {
     let mut characters = Vec::new();
     let mut generals = Vec::new();
 
-    let detail_names = doc.select(META_TAGS_DT);
-    let detail_definitions = doc.select(META_TAGS_DF);
+    let mut series = Vec::new();
+
+    let detail_names = doc.select(META_TAGS_DT)?;
+    let detail_definitions = doc.select(META_TAGS_DF)?;
+
+    // every work has at least a rating, so no tags at all means the markup changed
+    if detail_names.is_empty() {
+        return Err(err!("selector `{}` matched nothing", META_TAGS_DT));
+    }
 
-    let nodes = detail_names.into_iter().zip(detail_definitions.into_iter());
+    let nodes = detail_names.into_iter().zip(detail_definitions);
     for (detail_names, detail_definition) in nodes {
         let text = match detail_names.text().map(|mut text| {
             string_trim(&mut text);
@@ -368,7 +952,7 @@ fn get_story_meta(doc: &query::Document) -> Meta {
 
         let list = match text.as_str() {
             "Rating:" => {
-                let text = detail_definition.children().get(0).and_then(|node| {
+                let text = detail_definition.children().first().and_then(|node| {
                     node.text().map(|mut text| {
                         string_trim(&mut text);
 
@@ -393,6 +977,11 @@ fn get_story_meta(doc: &query::Document) -> Meta {
             "Relationship:" => Some(&mut pairings),
             "Character:" => Some(&mut characters),
             "Additional Tags:" => Some(&mut generals),
+            "Series:" => {
+                series.extend(get_story_series(&detail_definition)?);
+
+                None
+            }
             _ => None,
         };
 
@@ -405,7 +994,7 @@ fn get_story_meta(doc: &query::Document) -> Meta {
         }
     }
 
-    Meta {
+    Ok(Meta {
         rating,
         categories,
         origins,
@@ -413,7 +1002,51 @@ fn get_story_meta(doc: &query::Document) -> Meta {
         pairings,
         characters,
         generals,
-    }
+        series,
+    })
+}
+
+/// Parses a `Series:` entry, which reads as `Part 2 of <a>Series</a>` once for every series
+/// the story is in.
+fn get_story_series(
+    detail_definition: &query::Element,
+) -> Result<Vec<SeriesInfo>, query::SelectorError> {
+    let text = detail_definition.text().unwrap_or_default();
+
+    let positions = text.split("Part ").skip(1).map(|part| {
+        part.split_whitespace()
+            .next()
+            .and_then(|position| position.parse::<usize>().ok())
+    });
+
+    Ok(detail_definition
+        .select("a")?
+        .into_iter()
+        .zip(positions)
+        .filter_map(|(element, position)| {
+            let id = element.attr("href").as_deref().and_then(path_id)?;
+            let mut name = element.text()?;
+            string_trim(&mut name);
+
+            Some(SeriesInfo {
+                id,
+                name,
+                position: position?,
+            })
+        })
+        .collect())
+}
+
+/// Gets the numeric id from a work or series url, ie `/works/1234` or `https://archiveofourown.org/series/1234`.
+fn path_id(url: &str) -> Option<usize> {
+    let url = Uri::try_from(url).ok()?;
+
+    url.path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .nth(1)?
+        .parse::<usize>()
+        .ok()
 }
 
 fn rebuild_url(base_url: &Uri, new_url: &Uri) -> Result<Uri, ao3fti_common::Report> {
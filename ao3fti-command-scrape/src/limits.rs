@@ -0,0 +1,85 @@
+use ao3fti_common::{err, Context as _, Report};
+use time::{Date, Month};
+
+/// Bounds on how much of a search is crawled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// The most listing pages to crawl, across every window of a sliced crawl.
+    pub max_pages: Option<usize>,
+    /// The most works to take from listings, whether or not they're already stored.
+    pub max_works: Option<usize>,
+    /// Skip works that were last updated before this day.
+    pub since: Option<Date>,
+    /// Only fetch listings and print the works that would be scraped, nothing is stored.
+    pub dry_run: bool,
+}
+
+/// What's left of the [`Limits`] as a crawl goes on.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    pub(crate) limits: Limits,
+    pages: usize,
+    works: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            pages: 0,
+            works: 0,
+        }
+    }
+
+    /// If there's no point in fetching another listing page.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.limits.max_pages.is_some_and(|max| self.pages >= max)
+            || self.limits.max_works.is_some_and(|max| self.works >= max)
+    }
+
+    /// Counts a listing page, unless there's nothing left to spend on it.
+    pub(crate) fn take_page(&mut self) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+
+        self.pages += 1;
+
+        true
+    }
+
+    /// Counts a work, unless the work limit has been reached.
+    pub(crate) fn take_work(&mut self) -> bool {
+        if self.limits.max_works.is_some_and(|max| self.works >= max) {
+            return false;
+        }
+
+        self.works += 1;
+
+        true
+    }
+
+    /// If a work last updated on `updated` is older than `--since`, works without a date are
+    /// kept.
+    pub(crate) fn too_old(&self, updated: Option<Date>) -> bool {
+        matches!((self.limits.since, updated), (Some(since), Some(updated)) if updated < since)
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date.
+pub fn parse_date(s: &str) -> Result<Date, Report> {
+    fn inner(s: &str) -> Result<Date, Report> {
+        let (year, rest) = s.split_once('-').ok_or_else(|| err!("missing the month"))?;
+        let (month, day) = rest
+            .split_once('-')
+            .ok_or_else(|| err!("missing the day"))?;
+
+        Ok(Date::from_calendar_date(
+            year.parse()?,
+            Month::try_from(month.parse::<u8>()?)?,
+            day.parse()?,
+        )?)
+    }
+
+    inner(s).with_context(|| format!("invalid date `{}`, expected one like `2022-08-16`", s))
+}
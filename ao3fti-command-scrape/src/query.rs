@@ -1,6 +1,6 @@
 // Modified version of crabquery
 
-use std::{cell::Ref, collections::HashMap, convert::TryFrom, sync::Arc};
+use std::{convert::TryFrom, fmt, sync::Arc};
 
 use html5ever::{
     driver::ParseOpts,
@@ -9,7 +9,7 @@ use html5ever::{
     tendril::TendrilSink,
     tree_builder::TreeBuilderOpts,
 };
-use markup5ever::{Attribute, QualName};
+use markup5ever::Attribute;
 use markup5ever_arcdom::{ArcDom, Handle, NodeData, SerializableHandle};
 
 pub struct Document {
@@ -39,260 +39,805 @@ impl TryFrom<&str> for Document {
 }
 
 impl Document {
-    pub fn select(&self, selector: impl Into<Selector>) -> Vec<Element> {
-        let sel: Selector = selector.into();
-        sel.find(self.doc.document.children.borrow())
+    pub fn select(&self, selector: &str) -> Result<Vec<Element>, SelectorError> {
+        let sel = Selector::try_from(selector)?;
+
+        Ok(sel.find(&self.doc.document))
     }
 }
 
+/// A selector that failed to parse, with the byte offset where parsing stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorError {
+    pub selector: String,
+    pub position: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid selector `{}` at position {}: {}",
+            self.selector, self.position, self.reason
+        )
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+/// A comma separated list of selectors, matching elements that match any of them.
 #[derive(Debug, PartialEq, Clone)]
-enum AttributeSpec {
-    Present,
-    Exact(String),
-    Starts(String),
-    Ends(String),
-    Contains(String),
+pub struct Selector {
+    list: Vec<Complex>,
 }
 
-impl AttributeSpec {
-    fn matches(&self, other: String) -> bool {
-        use AttributeSpec::*;
+/// Compound selectors joined by combinators, `combinators[i]` sits between `compounds[i]`
+/// and `compounds[i + 1]`.
+#[derive(Debug, PartialEq, Clone)]
+struct Complex {
+    compounds: Vec<Compound>,
+    combinators: Vec<Combinator>,
+}
 
-        match self {
-            Present => true,
-            Exact(v) => &other == v,
-            Starts(v) => other.starts_with(v),
-            Ends(v) => other.ends_with(v),
-            Contains(v) => other.contains(v),
-        }
-    }
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+struct Compound {
+    /// The type selector, `None` for both `*` and when it's left out.
+    tag: Option<String>,
+    simples: Vec<Simple>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
-struct Matcher {
-    tag: Vec<String>,
-    class: Vec<String>,
-    id: Vec<String>,
-    attribute: HashMap<String, AttributeSpec>,
-    direct_match: bool,
-}
-
-impl From<String> for Matcher {
-    fn from(input: String) -> Self {
-        Self::from(input.as_str())
-    }
-}
-
-impl From<&str> for Matcher {
-    fn from(input: &str) -> Self {
-        let mut segments = vec![];
-        let mut buf = "".to_string();
-
-        for c in input.chars() {
-            match c {
-                '>' => {
-                    return Self {
-                        tag: vec![],
-                        class: vec![],
-                        id: vec![],
-                        attribute: HashMap::new(),
-                        direct_match: true,
-                    };
+enum Simple {
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        op: AttributeOp,
+        value: String,
+        case_insensitive: bool,
+    },
+    /// `:nth-child(an+b)` and friends, `:first-child` is `:nth-child(1)` and so on.
+    Nth {
+        of_type: bool,
+        from_end: bool,
+        a: i32,
+        b: i32,
+    },
+    Only {
+        of_type: bool,
+    },
+    Empty,
+    Root,
+    Not(Vec<Complex>),
+    Is(Vec<Complex>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum AttributeOp {
+    /// `[attr]`
+    Exists,
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`
+    Includes,
+    /// `[attr|=value]`
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
+}
+
+impl TryFrom<&str> for Selector {
+    type Error = SelectorError;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let mut parser = Parser { input, pos: 0 };
+
+        let list = parser.parse_list(false)?;
+
+        Ok(Selector { list })
+    }
+}
+
+impl std::str::FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Selector::try_from(input)
+    }
+}
+
+struct Parser<'i> {
+    input: &'i str,
+    pos: usize,
+}
+
+impl<'i> Parser<'i> {
+    fn error<T>(&self, position: usize, reason: impl Into<String>) -> Result<T, SelectorError> {
+        Err(SelectorError {
+            selector: self.input.to_string(),
+            position,
+            reason: reason.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SelectorError> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.bump();
+
+                Ok(())
+            }
+            Some(found) => self.error(self.pos, format!("expected `{}`, found `{}`", c, found)),
+            None => self.error(self.pos, format!("expected `{}`, found the end", c)),
+        }
+    }
+
+    /// Skips whitespace, returning if there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.bump();
+        }
+
+        self.pos != start
+    }
+
+    fn parse_list(&mut self, nested: bool) -> Result<Vec<Complex>, SelectorError> {
+        let mut list = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            list.push(self.parse_complex()?);
+            self.skip_whitespace();
+
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(')') if nested => break,
+                None if !nested => break,
+                None => return self.error(self.pos, "expected `)`, found the end"),
+                Some(c) => return self.error(self.pos, format!("unexpected `{}`", c)),
+            }
+        }
+
+        Ok(list)
+    }
+
+    fn parse_complex(&mut self) -> Result<Complex, SelectorError> {
+        let mut compounds = vec![self.parse_compound()?];
+        let mut combinators = Vec::new();
+
+        loop {
+            let whitespace = self.skip_whitespace();
+
+            let combinator = match self.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(',') | Some(')') | None => break,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(c) => return self.error(self.pos, format!("unexpected `{}`", c)),
+            };
+
+            if combinator != Combinator::Descendant {
+                self.bump();
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(self.parse_compound()?);
+        }
+
+        Ok(Complex {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn parse_compound(&mut self) -> Result<Compound, SelectorError> {
+        let start = self.pos;
+        let mut compound = Compound::default();
+
+        match self.peek() {
+            Some('*') => {
+                self.bump();
+            }
+            Some(c) if is_ident_start(c) => {
+                compound.tag = Some(self.parse_ident()?.to_ascii_lowercase());
+            }
+            _ => {}
+        }
+
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.bump();
+                    compound.simples.push(Simple::Id(self.parse_ident()?));
                 }
-                '#' | '.' | '[' => {
-                    segments.push(buf);
-                    buf = "".to_string();
+                Some('.') => {
+                    self.bump();
+                    compound.simples.push(Simple::Class(self.parse_ident()?));
                 }
-                ']' => {
-                    segments.push(buf);
-                    buf = "".to_string();
-                    continue;
+                Some('[') => {
+                    self.bump();
+                    compound.simples.push(self.parse_attribute()?);
                 }
-                _ => {}
+                Some(':') => {
+                    self.bump();
+                    compound.simples.push(self.parse_pseudo()?);
+                }
+                _ => break,
+            }
+        }
+
+        if self.pos == start {
+            return match self.peek() {
+                Some(c) => self.error(self.pos, format!("expected a selector, found `{}`", c)),
+                None => self.error(self.pos, "expected a selector, found the end"),
             };
+        }
 
-            buf.push(c);
+        Ok(compound)
+    }
+
+    fn parse_ident(&mut self) -> Result<String, SelectorError> {
+        let start = self.pos;
+        let mut ident = String::new();
+
+        while let Some(c) = self.peek() {
+            if c == '\\' {
+                let escape = self.pos;
+                self.bump();
+
+                if self.peek() == Some('\n') {
+                    return self.error(escape, "a newline can't be escaped outside of a string");
+                }
+
+                match self.parse_escape() {
+                    Some(escaped) => ident.push(escaped),
+                    None => return self.error(self.pos, "unterminated escape"),
+                }
+            } else if is_ident(c) {
+                self.bump();
+                ident.push(c);
+            } else {
+                break;
+            }
         }
-        segments.push(buf);
-
-        let mut res = Self {
-            tag: vec![],
-            class: vec![],
-            id: vec![],
-            attribute: HashMap::new(),
-            direct_match: false,
-        };
 
-        for segment in segments {
-            match segment.chars().next() {
-                Some('#') => res.id.push(segment[1..].to_string()),
-                Some('.') => res.class.push(segment[1..].to_string()),
-                Some('[') => res.add_data_attribute(segment[1..].to_string()),
-                None => {}
-                _ => res.tag.push(segment),
+        if ident.is_empty() {
+            return self.error(start, "expected an identifier");
+        }
+
+        Ok(ident)
+    }
+
+    fn parse_string(&mut self) -> Result<String, SelectorError> {
+        let start = self.pos;
+        let quote = self.bump();
+        let mut value = String::new();
+
+        loop {
+            match self.bump() {
+                // an escaped newline continues the string on the next line
+                Some('\\') if self.eat('\n') => {}
+                Some('\\') => match self.parse_escape() {
+                    Some(escaped) => value.push(escaped),
+                    None => return self.error(start, "unterminated string"),
+                },
+                Some(c) if Some(c) == quote => break,
+                Some(c) => value.push(c),
+                None => return self.error(start, "unterminated string"),
             }
         }
 
-        res
+        Ok(value)
     }
-}
 
-impl Matcher {
-    fn add_data_attribute(&mut self, spec: String) {
-        use AttributeSpec::*;
+    /// Parses what follows a `\`, either up to six hex digits of a code point, ended early by a
+    /// single whitespace so `\31 23` is `123`, or any other character as itself.
+    fn parse_escape(&mut self) -> Option<char> {
+        let start = self.pos;
 
-        let parts = spec.split('=').collect::<Vec<_>>();
+        while self.pos - start < 6 && matches!(self.peek(), Some(c) if c.is_ascii_hexdigit()) {
+            self.bump();
+        }
 
-        if parts.len() == 1 {
-            let k = parts[0];
-            self.attribute.insert(k.to_string(), Present);
-            return;
+        if self.pos == start {
+            return self.bump();
         }
 
-        let v = parts[1].trim_matches('"').to_string();
-        let k = parts[0];
-        let k = k[..k.len() - 1].to_string();
+        let code = u32::from_str_radix(&self.input[start..self.pos], 16).unwrap_or(0);
+
+        if self.eat('\r') {
+            self.eat('\n');
+        } else if matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.bump();
+        }
 
-        match parts[0].chars().last() {
-            Some('^') => {
-                self.attribute.insert(k, Starts(v));
+        // zero, surrogates and anything past the last code point are replaced, as browsers do
+        Some(
+            char::from_u32(code)
+                .filter(|c| *c != '\0')
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
+    }
+
+    fn parse_attribute(&mut self) -> Result<Simple, SelectorError> {
+        self.skip_whitespace();
+        let name = self.parse_ident()?.to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let op = match self.peek() {
+            Some(']') => {
+                self.bump();
+
+                return Ok(Simple::Attribute {
+                    name,
+                    op: AttributeOp::Exists,
+                    value: String::new(),
+                    case_insensitive: false,
+                });
             }
-            Some('$') => {
-                self.attribute.insert(k, Ends(v));
+            Some('=') => {
+                self.bump();
+
+                AttributeOp::Equals
             }
-            Some('*') => {
-                self.attribute.insert(k, Contains(v));
+            Some(c @ ('~' | '|' | '^' | '$' | '*')) => {
+                self.bump();
+                self.expect('=')?;
+
+                match c {
+                    '~' => AttributeOp::Includes,
+                    '|' => AttributeOp::DashMatch,
+                    '^' => AttributeOp::Prefix,
+                    '$' => AttributeOp::Suffix,
+                    _ => AttributeOp::Substring,
+                }
             }
-            Some(_) => {
-                let k = parts[0].to_string();
-                self.attribute.insert(k, Exact(v));
+            Some(c) => return self.error(self.pos, format!("unexpected `{}` in attribute", c)),
+            None => return self.error(self.pos, "unterminated attribute selector"),
+        };
+
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some('"') | Some('\'') => self.parse_string()?,
+            _ => self.parse_ident()?,
+        };
+        self.skip_whitespace();
+
+        let case_insensitive = match self.peek() {
+            Some('i') | Some('I') => {
+                self.bump();
+                self.skip_whitespace();
+
+                true
             }
-            None => {
-                panic!("Colud not parse attribute spec \"{}\"", spec);
+            Some('s') | Some('S') => {
+                self.bump();
+                self.skip_whitespace();
+
+                false
             }
-        }
+            _ => false,
+        };
+
+        self.expect(']')?;
+
+        Ok(Simple::Attribute {
+            name,
+            op,
+            value,
+            case_insensitive,
+        })
     }
 
-    fn matches(&self, name: &QualName, attrs: Ref<'_, Vec<Attribute>>) -> bool {
-        let mut id_match = self.id.is_empty();
-        if let Some(el_id) = get_attr(&attrs, "id") {
-            let el_ids: Vec<_> = el_id.split_whitespace().collect();
-            id_match = self.id.iter().all(|id| el_ids.iter().any(|eid| eid == id))
+    fn parse_pseudo(&mut self) -> Result<Simple, SelectorError> {
+        let start = self.pos;
+
+        if self.peek() == Some(':') {
+            return self.error(start, "pseudo-elements are not supported");
         }
 
-        let mut class_match = self.class.is_empty();
-        if let Some(el_class) = get_attr(&attrs, "class") {
-            let el_classes: Vec<_> = el_class.split_whitespace().collect();
+        let name = self.parse_ident()?.to_ascii_lowercase();
 
-            class_match = self
-                .class
-                .iter()
-                .all(|class| el_classes.iter().any(|eclass| eclass == class))
+        if self.eat('(') {
+            self.skip_whitespace();
+
+            let simple = match name.as_str() {
+                "not" => Simple::Not(self.parse_list(true)?),
+                "is" | "where" => Simple::Is(self.parse_list(true)?),
+                "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+                    let (a, b) = self.parse_nth()?;
+
+                    Simple::Nth {
+                        of_type: name.ends_with("of-type"),
+                        from_end: name.starts_with("nth-last"),
+                        a,
+                        b,
+                    }
+                }
+                _ => return self.error(start, format!("unsupported pseudo-class `:{}()`", name)),
+            };
+
+            self.skip_whitespace();
+            self.expect(')')?;
+
+            return Ok(simple);
         }
 
-        let mut attr_match = true;
-        for (k, v) in &self.attribute {
-            if let Some(value) = get_attr(&attrs, k.as_str()) {
-                if !v.matches(value) {
-                    attr_match = false;
-                    break;
+        let nth = |of_type, from_end| Simple::Nth {
+            of_type,
+            from_end,
+            a: 0,
+            b: 1,
+        };
+
+        match name.as_str() {
+            "first-child" => Ok(nth(false, false)),
+            "last-child" => Ok(nth(false, true)),
+            "first-of-type" => Ok(nth(true, false)),
+            "last-of-type" => Ok(nth(true, true)),
+            "only-child" => Ok(Simple::Only { of_type: false }),
+            "only-of-type" => Ok(Simple::Only { of_type: true }),
+            "empty" => Ok(Simple::Empty),
+            "root" => Ok(Simple::Root),
+            _ => self.error(start, format!("unsupported pseudo-class `:{}`", name)),
+        }
+    }
+
+    /// Parses the `an+b` argument of the `:nth-*` pseudo-classes.
+    fn parse_nth(&mut self) -> Result<(i32, i32), SelectorError> {
+        let start = self.pos;
+        let end = match self.input[start..].find(')') {
+            Some(offset) => start + offset,
+            None => return self.error(start, "expected `)`, found the end"),
+        };
+
+        let text = self.input[start..end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        let parse = |number: &str| -> Option<i32> {
+            number
+                .strip_prefix('+')
+                .unwrap_or(number)
+                .parse::<i32>()
+                .ok()
+        };
+
+        let nth = match text.as_str() {
+            "odd" => Some((2, 1)),
+            "even" => Some((2, 0)),
+            _ => match text.split_once('n') {
+                Some((a, b)) => {
+                    let a = match a {
+                        "" | "+" => Some(1),
+                        "-" => Some(-1),
+                        a => parse(a),
+                    };
+                    let b = match b {
+                        "" => Some(0),
+                        b if b.starts_with(['+', '-']) => parse(b),
+                        _ => None,
+                    };
+
+                    a.zip(b)
                 }
+                None => parse(&text).map(|b| (0, b)),
+            },
+        };
+
+        match nth {
+            Some(nth) => {
+                self.pos = end;
+
+                Ok(nth)
             }
+            None => self.error(start, format!("invalid `an+b` expression `{}`", text)),
         }
+    }
+}
 
-        let name = name.local.to_string();
-        let tag_match = self.tag.is_empty() || self.tag.iter().any(|tag| &name == tag);
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '-' || c == '\\' || !c.is_ascii()
+}
 
-        tag_match && id_match && class_match && attr_match
-    }
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || !c.is_ascii()
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Selector {
-    matchers: Vec<Matcher>,
+fn get_attr(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| &attr.name.local == name)
+        .map(|attr| attr.value.to_string())
 }
 
-impl From<&str> for Selector {
-    fn from(input: &str) -> Self {
-        let matchers: Vec<_> = input.split_whitespace().map(Matcher::from).collect();
+fn is_element(node: &Handle) -> bool {
+    matches!(node.data, NodeData::Element { .. })
+}
 
-        Selector { matchers }
-    }
+fn parent(node: &Handle) -> Option<Handle> {
+    let weak = node.parent.take()?;
+    let parent = weak.upgrade();
+    node.parent.set(Some(weak));
+
+    parent
 }
 
-impl From<String> for Selector {
-    fn from(input: String) -> Self {
-        let matchers: Vec<_> = input.split_whitespace().map(Matcher::from).collect();
+fn parent_element(node: &Handle) -> Option<Handle> {
+    parent(node).filter(is_element)
+}
 
-        Selector { matchers }
+/// The element children of the node's parent, including the node itself.
+fn element_siblings(node: &Handle) -> Vec<Handle> {
+    match parent(node) {
+        Some(parent) => parent
+            .children
+            .borrow()
+            .iter()
+            .filter(|sibling| is_element(sibling))
+            .map(Arc::clone)
+            .collect(),
+        None => vec![Arc::clone(node)],
     }
 }
 
-fn get_attr(attrs: &Ref<'_, Vec<Attribute>>, name: &str) -> Option<String> {
-    attrs
+/// The element siblings before the node, closest first.
+fn previous_element_siblings(node: &Handle) -> Vec<Handle> {
+    let mut siblings = element_siblings(node);
+
+    match siblings
         .iter()
-        .filter(|attr| &attr.name.local == name)
-        .take(1)
-        .map(|attr| attr.value.to_string())
-        .collect::<Vec<_>>()
-        .pop()
+        .position(|sibling| Arc::ptr_eq(sibling, node))
+    {
+        Some(index) => {
+            siblings.truncate(index);
+            siblings.reverse();
+
+            siblings
+        }
+        None => Vec::new(),
+    }
+}
+
+fn local_name(node: &Handle) -> Option<&str> {
+    match node.data {
+        NodeData::Element { ref name, .. } => Some(&*name.local),
+        _ => None,
+    }
+}
+
+fn matches_list(list: &[Complex], node: &Handle) -> bool {
+    list.iter().any(|complex| complex.matches(node))
 }
 
 impl Selector {
-    fn find_nodes(
-        &self,
-        matcher: &Matcher,
-        elements: Vec<Handle>,
-        direct_match: bool,
-    ) -> Vec<Handle> {
-        let mut acc = vec![];
-
-        for el in elements.iter() {
-            if !direct_match {
-                let children: Vec<_> = el.children.borrow().iter().map(Arc::clone).collect();
-                acc.append(&mut self.find_nodes(matcher, children, false));
+    pub fn matches(&self, node: &Handle) -> bool {
+        matches_list(&self.list, node)
+    }
+
+    /// Finds every descendant of `root` that matches, in document order.
+    fn find(&self, root: &Handle) -> Vec<Element> {
+        let mut acc = Vec::new();
+
+        self.find_in(root, &mut acc);
+
+        acc
+    }
+
+    fn find_in(&self, node: &Handle, acc: &mut Vec<Element>) {
+        for child in node.children.borrow().iter() {
+            if self.matches(child) {
+                acc.push(Element::from(child));
             }
 
-            match el.data {
-                NodeData::Element {
-                    ref name,
-                    ref attrs,
-                    ..
-                } if matcher.matches(name, attrs.borrow()) => {
-                    acc.push(Arc::clone(el));
+            self.find_in(child, acc);
+        }
+    }
+}
+
+impl Complex {
+    fn matches(&self, node: &Handle) -> bool {
+        self.matches_at(self.compounds.len() - 1, node)
+    }
+
+    /// Matches right to left, `index` is the compound that `node` needs to match.
+    fn matches_at(&self, index: usize, node: &Handle) -> bool {
+        if !self.compounds[index].matches(node) {
+            return false;
+        }
+
+        if index == 0 {
+            return true;
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Child => {
+                parent_element(node).is_some_and(|parent| self.matches_at(index - 1, &parent))
+            }
+            Combinator::Descendant => {
+                let mut ancestor = parent_element(node);
+
+                while let Some(current) = ancestor {
+                    if self.matches_at(index - 1, &current) {
+                        return true;
+                    }
+
+                    ancestor = parent_element(&current);
                 }
-                _ => {}
-            };
+
+                false
+            }
+            Combinator::NextSibling => previous_element_siblings(node)
+                .first()
+                .is_some_and(|sibling| self.matches_at(index - 1, sibling)),
+            Combinator::SubsequentSibling => previous_element_siblings(node)
+                .iter()
+                .any(|sibling| self.matches_at(index - 1, sibling)),
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, node: &Handle) -> bool {
+        let (name, attrs) = match node.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => (name, attrs.borrow()),
+            _ => return false,
+        };
+
+        if let Some(tag) = &self.tag {
+            if &*name.local != tag.as_str() {
+                return false;
+            }
         }
 
-        acc
+        self.simples
+            .iter()
+            .all(|simple| simple.matches(node, &attrs))
     }
+}
+
+impl Simple {
+    fn matches(&self, node: &Handle, attrs: &[Attribute]) -> bool {
+        match self {
+            Simple::Id(id) => get_attr(attrs, "id").is_some_and(|value| &value == id),
+            Simple::Class(class) => get_attr(attrs, "class")
+                .is_some_and(|value| value.split_whitespace().any(|value| value == class)),
+            Simple::Attribute {
+                name,
+                op,
+                value,
+                case_insensitive,
+            } => match get_attr(attrs, name) {
+                Some(found) => {
+                    let (found, value) = if *case_insensitive {
+                        (found.to_lowercase(), value.to_lowercase())
+                    } else {
+                        (found, value.clone())
+                    };
 
-    fn find(&self, elements: Ref<'_, Vec<Handle>>) -> Vec<Element> {
-        let mut elements: Vec<_> = elements.iter().map(Arc::clone).collect();
-        let mut direct_match = false;
+                    match op {
+                        AttributeOp::Exists => true,
+                        AttributeOp::Equals => found == value,
+                        AttributeOp::Includes => {
+                            found.split_whitespace().any(|found| found == value)
+                        }
+                        AttributeOp::DashMatch => {
+                            found == value || found.starts_with(&format!("{}-", value))
+                        }
+                        AttributeOp::Prefix => !value.is_empty() && found.starts_with(&value),
+                        AttributeOp::Suffix => !value.is_empty() && found.ends_with(&value),
+                        AttributeOp::Substring => !value.is_empty() && found.contains(&value),
+                    }
+                }
+                None => false,
+            },
+            Simple::Nth {
+                of_type,
+                from_end,
+                a,
+                b,
+            } => {
+                let mut siblings = same_type(element_siblings(node), node, *of_type);
+                if *from_end {
+                    siblings.reverse();
+                }
 
-        for matcher in &self.matchers {
-            if matcher.direct_match {
-                direct_match = true;
-                elements = elements
+                match siblings
                     .iter()
-                    .flat_map(|el| {
-                        el.children
-                            .borrow()
-                            .iter()
-                            .map(Arc::clone)
-                            .collect::<Vec<_>>()
-                    })
-                    .collect();
-                continue;
+                    .position(|sibling| Arc::ptr_eq(sibling, node))
+                {
+                    Some(position) => nth_matches(*a, *b, position as i32 + 1),
+                    None => false,
+                }
+            }
+            Simple::Only { of_type } => {
+                same_type(element_siblings(node), node, *of_type).len() == 1
             }
-            elements = self.find_nodes(matcher, elements, direct_match);
-            direct_match = false;
+            Simple::Empty => node.children.borrow().iter().all(|child| match child.data {
+                NodeData::Element { .. } => false,
+                NodeData::Text { ref contents } => contents.borrow().is_empty(),
+                _ => true,
+            }),
+            Simple::Root => {
+                parent(node).is_some_and(|parent| matches!(parent.data, NodeData::Document))
+            }
+            Simple::Not(list) => !matches_list(list, node),
+            Simple::Is(list) => matches_list(list, node),
         }
+    }
+}
 
-        elements.iter().map(Element::from).collect()
+fn same_type(siblings: Vec<Handle>, node: &Handle, of_type: bool) -> Vec<Handle> {
+    if !of_type {
+        return siblings;
     }
+
+    let name = local_name(node);
+
+    siblings
+        .into_iter()
+        .filter(|sibling| local_name(sibling) == name)
+        .collect()
+}
+
+/// Checks if `index` (starting at 1) is `a * n + b` for some `n >= 0`.
+fn nth_matches(a: i32, b: i32, index: i32) -> bool {
+    if a == 0 {
+        return index == b;
+    }
+
+    let diff = index - b;
+
+    diff % a == 0 && diff / a >= 0
 }
 
 #[derive(Debug)]
@@ -367,8 +912,9 @@ impl Element {
             .collect::<Vec<_>>()
     }
 
-    pub fn select(&self, selector: impl Into<Selector>) -> Vec<Element> {
-        let sel: Selector = selector.into();
-        sel.find(self.handle.children.borrow())
+    pub fn select(&self, selector: &str) -> Result<Vec<Element>, SelectorError> {
+        let sel = Selector::try_from(selector)?;
+
+        Ok(sel.find(&self.handle))
     }
 }
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use ao3fti_common::Conf;
+use html5ever::{parse_fragment, tendril::TendrilSink, LocalName, Namespace, QualName};
+use markup5ever_arcdom::{ArcDom, Handle, NodeData};
+
+/// Elements that are kept, everything not listed here is unwrapped and only its children kept.
+static ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "acronym",
+    "b",
+    "big",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "ins",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// Elements that are dropped along with their children.
+static DROPPED_ELEMENTS: &[&str] = &[
+    "embed", "form", "frame", "frameset", "iframe", "math", "noscript", "object", "script",
+    "select", "style", "svg", "template", "textarea", "title",
+];
+
+/// Elements that have no closing tag.
+static VOID_ELEMENTS: &[&str] = &["br", "hr"];
+
+/// Attributes that are kept for a given element, any other attribute is removed.
+static ALLOWED_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "title"]),
+    ("abbr", &["title"]),
+    ("acronym", &["title"]),
+    ("blockquote", &["cite"]),
+    ("q", &["cite"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan"]),
+];
+
+/// Attributes that hold urls, which are only kept when they use a safe scheme.
+static URL_ATTRIBUTES: &[&str] = &["href", "cite"];
+
+static URL_SCHEMES: &[&str] = &["http:", "https:", "mailto:"];
+
+/// Re-sanitizes the summaries of every stored story, for stories scraped before summaries were
+/// sanitized or after the allowlist changes.
+#[tracing::instrument(skip(conf), err)]
+pub async fn run(conf: Arc<Conf>) -> Result<(), ao3fti_common::Report> {
+    let pool = ao3fti_queries::init_database_connection(conf).await?;
+
+    let summaries = ao3fti_queries::get_story_summaries(pool.clone()).await?;
+
+    let mut trans = pool.begin().await?;
+    let mut updated = 0;
+
+    for (story_id, summary) in summaries {
+        let sanitized = sanitize(&summary)?;
+
+        if sanitized != summary {
+            ao3fti_queries::update_story_summary(&mut trans, story_id, &sanitized).await?;
+
+            updated += 1;
+        }
+    }
+
+    trans.commit().await?;
+
+    tracing::info!(updated = updated, "sanitized story summaries");
+
+    Ok(())
+}
+
+/// Reduces a fragment of scraped HTML to an allowlist of elements and attributes.
+///
+/// Scripts, styles, embedded content, event handlers, and `javascript:` urls are all removed,
+/// what's left is safe to render as is.
+pub fn sanitize(html: &str) -> Result<String, ao3fti_common::Report> {
+    let context = QualName::new(
+        None,
+        Namespace::from("http://www.w3.org/1999/xhtml"),
+        LocalName::from("body"),
+    );
+
+    let dom = parse_fragment(ArcDom::default(), Default::default(), context, vec![])
+        .from_utf8()
+        .read_from(&mut html.as_bytes())?;
+
+    let mut buffer = String::with_capacity(html.len());
+
+    // fragments are parsed into a `html` element that stands in for the context element
+    for root in dom.document.children.borrow().iter() {
+        for child in root.children.borrow().iter() {
+            write_node(&mut buffer, child);
+        }
+    }
+
+    Ok(buffer)
+}
+
+fn write_node(buffer: &mut String, node: &Handle) {
+    match node.data {
+        NodeData::Text { ref contents } => escape(buffer, &contents.borrow(), false),
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => {
+            let tag = &*name.local;
+
+            if DROPPED_ELEMENTS.contains(&tag) {
+                return;
+            }
+
+            if !ALLOWED_ELEMENTS.contains(&tag) {
+                write_children(buffer, node);
+
+                return;
+            }
+
+            let allowed = ALLOWED_ATTRIBUTES
+                .iter()
+                .find(|(element, _)| *element == tag)
+                .map(|(_, attributes)| *attributes)
+                .unwrap_or(&[]);
+
+            buffer.push('<');
+            buffer.push_str(tag);
+
+            for attr in attrs.borrow().iter() {
+                let key = &*attr.name.local;
+
+                if !allowed.contains(&key) {
+                    continue;
+                }
+
+                if URL_ATTRIBUTES.contains(&key) && !is_safe_url(&attr.value) {
+                    continue;
+                }
+
+                buffer.push(' ');
+                buffer.push_str(key);
+                buffer.push_str("=\"");
+                escape(buffer, &attr.value, true);
+                buffer.push('"');
+            }
+
+            if tag == "a" {
+                buffer.push_str(" rel=\"nofollow noopener noreferrer\"");
+            }
+
+            buffer.push('>');
+
+            if VOID_ELEMENTS.contains(&tag) {
+                return;
+            }
+
+            write_children(buffer, node);
+
+            buffer.push_str("</");
+            buffer.push_str(tag);
+            buffer.push('>');
+        }
+        _ => {}
+    }
+}
+
+fn write_children(buffer: &mut String, node: &Handle) {
+    for child in node.children.borrow().iter() {
+        write_node(buffer, child);
+    }
+}
+
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+
+    match url.find([':', '/', '?', '#']) {
+        // there's a scheme, so it needs to be one we trust
+        Some(index) if url[index..].starts_with(':') => {
+            URL_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+        }
+        _ => true,
+    }
+}
+
+fn escape(buffer: &mut String, text: &str, attribute: bool) {
+    for c in text.chars() {
+        match c {
+            '&' => buffer.push_str("&amp;"),
+            '<' => buffer.push_str("&lt;"),
+            '>' => buffer.push_str("&gt;"),
+            '"' if attribute => buffer.push_str("&quot;"),
+            '\u{a0}' => buffer.push_str("&nbsp;"),
+            c => buffer.push(c),
+        }
+    }
+}
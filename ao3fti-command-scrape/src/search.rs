@@ -0,0 +1,266 @@
+use std::{fmt::Write as _, str::FromStr, sync::Arc};
+
+use ao3fti_common::{err, models::Rating, Conf, Report};
+
+use crate::{Limits, Slice};
+
+/// An archive warning, as AO3 filters them by id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Warning {
+    ChooseNotToUse,
+    NoWarnings,
+    Violence,
+    MajorCharacterDeath,
+    NonCon,
+    Underage,
+}
+
+impl Warning {
+    fn id(self) -> u32 {
+        match self {
+            Warning::ChooseNotToUse => 14,
+            Warning::NoWarnings => 16,
+            Warning::Violence => 17,
+            Warning::MajorCharacterDeath => 18,
+            Warning::NonCon => 19,
+            Warning::Underage => 20,
+        }
+    }
+}
+
+impl FromStr for Warning {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "choose-not-to-use" => Ok(Warning::ChooseNotToUse),
+            "no-warnings" => Ok(Warning::NoWarnings),
+            "violence" => Ok(Warning::Violence),
+            "major-character-death" => Ok(Warning::MajorCharacterDeath),
+            "non-con" => Ok(Warning::NonCon),
+            "underage" => Ok(Warning::Underage),
+            _ => Err(err!(
+                "unknown warning `{}`, expected `choose-not-to-use`, `no-warnings`, `violence`, `major-character-death`, `non-con` or `underage`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Completion {
+    Complete,
+    InProgress,
+}
+
+impl FromStr for Completion {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "complete" => Ok(Completion::Complete),
+            "in-progress" => Ok(Completion::InProgress),
+            _ => Err(err!(
+                "unknown completion `{}`, expected `complete` or `in-progress`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    BestMatch,
+    Author,
+    Title,
+    Posted,
+    Updated,
+    Words,
+    Hits,
+    Kudos,
+    Comments,
+    Bookmarks,
+}
+
+impl Sort {
+    fn column(self) -> &'static str {
+        match self {
+            Sort::BestMatch => "_score",
+            Sort::Author => "authors_to_sort_on",
+            Sort::Title => "title_to_sort_on",
+            Sort::Posted => "created_at",
+            Sort::Updated => "revised_at",
+            Sort::Words => "word_count",
+            Sort::Hits => "hits",
+            Sort::Kudos => "kudos_count",
+            Sort::Comments => "comments_count",
+            Sort::Bookmarks => "bookmarks_count",
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "best-match" => Ok(Sort::BestMatch),
+            "author" => Ok(Sort::Author),
+            "title" => Ok(Sort::Title),
+            "posted" => Ok(Sort::Posted),
+            "updated" => Ok(Sort::Updated),
+            "words" => Ok(Sort::Words),
+            "hits" => Ok(Sort::Hits),
+            "kudos" => Ok(Sort::Kudos),
+            "comments" => Ok(Sort::Comments),
+            "bookmarks" => Ok(Sort::Bookmarks),
+            _ => Err(err!(
+                "unknown sort `{}`, expected `best-match`, `author`, `title`, `posted`, `updated`, `words`, `hits`, `kudos`, `comments` or `bookmarks`",
+                s
+            )),
+        }
+    }
+}
+
+/// The filters of an AO3 works search, stored with the crawl it started as JSON.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Search {
+    pub query: Option<String>,
+    pub fandoms: Vec<String>,
+    pub ships: Vec<String>,
+    pub characters: Vec<String>,
+    pub tags: Vec<String>,
+    pub excluded_tags: Vec<String>,
+    pub rating: Option<Rating>,
+    pub warnings: Vec<Warning>,
+    /// The language code, `en` and not `English`.
+    pub language: Option<String>,
+    pub words_min: Option<u32>,
+    pub words_max: Option<u32>,
+    pub completion: Option<Completion>,
+    pub sort: Option<Sort>,
+    pub ascending: bool,
+}
+
+impl Search {
+    /// Builds the path and query of the `/works/search` page these filters would be submitted
+    /// to.
+    pub fn url(&self) -> Result<String, Report> {
+        let mut pairs = Vec::new();
+
+        let mut push = |key: &str, value: &str| {
+            pairs.push(format!("work_search%5B{}%5D={}", key, encode(value)));
+        };
+
+        push("query", self.query.as_deref().unwrap_or_default());
+
+        if let Some(completion) = self.completion {
+            push(
+                "complete",
+                match completion {
+                    Completion::Complete => "T",
+                    Completion::InProgress => "F",
+                },
+            );
+        }
+
+        match (self.words_min, self.words_max) {
+            (Some(min), Some(max)) => push("word_count", &format!("{}-{}", min, max)),
+            // AO3 only has exclusive bounds, these are inclusive
+            (Some(min), None) => push("word_count", &format!(">{}", min.saturating_sub(1))),
+            (None, Some(max)) => push("word_count", &format!("<{}", max.saturating_add(1))),
+            (None, None) => {}
+        }
+
+        if let Some(language) = &self.language {
+            push("language_id", language);
+        }
+
+        for (key, names) in [
+            ("fandom_names", &self.fandoms),
+            ("relationship_names", &self.ships),
+            ("character_names", &self.characters),
+            ("freeform_names", &self.tags),
+            ("excluded_tag_names", &self.excluded_tags),
+        ] {
+            if !names.is_empty() {
+                push(key, &names.join(","));
+            }
+        }
+
+        if let Some(rating) = &self.rating {
+            let id = match rating {
+                Rating::NotRated => 9,
+                Rating::General => 10,
+                Rating::Teen => 11,
+                Rating::Mature => 12,
+                Rating::Explicit => 13,
+                Rating::Unknown => return Err(err!("unable to search for an unknown rating")),
+            };
+
+            push("rating_ids", &id.to_string());
+        }
+
+        if let Some(sort) = self.sort {
+            push("sort_column", sort.column());
+            push(
+                "sort_direction",
+                if self.ascending { "asc" } else { "desc" },
+            );
+        }
+
+        for warning in &self.warnings {
+            pairs.push(format!(
+                "work_search%5Barchive_warning_ids%5D%5B%5D={}",
+                warning.id()
+            ));
+        }
+
+        Ok(format!("/works/search?{}", pairs.join("&")))
+    }
+}
+
+/// Form encodes a query value, spaces become `+` like they do when AO3's search form is
+/// submitted.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Scrapes and indexes the works of a search built from filters, like [`crate::run`] does for
+/// a hand built url.
+#[tracing::instrument(skip(conf, search), err)]
+pub async fn run(
+    conf: Arc<Conf>,
+    search: Search,
+    slice: Option<Slice>,
+    limits: Limits,
+) -> Result<(), ao3fti_common::Report> {
+    let url = search.url()?;
+    let json = serde_json::to_string(&search)?;
+
+    tracing::info!(url = %url, "built search url");
+
+    // a crawl's future is too deep to be laid out inline in this one
+    Box::pin(crate::start(conf, &url, Some(&json), slice, limits)).await
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use ao3fti_common::{err, Conf, Context as _, Uri};
+use tracing::{Instrument as _, Span};
+
+use crate::{path_id, query, rebuild_url, string_trim};
+
+/// Scrapes the pages of every series that hasn't been checked yet, storing the position of
+/// each of its works.
+#[tracing::instrument(skip(conf), err)]
+pub async fn run(conf: Arc<Conf>) -> Result<(), ao3fti_common::Report> {
+    let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
+
+    let series = ao3fti_queries::get_unchecked_series(pool.clone()).await?;
+
+    tracing::info!(count = series.len(), "scraping series");
+
+    for series_id in series {
+        let span = tracing::debug_span!("series loop", series_id = series_id).or_current();
+
+        let (name, story_ids) = scrape_series(&conf, series_id)
+            .instrument(span.clone())
+            .await?;
+
+        let mut trans = pool.begin().await?;
+
+        ao3fti_queries::insert_series_works(&mut trans, series_id, &name, &story_ids)
+            .instrument(span.clone())
+            .await?;
+
+        trans.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(conf), err)]
+async fn scrape_series(
+    conf: &Conf,
+    series_id: usize,
+) -> Result<(String, Vec<usize>), ao3fti_common::Report> {
+    static NEXT_SELECTOR: &str = "#main ol.pagination.actions > li > a[rel=next]";
+
+    let base_url = Uri::try_from(conf.base_url.as_str())?;
+    let series_url = format!("{}/series/{}", conf.base_url, series_id);
+
+    let mut url = Some(
+        Uri::try_from(series_url.as_str())
+            .with_context(|| format!("with url, at line {}: `{}`", line!(), series_url))?,
+    );
+
+    let mut name = None;
+    let mut story_ids = Vec::new();
+
+    while let Some(url_ref) = &url {
+        tracing::info!(url = %url_ref.to_string(), "scraping series page");
+
+        let html = ao3fti_common::utils::req(conf, url_ref).await?;
+
+        let doc = query::Document::try_from(html.as_str())?;
+
+        if name.is_none() {
+            name = get_series_name(&doc)?;
+        }
+
+        story_ids.extend(get_series_works(&doc)?);
+
+        url = doc
+            .select(NEXT_SELECTOR)?
+            .into_iter()
+            .last()
+            .and_then(|element| element.attr("href"))
+            .map(|href| {
+                Uri::try_from(href.as_str())
+                    .with_context(|| format!("with url, at line {}: `{}`", line!(), href))
+                    .and_then(|next_url| rebuild_url(&base_url, &next_url))
+            })
+            .transpose()?;
+
+        ao3fti_common::utils::sleep(conf)
+            .instrument(Span::current())
+            .await?;
+    }
+
+    let name = name.ok_or_else(|| err!("unable to scrape name of series `{}`", series_id))?;
+
+    Ok((name, story_ids))
+}
+
+pub(crate) fn get_series_name(
+    doc: &query::Document,
+) -> Result<Option<String>, query::SelectorError> {
+    static SERIES_NAME: &str = "#main > h2.heading";
+
+    Ok(doc
+        .select(SERIES_NAME)?
+        .into_iter()
+        .next()
+        .and_then(|element| element.text())
+        .map(|mut name| {
+            string_trim(&mut name);
+
+            name
+        }))
+}
+
+/// Gets the ids of the works listed on a series page, in the order they're listed.
+pub(crate) fn get_series_works(doc: &query::Document) -> Result<Vec<usize>, query::SelectorError> {
+    static SERIES_WORKS: &str = "#main ul.series.work.index > li.work h4.heading > a";
+
+    // the heading also links the authors, only the work link has a `/works/` path
+    Ok(doc
+        .select(SERIES_WORKS)?
+        .into_iter()
+        .filter_map(|element| element.attr("href"))
+        .filter(|href| href.starts_with("/works/"))
+        .filter_map(|href| path_id(&href))
+        .collect())
+}
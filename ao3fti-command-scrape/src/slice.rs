@@ -0,0 +1,223 @@
+use std::str::FromStr;
+
+use ao3fti_common::{err, Conf, Context as _, Report, Uri};
+use time::{Date, Month, OffsetDateTime};
+use tracing::{Instrument as _, Span};
+
+use crate::{crawl, get_page_total, limits::Budget, query, Store};
+
+/// The date a search is split on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceBy {
+    CreatedAt,
+    RevisedAt,
+}
+
+impl SliceBy {
+    fn field(self) -> &'static str {
+        match self {
+            SliceBy::CreatedAt => "created_at",
+            SliceBy::RevisedAt => "revised_at",
+        }
+    }
+}
+
+impl FromStr for SliceBy {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created-at" => Ok(SliceBy::CreatedAt),
+            "revised-at" => Ok(SliceBy::RevisedAt),
+            _ => Err(err!(
+                "unknown slice `{}`, expected `created-at` or `revised-at`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Slice {
+    pub by: SliceBy,
+    /// The number of works a listing can have before it stops paginating.
+    pub cap: usize,
+}
+
+/// A range of days, inclusive on both ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Window {
+    pub(crate) from: Date,
+    pub(crate) to: Date,
+}
+
+impl Window {
+    /// Every day up until today, works imported to the archive can be dated well before it
+    /// opened so this starts at the epoch.
+    ///
+    /// `since` is when works were last updated, so it only narrows windows of revision dates, a
+    /// work created long before it can still have been updated since.
+    pub(crate) fn all(by: SliceBy, since: Option<Date>) -> Result<Window, Report> {
+        Ok(Window {
+            from: match (by, since) {
+                (SliceBy::RevisedAt, Some(since)) => since,
+                _ => Date::from_calendar_date(1970, Month::January, 1)?,
+            },
+            to: OffsetDateTime::now_utc().date(),
+        })
+    }
+
+    /// Splits the window in half, unless it's only a single day.
+    pub(crate) fn split(self) -> Option<(Window, Window)> {
+        let from = self.from.to_julian_day();
+        let to = self.to.to_julian_day();
+
+        if from >= to {
+            return None;
+        }
+
+        let middle = Date::from_julian_day(from + (to - from) / 2).ok()?;
+        let after = middle.next_day()?;
+
+        Some((
+            Window {
+                from: self.from,
+                to: middle,
+            },
+            Window {
+                from: after,
+                to: self.to,
+            },
+        ))
+    }
+}
+
+/// Crawls a search one date window at a time, splitting any window that has more works than
+/// AO3 will paginate through.
+///
+/// Windows don't overlap, but a work revised mid crawl can move into one that hasn't been
+/// crawled yet, those are skipped as they're already stored.
+#[tracing::instrument(skip(conf, store, base_url, budget, url), err)]
+pub(crate) async fn crawl_sliced(
+    conf: &Conf,
+    store: Option<&Store<'_>>,
+    base_url: &Uri,
+    budget: &mut Budget,
+    url: &Uri,
+    slice: Slice,
+) -> Result<(), Report> {
+    let mut windows = vec![Window::all(slice.by, budget.limits.since)?];
+
+    while let Some(window) = windows.pop() {
+        if budget.exhausted() {
+            tracing::info!(limits = ?budget.limits, "reached the crawl limits");
+
+            break;
+        }
+
+        let window_url = slice_url(url, slice.by, window)?;
+
+        let span = tracing::debug_span!("window loop", from = %format_date(window.from), to = %format_date(window.to))
+            .or_current();
+
+        let html = ao3fti_common::utils::req(conf, &window_url)
+            .instrument(span.clone())
+            .await?;
+
+        let total = {
+            let doc = query::Document::try_from(html.as_str())?;
+
+            get_page_total(&doc)?.unwrap_or(0)
+        };
+
+        tracing::info!(parent: &span, total = total, "counted works in window");
+
+        if total > slice.cap {
+            if let Some((earlier, later)) = window.split() {
+                // earlier is popped first, so the crawl runs oldest to newest
+                windows.push(later);
+                windows.push(earlier);
+
+                ao3fti_common::utils::sleep(conf)
+                    .instrument(span.clone())
+                    .await?;
+
+                continue;
+            }
+
+            tracing::warn!(parent: &span, cap = slice.cap, "window is a single day, only the first works will be crawled");
+        }
+
+        if total > 0 {
+            crawl(conf, store, base_url, budget, window_url, Some(html))
+                .instrument(span.clone())
+                .await?;
+        }
+
+        ao3fti_common::utils::sleep(conf)
+            .instrument(Span::current())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Narrows a search to works dated within a window, by adding a range to its
+/// `work_search[query]`, and restarts it from the first page.
+pub(crate) fn slice_url(url: &Uri, by: SliceBy, window: Window) -> Result<Uri, Report> {
+    static QUERY_KEYS: &[&str] = &["work_search%5Bquery%5D", "work_search[query]"];
+
+    let range = format!(
+        "{}%3A%5B{}+TO+{}%5D",
+        by.field(),
+        format_date(window.from),
+        format_date(window.to)
+    );
+
+    let mut pairs = Vec::new();
+    let mut found = false;
+
+    for pair in url.query().unwrap_or_default().split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+        if pair.is_empty() || key == "page" {
+            continue;
+        }
+
+        if QUERY_KEYS.contains(&key) {
+            found = true;
+
+            if value.is_empty() {
+                pairs.push(format!("{}={}", key, range));
+            } else {
+                pairs.push(format!("{}={}+{}", key, value, range));
+            }
+        } else {
+            pairs.push(pair.to_string());
+        }
+    }
+
+    if !found {
+        pairs.push(format!("{}={}", QUERY_KEYS[0], range));
+    }
+
+    let sliced = format!("{}?{}", url.path(), pairs.join("&"));
+
+    let path_and_query = sliced
+        .parse()
+        .with_context(|| format!("with url, at line {}: `{}`", line!(), sliced))?;
+
+    let mut parts = url.clone().into_parts();
+    parts.path_and_query = Some(path_and_query);
+
+    Uri::from_parts(parts).with_context(|| format!("with url, at line {}: `{}`", line!(), sliced))
+}
+
+pub(crate) fn format_date(date: Date) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
@@ -0,0 +1,111 @@
+use std::{fmt::Write as _, sync::Arc};
+
+use ao3fti_common::{models::Tag, Conf, Context as _, Uri};
+use ao3fti_queries::TagRelations;
+use tracing::{Instrument as _, Span};
+
+use crate::{query, string_trim};
+
+/// Scrapes the tag pages of every tag that hasn't been checked yet, storing its canonical form,
+/// synonyms, parent fandoms and meta tags.
+#[tracing::instrument(skip(conf), err)]
+pub async fn run(conf: Arc<Conf>) -> Result<(), ao3fti_common::Report> {
+    let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
+
+    let tags = ao3fti_queries::get_unchecked_tags(pool.clone()).await?;
+
+    tracing::info!(count = tags.len(), "scraping tag relations");
+
+    for tag in tags {
+        let span = tracing::debug_span!("tag loop", tag_id = tag.id).or_current();
+
+        let tag_url = tag_url(&conf.base_url, &tag.name)?;
+
+        let relations = scrape_tag(&conf, &tag, &tag_url)
+            .instrument(span.clone())
+            .await?;
+
+        let mut trans = pool.begin().await?;
+
+        ao3fti_queries::insert_tag_relations(&mut trans, &tag, relations)
+            .instrument(span.clone())
+            .await?;
+
+        trans.commit().await?;
+
+        ao3fti_common::utils::sleep(&conf)
+            .instrument(Span::current())
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(conf, tag, tag_url), fields(tag_url = %tag_url.to_string()), err)]
+async fn scrape_tag(
+    conf: &Conf,
+    tag: &Tag,
+    tag_url: &Uri,
+) -> Result<TagRelations, ao3fti_common::Report> {
+    tracing::info!(name = %tag.name, "scraping tag");
+
+    let html = ao3fti_common::utils::req(conf, tag_url).await?;
+
+    let doc = query::Document::try_from(html.as_str())?;
+
+    get_tag_relations(&doc)
+}
+
+pub(crate) fn get_tag_relations(
+    doc: &query::Document,
+) -> Result<TagRelations, ao3fti_common::Report> {
+    static TAG_CANONICAL: &str = "#main .merger.module p > a.tag";
+    static TAG_SYNONYMS: &str = "#main .synonym.listbox > ul.tags > li > a.tag";
+    static TAG_PARENTS: &str = "#main .parent.listbox > ul.tags > li > a.tag";
+    static TAG_METAS: &str = "#main .meta.listbox > ul.tags > li > a.tag";
+
+    let names = |selector: &str| -> Result<Vec<String>, query::SelectorError> {
+        Ok(doc
+            .select(selector)?
+            .into_iter()
+            .filter_map(|element| element.text())
+            .map(|mut name| {
+                string_trim(&mut name);
+
+                name
+            })
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>())
+    };
+
+    Ok(TagRelations {
+        canonical: names(TAG_CANONICAL)?.into_iter().next(),
+        synonyms: names(TAG_SYNONYMS)?,
+        parents: names(TAG_PARENTS)?,
+        metas: names(TAG_METAS)?,
+    })
+}
+
+/// Builds the url of a tag's page, using AO3's escapes for characters that can't appear in a tag path.
+pub(crate) fn tag_url(base_url: &str, name: &str) -> Result<Uri, ao3fti_common::Report> {
+    let mut url = format!("{}/tags/", base_url);
+
+    for c in name.chars() {
+        match c {
+            '/' => url.push_str("*s*"),
+            '&' => url.push_str("*a*"),
+            '.' => url.push_str("*d*"),
+            '?' => url.push_str("*q*"),
+            '#' => url.push_str("*h*"),
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '~' | '*') => url.push(c),
+            c => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    write!(url, "%{:02X}", byte)?;
+                }
+            }
+        }
+    }
+
+    Uri::try_from(url.as_str()).with_context(|| format!("with url, at line {}: `{}`", line!(), url))
+}
@@ -0,0 +1,654 @@
+//! Golden tests for the parsers, run against saved AO3 pages in `fixtures/`.
+//!
+//! Every `<name>.html` fixture that parses has a `<name>.json` file next to it with the expected
+//! output. When AO3 changes its markup on purpose, save the new page over the fixture and run the
+//! tests with `UPDATE_GOLDEN=1` to rewrite the expected output, then review the diff.
+
+use std::{fmt::Debug, path::PathBuf};
+
+use ao3fti_common::{models::Rating, Report, Uri};
+use ao3fti_queries::{Info, Meta, RelationInfo, TagRelations};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    get_download_url, get_next_page, get_page_total, get_page_works, get_story_info,
+    get_story_language, get_story_meta, get_story_relations, get_story_stats,
+    limits::{Budget, Limits},
+    query,
+    sanitize::sanitize,
+    search::{Completion, Search, Sort, Warning},
+    series,
+    slice::{format_date, slice_url, SliceBy, Window},
+    tags::{get_tag_relations, tag_url},
+    Stats,
+};
+
+static BASE_URL: &str = "https://archiveofourown.org";
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct SearchGolden {
+    total: Option<usize>,
+    works: Vec<PageWorkGolden>,
+    next: Option<String>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct PageWorkGolden {
+    url: String,
+    updated: Option<String>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct WorkGolden {
+    download_url: String,
+    language: Option<String>,
+    stats: Stats,
+    relations: Vec<RelationInfo>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct DownloadGolden {
+    info: Info,
+    meta: Meta,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct SeriesGolden {
+    name: Option<String>,
+    works: Vec<usize>,
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(name)
+}
+
+fn fixture_html(name: &str) -> String {
+    let path = fixture_path(&format!("{}.html", name));
+
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("unable to read fixture `{}`: {}", path.display(), err))
+}
+
+fn fixture(name: &str) -> query::Document {
+    query::Document::try_from(fixture_html(name).as_str())
+        .unwrap_or_else(|err| panic!("unable to parse fixture `{}`: {:?}", name, err))
+}
+
+/// Unwraps a parser result, the panic message carries the whole error chain so a selector that
+/// stopped matching shows up as `selector ... matched nothing`.
+fn parsed<T, E>(name: &str, result: Result<T, E>) -> T
+where
+    E: Into<Report>,
+{
+    result.unwrap_or_else(|err| {
+        panic!(
+            "fixture `{}` no longer parses, has the markup changed?\n{:?}",
+            name,
+            err.into()
+        )
+    })
+}
+
+fn golden<T>(name: &str, actual: T)
+where
+    T: Debug + PartialEq + Serialize + DeserializeOwned,
+{
+    let path = fixture_path(&format!("{}.json", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        let json = serde_json::to_string_pretty(&actual).expect("unable to serialize golden");
+
+        std::fs::write(&path, format!("{}\n", json))
+            .unwrap_or_else(|err| panic!("unable to write golden `{}`: {}", path.display(), err));
+
+        return;
+    }
+
+    let json = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("unable to read golden `{}`: {}", path.display(), err));
+    let expected: T = serde_json::from_str(&json)
+        .unwrap_or_else(|err| panic!("unable to parse golden `{}`: {}", path.display(), err));
+
+    assert_eq!(
+        expected, actual,
+        "fixture `{}` differs from its golden",
+        name
+    );
+}
+
+fn assert_matched_nothing(name: &str, err: Report) {
+    assert!(
+        err.chain()
+            .any(|cause| cause.to_string().contains("matched nothing")),
+        "fixture `{}` failed without naming a selector: {:?}",
+        name,
+        err
+    );
+}
+
+fn story_url(story_id: usize) -> Uri {
+    Uri::try_from(format!("{}/works/{}?view_adult=true", BASE_URL, story_id)).unwrap()
+}
+
+fn search(name: &str) {
+    let base_url = Uri::try_from(BASE_URL).unwrap();
+    let page_url = Uri::try_from(format!(
+        "{}/works/search?work_search%5Bquery%5D=tales",
+        BASE_URL
+    ))
+    .unwrap();
+    let doc = fixture(name);
+
+    let total = parsed(name, get_page_total(&doc));
+    let works = parsed(name, get_page_works(&base_url, &page_url, &doc));
+    let next = parsed(name, get_next_page(&page_url, &doc));
+
+    golden(
+        name,
+        SearchGolden {
+            total,
+            works: works
+                .iter()
+                .map(|work| PageWorkGolden {
+                    url: work.url.to_string(),
+                    updated: work.updated.map(format_date),
+                })
+                .collect(),
+            next: next.as_ref().map(Uri::to_string),
+        },
+    );
+}
+
+fn work(name: &str, story_id: usize) {
+    let doc = fixture(name);
+
+    let download_url = parsed(name, get_download_url(&story_url(story_id), &doc));
+    let language = parsed(name, get_story_language(&doc));
+    let stats = parsed(name, get_story_stats(&story_url(story_id), &doc));
+    let relations = parsed(name, get_story_relations(story_id, &doc));
+
+    golden(
+        name,
+        WorkGolden {
+            download_url,
+            language,
+            stats,
+            relations,
+        },
+    );
+}
+
+fn download(name: &str, story_id: usize) {
+    let doc = fixture(name);
+
+    let info = parsed(name, get_story_info(BASE_URL, &story_url(story_id), &doc));
+    let meta = parsed(name, get_story_meta(&doc));
+
+    golden(name, DownloadGolden { info, meta });
+}
+
+#[test]
+fn search_page() {
+    search("search");
+}
+
+#[test]
+fn search_last_page() {
+    search("search-last");
+}
+
+#[test]
+fn work_single_chapter() {
+    work("work-single", 1001);
+}
+
+#[test]
+fn work_multi_chapter() {
+    work("work-multi", 2001);
+}
+
+#[test]
+fn download_single_chapter() {
+    download("work-single.download", 1001);
+}
+
+#[test]
+fn download_multi_chapter() {
+    download("work-multi.download", 2001);
+}
+
+#[test]
+fn series_page() {
+    let doc = fixture("series");
+
+    let name = parsed("series", series::get_series_name(&doc));
+    let works = parsed("series", series::get_series_works(&doc));
+
+    golden("series", SeriesGolden { name, works });
+}
+
+#[test]
+fn tag_pages() {
+    for name in ["tag-canonical", "tag-synonym"] {
+        let doc = fixture(name);
+
+        let relations: TagRelations = parsed(name, get_tag_relations(&doc));
+
+        golden(name, relations);
+    }
+}
+
+#[test]
+fn tag_urls_are_escaped() {
+    for (name, path) in [
+        ("Fluff", "Fluff"),
+        (
+            "Harry Potter - J. K. Rowling",
+            "Harry%20Potter%20-%20J*d*%20K*d*%20Rowling",
+        ),
+        (
+            "Draco Malfoy/Harry Potter",
+            "Draco%20Malfoy*s*Harry%20Potter",
+        ),
+        ("Q&A?", "Q*a*A*q*"),
+        ("#1 Fan", "*h*1%20Fan"),
+        ("Pokémon", "Pok%C3%A9mon"),
+    ] {
+        assert_eq!(
+            tag_url(BASE_URL, name).unwrap().to_string(),
+            format!("{}/tags/{}", BASE_URL, path),
+            "tag `{}`",
+            name
+        );
+    }
+}
+
+#[test]
+fn unavailable_works_have_no_download() {
+    for (name, story_id) in [("restricted", 1002), ("adult", 2001), ("deleted", 404)] {
+        let doc = fixture(name);
+
+        match get_download_url(&story_url(story_id), &doc) {
+            Ok(url) => panic!(
+                "fixture `{}` should not have a download, got `{}`",
+                name, url
+            ),
+            Err(err) => assert_matched_nothing(name, err),
+        }
+    }
+}
+
+#[test]
+fn drifted_markup_names_the_selector() {
+    let html = fixture_html("work-single.download").replace("class=\"meta\"", "class=\"front\"");
+    let doc = query::Document::try_from(html.as_str()).unwrap();
+
+    let err = get_story_info(BASE_URL, &story_url(1001), &doc).unwrap_err();
+    assert!(
+        format!("{:?}", err)
+            .contains("selector `html > body > #preface > .meta > h1` matched nothing"),
+        "{:?}",
+        err
+    );
+
+    let err = get_story_meta(&doc).unwrap_err();
+    assert!(
+        format!("{:?}", err)
+            .contains("selector `html > body > #preface > .meta > .tags > dt` matched nothing"),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn search_is_sliced_by_date() {
+    let from = time::Date::from_calendar_date(2020, time::Month::January, 1).unwrap();
+    let to = time::Date::from_calendar_date(2020, time::Month::January, 31).unwrap();
+
+    let (earlier, later) = Window { from, to }.split().unwrap();
+    assert_eq!(earlier.to.day(), 16);
+    assert_eq!(later.from.day(), 17);
+    assert_eq!(Window { from, to: from }.split(), None);
+
+    let epoch = time::Date::from_calendar_date(1970, time::Month::January, 1).unwrap();
+    assert_eq!(
+        Window::all(SliceBy::RevisedAt, Some(from)).unwrap().from,
+        from
+    );
+    assert_eq!(Window::all(SliceBy::RevisedAt, None).unwrap().from, epoch);
+    assert_eq!(
+        Window::all(SliceBy::CreatedAt, Some(from)).unwrap().from,
+        epoch
+    );
+
+    let url = Uri::try_from(format!(
+        "{}/works/search?page=3&work_search%5Bquery%5D=tales&work_search%5Bsort_column%5D=revised_at",
+        BASE_URL
+    ))
+    .unwrap();
+
+    assert_eq!(
+        slice_url(&url, SliceBy::RevisedAt, earlier).unwrap().to_string(),
+        format!(
+            "{}/works/search?work_search%5Bquery%5D=tales+revised_at%3A%5B2020-01-01+TO+2020-01-16%5D&work_search%5Bsort_column%5D=revised_at",
+            BASE_URL
+        )
+    );
+
+    let url = Uri::try_from(format!("{}/tags/Original%20Work/works", BASE_URL)).unwrap();
+
+    assert_eq!(
+        slice_url(&url, SliceBy::CreatedAt, later).unwrap().to_string(),
+        format!(
+            "{}/tags/Original%20Work/works?work_search%5Bquery%5D=created_at%3A%5B2020-01-17+TO+2020-01-31%5D",
+            BASE_URL
+        )
+    );
+}
+
+#[test]
+fn search_is_built_from_filters() {
+    assert_eq!(
+        Search::default().url().unwrap(),
+        "/works/search?work_search%5Bquery%5D="
+    );
+
+    let search = Search {
+        fandoms: vec!["Original Work".to_string()],
+        ships: vec!["Alice/Bob".to_string()],
+        characters: vec!["Alice".to_string(), "Bob".to_string()],
+        tags: vec!["Fluff".to_string()],
+        excluded_tags: vec!["Angst".to_string()],
+        rating: Some(Rating::Teen),
+        warnings: vec![Warning::NoWarnings, Warning::ChooseNotToUse],
+        language: Some("en".to_string()),
+        words_min: Some(1000),
+        completion: Some(Completion::Complete),
+        sort: Some(Sort::Kudos),
+        ..Search::default()
+    };
+
+    assert_eq!(
+        search.url().unwrap(),
+        concat!(
+            "/works/search?work_search%5Bquery%5D=",
+            "&work_search%5Bcomplete%5D=T",
+            "&work_search%5Bword_count%5D=%3E999",
+            "&work_search%5Blanguage_id%5D=en",
+            "&work_search%5Bfandom_names%5D=Original+Work",
+            "&work_search%5Brelationship_names%5D=Alice%2FBob",
+            "&work_search%5Bcharacter_names%5D=Alice%2CBob",
+            "&work_search%5Bfreeform_names%5D=Fluff",
+            "&work_search%5Bexcluded_tag_names%5D=Angst",
+            "&work_search%5Brating_ids%5D=11",
+            "&work_search%5Bsort_column%5D=kudos_count",
+            "&work_search%5Bsort_direction%5D=desc",
+            "&work_search%5Barchive_warning_ids%5D%5B%5D=16",
+            "&work_search%5Barchive_warning_ids%5D%5B%5D=14",
+        )
+    );
+
+    let json = serde_json::to_string(&search).unwrap();
+    assert_eq!(serde_json::from_str::<Search>(&json).unwrap(), search);
+
+    assert!(Search {
+        rating: Some(Rating::Unknown),
+        ..Search::default()
+    }
+    .url()
+    .is_err());
+}
+
+#[test]
+fn limits_bound_the_crawl() {
+    let since = crate::parse_date("2020-01-15").unwrap();
+    assert_eq!(format_date(since), "2020-01-15");
+    assert!(crate::parse_date("2020-13-01").is_err());
+    assert!(crate::parse_date("yesterday").is_err());
+
+    let mut budget = Budget::new(Limits {
+        max_pages: Some(2),
+        max_works: Some(3),
+        since: Some(since),
+        dry_run: true,
+    });
+
+    let works = get_page_works(
+        &Uri::try_from(BASE_URL).unwrap(),
+        &Uri::try_from(BASE_URL).unwrap(),
+        &fixture("search"),
+    )
+    .unwrap();
+    assert!(budget.too_old(works[0].updated));
+    assert!(!budget.too_old(works[1].updated));
+    assert!(!budget.too_old(None));
+
+    assert!(budget.take_page());
+    assert!(budget.take_work() && budget.take_work() && budget.take_work());
+    assert!(!budget.take_work());
+    assert!(budget.exhausted());
+    assert!(!budget.take_page());
+}
+
+#[test]
+fn scripts_and_styles_are_dropped() {
+    for (html, expected) in [
+        ("<p>hi</p><script>alert(1)</script>", "<p>hi</p>"),
+        ("<p>hi<style>p { display: none }</style></p>", "<p>hi</p>"),
+        (
+            "<iframe src=\"https://example.com\"></iframe><p>text</p>",
+            "<p>text</p>",
+        ),
+        ("<svg><script>alert(1)</script></svg>", ""),
+    ] {
+        assert_eq!(sanitize(html).unwrap(), expected, "`{}`", html);
+    }
+}
+
+#[test]
+fn unsafe_attributes_are_dropped() {
+    for (html, expected) in [
+        ("<p onclick=\"alert(1)\">a</p>", "<p>a</p>"),
+        ("<img src=\"x\" onerror=\"alert(1)\">", ""),
+        ("<p style=\"position: fixed\">a</p>", "<p>a</p>"),
+        (
+            "<a href=\"javascript:alert(1)\">a</a>",
+            "<a rel=\"nofollow noopener noreferrer\">a</a>",
+        ),
+        (
+            "<a href=\" JaVaScRiPt:alert(1)\">a</a>",
+            "<a rel=\"nofollow noopener noreferrer\">a</a>",
+        ),
+        (
+            "<a href=\"data:text/html,<script>alert(1)</script>\">a</a>",
+            "<a rel=\"nofollow noopener noreferrer\">a</a>",
+        ),
+        (
+            "<blockquote cite=\"vbscript:msgbox\">q</blockquote>",
+            "<blockquote>q</blockquote>",
+        ),
+    ] {
+        assert_eq!(sanitize(html).unwrap(), expected, "`{}`", html);
+    }
+}
+
+#[test]
+fn allowed_markup_is_kept() {
+    for (html, expected) in [
+        (
+            "<p><strong>bold</strong> <em>and</em><br>more</p>",
+            "<p><strong>bold</strong> <em>and</em><br>more</p>",
+        ),
+        (
+            "<a href=\"https://archiveofourown.org/works/1\" title=\"a work\">link</a>",
+            "<a href=\"https://archiveofourown.org/works/1\" title=\"a work\" rel=\"nofollow noopener noreferrer\">link</a>",
+        ),
+        (
+            "<a href=\"/works/1#chapter\">relative</a>",
+            "<a href=\"/works/1#chapter\" rel=\"nofollow noopener noreferrer\">relative</a>",
+        ),
+        (
+            "<blockquote cite=\"http://example.com\"><p>q</p></blockquote>",
+            "<blockquote cite=\"http://example.com\"><p>q</p></blockquote>",
+        ),
+        (
+            "<table><tr><td colspan=\"2\">cell</td></tr></table>",
+            "<table><tbody><tr><td colspan=\"2\">cell</td></tr></tbody></table>",
+        ),
+        ("<font color=\"red\">unwrapped</font>", "unwrapped"),
+        (
+            "<p title=\"dropped\">a &lt; b &amp; c\u{a0}</p>",
+            "<p>a &lt; b &amp; c&nbsp;</p>",
+        ),
+    ] {
+        assert_eq!(sanitize(html).unwrap(), expected, "`{}`", html);
+    }
+}
+
+static SELECTOR_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<body>
+<div id="main" class="wrapper region">
+<h2 class="heading">Title</h2>
+<p class="first">one</p>
+<p lang="en-GB" data-note="a ] b">two</p>
+<span></span>
+<p title="Hello World">three</p>
+<ul>
+<li>a</li>
+<li class="x y">b</li>
+<li>c</li>
+<li>d</li>
+<li>e</li>
+</ul>
+<div id="123">numbered</div>
+</div>
+</body>
+</html>"#;
+
+/// The trimmed text of every element a selector matches, in document order.
+fn selected(selector: &str) -> Vec<String> {
+    let doc = query::Document::try_from(SELECTOR_HTML).unwrap();
+
+    doc.select(selector)
+        .unwrap_or_else(|err| panic!("`{}` failed: {}", selector, err))
+        .into_iter()
+        .map(|element| element.text().unwrap_or_default().trim().to_string())
+        .collect()
+}
+
+fn selector_error(selector: &str) -> (usize, String) {
+    let doc = query::Document::try_from(SELECTOR_HTML).unwrap();
+
+    match doc.select(selector) {
+        Ok(found) => panic!("`{}` should not parse, found {:?}", selector, found),
+        Err(err) => (err.position, err.reason),
+    }
+}
+
+#[test]
+fn selectors_combine() {
+    assert_eq!(selected("#main > p"), ["one", "two", "three"]);
+    assert_eq!(selected("body p"), ["one", "two", "three"]);
+    assert_eq!(selected("body > p"), Vec::<String>::new());
+    assert_eq!(selected("h2 + p"), ["one"]);
+    assert_eq!(selected("span + p"), ["three"]);
+    assert_eq!(selected("span + span"), Vec::<String>::new());
+    assert_eq!(selected("h2 ~ p"), ["one", "two", "three"]);
+    assert_eq!(selected(".first ~ p"), ["two", "three"]);
+    assert_eq!(selected("h2, .first"), ["Title", "one"]);
+    assert_eq!(selected("div.wrapper.region > ul li.x"), ["b"]);
+}
+
+#[test]
+fn pseudo_classes_match() {
+    assert_eq!(selected("li:first-child"), ["a"]);
+    assert_eq!(selected("li:last-child"), ["e"]);
+    assert_eq!(selected("li:nth-child(2n+1)"), ["a", "c", "e"]);
+    assert_eq!(selected("li:nth-child(odd)"), ["a", "c", "e"]);
+    assert_eq!(selected("li:nth-child(even)"), ["b", "d"]);
+    assert_eq!(selected("li:nth-child( -n + 2 )"), ["a", "b"]);
+    assert_eq!(selected("li:nth-child(3)"), ["c"]);
+    assert_eq!(selected("li:nth-last-child(2)"), ["d"]);
+    assert_eq!(selected("p:first-of-type"), ["one"]);
+    assert_eq!(selected("p:last-of-type"), ["three"]);
+    assert_eq!(selected("#main > p:nth-of-type(2)"), ["two"]);
+    assert_eq!(selected("#main > p:nth-last-of-type(3)"), ["one"]);
+    assert_eq!(selected("h2:only-of-type"), ["Title"]);
+    assert_eq!(selected("li:only-child"), Vec::<String>::new());
+    assert_eq!(selected("#main > :empty"), [""]);
+    assert_eq!(selected(":root").len(), 1);
+    assert_eq!(selected("li:not(.x)"), ["a", "c", "d", "e"]);
+    assert_eq!(
+        selected("li:not(:first-child, :last-child)"),
+        ["b", "c", "d"]
+    );
+    assert_eq!(selected("li:is(.y, :last-child)"), ["b", "e"]);
+    assert_eq!(selected("li:where(:nth-child(4))"), ["d"]);
+}
+
+#[test]
+fn attributes_match() {
+    assert_eq!(selected("[title]"), ["three"]);
+    assert_eq!(selected("[title=\"Hello World\"]"), ["three"]);
+    assert_eq!(selected("[title='Hello World']"), ["three"]);
+    assert_eq!(selected("[title=\"hello world\"]"), Vec::<String>::new());
+    assert_eq!(selected("[title=\"hello world\" i]"), ["three"]);
+    assert_eq!(selected("[title~=World]"), ["three"]);
+    assert_eq!(selected("[title^=Hell]"), ["three"]);
+    assert_eq!(selected("[title$=world]"), Vec::<String>::new());
+    assert_eq!(selected("[title$=world i]"), ["three"]);
+    assert_eq!(selected("[title*=\"o W\"]"), ["three"]);
+    assert_eq!(selected("[title^=\"\"]"), Vec::<String>::new());
+    assert_eq!(selected("[lang|=en]"), ["two"]);
+    assert_eq!(selected("[lang|=en-GB]"), ["two"]);
+    assert_eq!(selected("[lang|=e]"), Vec::<String>::new());
+    assert_eq!(selected("[data-note=\"a ] b\"]"), ["two"]);
+    assert_eq!(selected("[ data-note = 'a ] b' ]"), ["two"]);
+    assert_eq!(selected("[class~=y]"), ["b"]);
+}
+
+#[test]
+fn escapes_are_decoded() {
+    assert_eq!(selected("#\\31 23"), ["numbered"]);
+    assert_eq!(selected("#\\000031\\32 3"), ["numbered"]);
+    assert_eq!(selected("[title=\"Hello\\20World\"]"), ["three"]);
+    assert_eq!(selected("[title=\"Hello\\\nWorld\"]"), Vec::<String>::new());
+    assert_eq!(selected("[title=\"Hello \\\nWorld\"]"), ["three"]);
+    assert_eq!(selected(".\\y"), ["b"]);
+    assert_eq!(selected("#\\110000"), Vec::<String>::new());
+}
+
+#[test]
+fn selector_errors_point_at_the_problem() {
+    for (selector, position, reason) in [
+        ("p >", 3, "expected a selector, found the end"),
+        ("a, ", 3, "expected a selector, found the end"),
+        ("p )", 2, "unexpected `)`"),
+        ("p#", 2, "expected an identifier"),
+        ("p[title", 7, "unterminated attribute selector"),
+        ("p[title!=a]", 7, "unexpected `!` in attribute"),
+        ("p[title~a]", 8, "expected `=`, found `a`"),
+        ("[title=\"open]", 7, "unterminated string"),
+        ("p:hover", 2, "unsupported pseudo-class `:hover`"),
+        ("p:has(a)", 2, "unsupported pseudo-class `:has()`"),
+        ("p::before", 2, "pseudo-elements are not supported"),
+        ("li:nth-child(2x)", 13, "invalid `an+b` expression `2x`"),
+        (":not(p", 6, "expected `)`, found the end"),
+        ("#a\\", 3, "unterminated escape"),
+        (
+            "#a\\\nb",
+            2,
+            "a newline can't be escaped outside of a string",
+        ),
+    ] {
+        assert_eq!(
+            selector_error(selector),
+            (position, reason.to_string()),
+            "`{}`",
+            selector
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ao3fti_common::{channel, err, Conf, Context as _, Report, Uri};
+use ao3fti_indexer::StoryData;
+use ao3fti_queries::Pool;
+use futures::future::TryFutureExt as _;
+use tracing::Span;
+
+use crate::{
+    get_download_url, get_story_id, parse_story, query, rebuild_url, store_story, FetchedStory,
+    Store,
+};
+
+/// Replays WARC files written by earlier crawls into the database and index, through the same
+/// parsers a crawl uses.
+///
+/// The files are read together, as a work and its download can end up either side of a
+/// rotation. Every work page in them is imported along with its download, works whose
+/// download is missing are skipped.
+///
+/// Files rotate at `conf.warc_size`, so they're streamed rather than read whole: a first pass
+/// notes where each page's last response is, and each work's pages are read back as it's
+/// imported.
+#[tracing::instrument(skip(conf), err)]
+pub async fn run(conf: Arc<Conf>, paths: Vec<PathBuf>) -> Result<(), ao3fti_common::Report> {
+    let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
+
+    let (line_sender, line_receiver) = channel::bounded(10_000);
+
+    let background_worker = tokio::task::spawn_blocking({
+        let span = Span::current();
+        let conf = conf.clone();
+
+        move || span.in_scope(|| ao3fti_indexer::index(conf.clone(), line_receiver))
+    })
+    .map_err(Report::from);
+
+    #[tracing::instrument(skip(pool, paths, line_sender), err)]
+    async fn inner(
+        pool: Pool,
+        paths: Vec<PathBuf>,
+        line_sender: channel::Sender<StoryData>,
+    ) -> Result<(), ao3fti_common::Report> {
+        let mut responses = HashMap::new();
+
+        for path in &paths {
+            tracing::info!(path = %path.display(), "reading warc");
+
+            // later files have the newer copy of a page
+            for (url, offset) in ao3fti_common::warc::responses(path)? {
+                responses.insert(url, (path.as_path(), offset));
+            }
+        }
+
+        let crawl_url = paths
+            .iter()
+            .map(|path| format!("file://{}", path.display()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let crawl_id = ao3fti_queries::insert_crawl(pool.clone(), &crawl_url, None).await?;
+
+        tracing::info!(crawl_id = %crawl_id, "importing warcs");
+
+        let store = Store {
+            pool: pool.clone(),
+            line_sender: &line_sender,
+            crawl_id,
+        };
+
+        let mut stories = responses
+            .keys()
+            .filter_map(|url| {
+                let url = Uri::try_from(url.as_str()).ok()?;
+
+                if !is_work_page(&url) {
+                    return None;
+                }
+
+                Some((get_story_id(&url).ok()?, url))
+            })
+            .collect::<Vec<_>>();
+
+        stories.sort_by_key(|(story_id, _)| *story_id);
+
+        for (story_id, story_url) in stories {
+            if ao3fti_queries::check_story_if_exists(pool.clone(), story_id).await? {
+                store_story(&store, story_id, None).await?;
+
+                continue;
+            }
+
+            match replay_story(&responses, story_id, &story_url) {
+                Ok(story) => store_story(&store, story_id, Some(story)).await?,
+                Err(err) => {
+                    tracing::warn!(story_id = %story_id, error = ?err, "skipping story");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // TODO(txuritan): make this be only one Result
+    let (res, _) = tokio::try_join!(background_worker, inner(pool, paths, line_sender))?;
+    res?;
+
+    Ok(())
+}
+
+/// If a url is a work's page, and not one of its chapters or anything else under it.
+fn is_work_page(url: &Uri) -> bool {
+    let mut segments = url.path().split('/').filter(|s| !s.is_empty());
+
+    matches!(
+        (segments.next(), segments.next(), segments.next()),
+        (Some("works"), Some(id), None) if id.bytes().all(|byte| byte.is_ascii_digit())
+    )
+}
+
+/// Where in which file the last successful response for each url is.
+type Responses<'p> = HashMap<String, (&'p Path, u64)>;
+
+/// Reads the body of a url's response back out of its file.
+fn response(responses: &Responses<'_>, url: &Uri) -> Result<String, ao3fti_common::Report> {
+    let (path, offset) = responses
+        .get(&url.to_string())
+        .ok_or_else(|| err!("`{}` isn't in the warc", url))?;
+
+    ao3fti_common::warc::read_at(path, *offset)?
+        .body()
+        .ok_or_else(|| err!("the response for `{}` has no body", url))
+}
+
+fn replay_story(
+    responses: &Responses<'_>,
+    story_id: usize,
+    story_url: &Uri,
+) -> Result<FetchedStory, ao3fti_common::Report> {
+    let story_html = response(responses, story_url)?;
+    let story_doc = query::Document::try_from(story_html.as_str())?;
+
+    let download_url = get_download_url(story_url, &story_doc)?;
+    let download_url = Uri::try_from(download_url.as_str())
+        .with_context(|| format!("with url, at line {}: `{}`", line!(), download_url))?;
+    // downloads are on the same host as the work, whichever host the crawl was against
+    let download_url = rebuild_url(story_url, &download_url)?;
+
+    let download_html = response(responses, &download_url)?;
+
+    let base_url = format!(
+        "{}://{}",
+        story_url.scheme_str().unwrap_or("https"),
+        story_url
+            .authority()
+            .map(|authority| authority.as_str())
+            .unwrap_or_default()
+    );
+
+    parse_story(&base_url, story_id, story_url, &story_doc, &download_html)
+}
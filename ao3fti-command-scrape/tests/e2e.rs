@@ -0,0 +1,499 @@
+//! End to end scrapes against a local mock of the archive, serving the pages in `fixtures/`.
+//!
+//! The mock rate limits and stalls some of its responses the first time they're requested, the
+//! same way AO3 does under load, so a scrape only finishes if the client backs off and retries.
+//! Every page has an ETag, and isn't sent again to a client that already has it.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use ao3fti_command_scrape::Limits;
+use ao3fti_common::Conf;
+use ao3fti_indexer::{Hit, IndexServer, NamedFieldDocument, SearchQuery, Synonyms, Value};
+use axum::{
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router, Server,
+};
+use tempfile::TempDir;
+
+static SEARCH_URL: &str = "https://archiveofourown.org/works/search?work_search%5Bquery%5D=tales";
+
+#[derive(Default)]
+struct Archive {
+    /// Rate limit every request instead of only the first for a page.
+    always_limited: bool,
+    /// How many times each path has been requested.
+    hits: Mutex<HashMap<String, usize>>,
+    /// How many requests were answered with a `304 Not Modified`.
+    not_modified: AtomicUsize,
+}
+
+fn fixture(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(format!("{}.html", name));
+
+    std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("unable to read fixture `{}`: {}", path.display(), err))
+}
+
+/// The third work reuses the first's pages, with its id and name swapped out.
+fn third(name: &str) -> String {
+    fixture(name)
+        .replace("1001", "3001")
+        .replace("First Work", "Third Work")
+        .replace("First%20Work", "Third%20Work")
+}
+
+fn rate_limited() -> Response {
+    (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "0")]).into_response()
+}
+
+async fn respond(archive: Arc<Archive>, uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().to_string();
+
+    let hit = {
+        let mut hits = archive.hits.lock().unwrap();
+        let hit = hits.entry(path.clone()).or_default();
+
+        *hit += 1;
+        *hit
+    };
+
+    if archive.always_limited {
+        return rate_limited();
+    }
+
+    let page = match (path.as_str(), hit) {
+        ("/works/2001", 1) => return rate_limited(),
+        ("/downloads/2001/Second%20Work.html", 1) => {
+            // longer than the client's timeout
+            tokio::time::sleep(Duration::from_secs(3)).await;
+
+            fixture("work-multi.download")
+        }
+        ("/works/search", _) => match uri.query() {
+            Some(query) if query.contains("page=2") => fixture("search-last"),
+            _ => fixture("search"),
+        },
+        ("/works/1001", _) => fixture("work-single"),
+        ("/works/2001", _) => fixture("work-multi"),
+        ("/works/3001", _) => third("work-single"),
+        ("/downloads/1001/First%20Work.html", _) => fixture("work-single.download"),
+        ("/downloads/2001/Second%20Work.html", _) => fixture("work-multi.download"),
+        ("/downloads/3001/Third%20Work.html", _) => third("work-single.download"),
+        _ => return (StatusCode::NOT_FOUND, Html(fixture("deleted"))).into_response(),
+    };
+
+    let etag = format!("\"{}-{}\"", path.len(), page.len());
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        archive.not_modified.fetch_add(1, Ordering::SeqCst);
+
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    ([(header::ETAG, etag)], Html(page)).into_response()
+}
+
+fn serve(archive: Archive) -> SocketAddr {
+    serve_shared(Arc::new(archive))
+}
+
+/// Serves an archive the test keeps a handle to, to look at what was requested.
+fn serve_shared(archive: Arc<Archive>) -> SocketAddr {
+    let app = Router::new().fallback(get(move |uri: Uri, headers: HeaderMap| {
+        respond(archive.clone(), uri, headers)
+    }));
+
+    let server =
+        Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+    let addr = server.local_addr();
+
+    tokio::spawn(server);
+
+    addr
+}
+
+fn conf(dir: &TempDir, addr: SocketAddr, concurrency: usize) -> Conf {
+    Conf {
+        database: format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("ao3fti.db").display()
+        ),
+        index: dir.path().join("index"),
+        base_url: format!("http://{}", addr),
+        delay_min: 0,
+        delay_max: 0,
+        timeout: 1,
+        retries: 3,
+        concurrency,
+        cache: None,
+        cache_only: false,
+        warc: None,
+        warc_size: 1_000_000_000,
+        ranking_profiles: None,
+        ranking_profile: "relevance".into(),
+    }
+}
+
+fn search(index: &Arc<IndexServer>, query: &str) -> Vec<u64> {
+    let serp = ao3fti_indexer::serp(
+        index.clone(),
+        SearchQuery {
+            query: query.to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+
+    let mut ids = serp
+        .hits
+        .into_iter()
+        .filter_map(
+            |Hit {
+                 doc: NamedFieldDocument(map),
+                 ..
+             }| match map.get("id").and_then(|values| values.first()) {
+                Some(Value::U64(id)) => Some(*id),
+                _ => None,
+            },
+        )
+        .collect::<Vec<_>>();
+
+    ids.sort_unstable();
+
+    ids
+}
+
+/// Scrapes the mock's search and checks everything ended up stored and indexed.
+async fn scrape_search(concurrency: usize) {
+    let dir = tempfile::tempdir().unwrap();
+    let addr = serve(Archive::default());
+    let conf = Arc::new(conf(&dir, addr, concurrency));
+
+    ao3fti_command_scrape::run(conf.clone(), SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap();
+
+    let pool = ao3fti_queries::init_database_connection(conf.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        ao3fti_queries::get_story_count(pool.clone()).await.unwrap(),
+        3
+    );
+
+    let crawls = ao3fti_queries::get_story_crawls(pool.clone(), 2001)
+        .await
+        .unwrap();
+    assert_eq!(crawls.len(), 1);
+    assert!(crawls[0]
+        .url
+        .ends_with("/works/search?work_search%5Bquery%5D=tales"));
+    assert_eq!(crawls[0].search, None);
+
+    let story = ao3fti_queries::get_story(pool.clone(), 2001).await.unwrap();
+    assert_eq!(story.name, "Second Work");
+    assert_eq!(story.authors.len(), 2);
+
+    assert!(
+        !ao3fti_queries::check_story_if_exists(pool.clone(), 1002)
+            .await
+            .unwrap(),
+        "restricted works are skipped"
+    );
+
+    let index = IndexServer::new(&conf).unwrap();
+
+    assert_eq!(search(&index, "adventure"), vec![2001]);
+    assert_eq!(search(&index, "\"once upon a time\""), vec![1001, 3001]);
+    assert_eq!(search(&index, "mythology"), vec![2001]);
+    assert_eq!(search(&index, "dragons"), Vec::<u64>::new());
+
+    assert_eq!(search(&index, "fandom:mythology"), vec![2001]);
+    assert_eq!(search(&index, "-tag:fluff"), vec![2001]);
+    assert_eq!(search(&index, "kudos>=1,000 complete:no"), vec![2001]);
+    assert_eq!(search(&index, "rating:teen..general"), vec![1001, 3001]);
+    assert_eq!(search(&index, "author:writer words>4"), vec![2001]);
+
+    let passages = ao3fti_indexer::passages(
+        index.clone(),
+        SearchQuery {
+            query: "ends".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+    assert_eq!(passages.works.len(), 1);
+    assert_eq!(passages.works[0].id, 2001);
+    assert_eq!(
+        (
+            passages.works[0].passages[0].chapter,
+            passages.works[0].passages[0].paragraph
+        ),
+        (2, 0)
+    );
+
+    let text = ao3fti_indexer::story_text(index, 2001).unwrap();
+    assert_eq!(
+        text.into_iter()
+            .map(|passage| passage.paragraphs)
+            .collect::<Vec<_>>(),
+        vec![vec!["It begins.".to_string()], vec!["It ends.".to_string()]]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrapes_search_into_database_and_index() {
+    scrape_search(1).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrapes_search_concurrently() {
+    scrape_search(4).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gives_up_when_always_rate_limited() {
+    let dir = tempfile::tempdir().unwrap();
+    let addr = serve(Archive {
+        always_limited: true,
+        ..Archive::default()
+    });
+    let conf = Arc::new(conf(&dir, addr, 1));
+
+    let err = ao3fti_command_scrape::run(conf, SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap_err();
+
+    assert!(
+        format!("{:?}", err).contains("after 3 retries"),
+        "{:?}",
+        err
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dry_run_only_fetches_listings() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = Arc::new(Archive::default());
+    let addr = serve_shared(archive.clone());
+    let conf = Arc::new(conf(&dir, addr, 1));
+
+    ao3fti_command_scrape::run(
+        conf,
+        SEARCH_URL,
+        None,
+        Limits {
+            max_pages: Some(1),
+            dry_run: true,
+            ..Limits::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let hits = archive.hits.lock().unwrap();
+    assert_eq!(hits.keys().collect::<Vec<_>>(), vec!["/works/search"]);
+    assert_eq!(hits["/works/search"], 1);
+
+    assert!(!dir.path().join("ao3fti.db").exists());
+    assert!(!dir.path().join("index").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_crawl_from_cache() {
+    let cache = tempfile::tempdir().unwrap();
+    let archive = Arc::new(Archive::default());
+    let addr = serve_shared(archive.clone());
+
+    let cached = |dir: &TempDir, cache_only: bool| {
+        Arc::new(Conf {
+            cache: Some(cache.path().to_path_buf()),
+            cache_only,
+            ..conf(dir, addr, 1)
+        })
+    };
+    let requests = || archive.hits.lock().unwrap().values().sum::<usize>();
+    let stored = |conf: Arc<Conf>| async move {
+        let pool = ao3fti_queries::init_database_connection(conf)
+            .await
+            .unwrap();
+
+        ao3fti_queries::get_story_count(pool).await.unwrap()
+    };
+
+    let fetched = tempfile::tempdir().unwrap();
+    ao3fti_command_scrape::run(cached(&fetched, false), SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap();
+    assert_eq!(archive.not_modified.load(Ordering::SeqCst), 0);
+
+    let before = requests();
+
+    let replayed = tempfile::tempdir().unwrap();
+    ao3fti_command_scrape::run(cached(&replayed, true), SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap();
+    assert_eq!(requests(), before, "a cache only crawl made requests");
+    assert_eq!(stored(cached(&replayed, true)).await, 3);
+
+    let revalidated = tempfile::tempdir().unwrap();
+    ao3fti_command_scrape::run(
+        cached(&revalidated, false),
+        SEARCH_URL,
+        None,
+        Limits::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        archive.not_modified.load(Ordering::SeqCst),
+        requests() - before,
+        "every page should have been revalidated"
+    );
+    assert_eq!(stored(cached(&revalidated, false)).await, 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exports_and_imports_warc() {
+    let warcs = tempfile::tempdir().unwrap();
+    let addr = serve(Archive::default());
+
+    let fetched = tempfile::tempdir().unwrap();
+    let fetch_conf = Arc::new(Conf {
+        warc: Some(warcs.path().to_path_buf()),
+        // small enough that every exchange starts a new file
+        warc_size: 1,
+        ..conf(&fetched, addr, 1)
+    });
+
+    ao3fti_command_scrape::run(fetch_conf.clone(), SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap();
+
+    let mut paths = std::fs::read_dir(warcs.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(paths.len() > 1, "the warc was never rotated");
+
+    let records = ao3fti_common::warc::read(&paths[0])
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records[0].header("WARC-Type"), Some("warcinfo"));
+    assert!(records
+        .iter()
+        .any(|record| record.header("WARC-Type") == Some("metadata")));
+
+    let imported = tempfile::tempdir().unwrap();
+    let conf = Arc::new(conf(&imported, addr, 1));
+
+    ao3fti_command_scrape::warc::run(conf.clone(), paths)
+        .await
+        .unwrap();
+
+    let pool = ao3fti_queries::init_database_connection(conf.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        ao3fti_queries::get_story_count(pool.clone()).await.unwrap(),
+        3
+    );
+
+    let story = ao3fti_queries::get_story(pool.clone(), 2001).await.unwrap();
+    assert_eq!(story.name, "Second Work");
+    assert_eq!(
+        ao3fti_queries::get_story_crawls(pool, 2001)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let index = IndexServer::new(&conf).unwrap();
+    assert_eq!(search(&index, "adventure"), vec![2001]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn imports_revalidated_pages_from_warc() {
+    let cache = tempfile::tempdir().unwrap();
+    let warcs = tempfile::tempdir().unwrap();
+    let archive = Arc::new(Archive::default());
+    let addr = serve_shared(archive.clone());
+
+    let cached = |dir: &TempDir, warc: Option<PathBuf>| {
+        Arc::new(Conf {
+            cache: Some(cache.path().to_path_buf()),
+            warc,
+            ..conf(dir, addr, 1)
+        })
+    };
+
+    let fetched = tempfile::tempdir().unwrap();
+    ao3fti_command_scrape::run(cached(&fetched, None), SEARCH_URL, None, Limits::default())
+        .await
+        .unwrap();
+
+    let revalidated = tempfile::tempdir().unwrap();
+    ao3fti_command_scrape::run(
+        cached(&revalidated, Some(warcs.path().to_path_buf())),
+        SEARCH_URL,
+        None,
+        Limits::default(),
+    )
+    .await
+    .unwrap();
+    assert!(archive.not_modified.load(Ordering::SeqCst) > 0);
+
+    let paths = std::fs::read_dir(warcs.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+
+    let records = paths
+        .iter()
+        .flat_map(|path| ao3fti_common::warc::read(path).unwrap())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert!(records
+        .iter()
+        .any(|record| record.header("WARC-Type") == Some("revisit") && record.body().is_some()));
+
+    let imported = tempfile::tempdir().unwrap();
+    let conf = Arc::new(conf(&imported, addr, 1));
+
+    ao3fti_command_scrape::warc::run(conf.clone(), paths)
+        .await
+        .unwrap();
+
+    let pool = ao3fti_queries::init_database_connection(conf)
+        .await
+        .unwrap();
+    assert_eq!(ao3fti_queries::get_story_count(pool).await.unwrap(), 3);
+}
@@ -1,14 +1,24 @@
+mod names;
+
+#[cfg(test)]
+mod tests;
+
 use std::{sync::Arc, time::Duration};
 
-use ao3fti_common::{models::Story, Conf};
+use ao3fti_common::{
+    models::{Story, Tag, WorkRelation},
+    Conf,
+};
 use ao3fti_indexer::{
-    Hit, IndexServer, NamedFieldDocument, SearchQuery as ApiSearchQuery, Serp, Value,
+    Duplicates, DuplicatesQuery, Hit, IndexServer, InvalidSimilarity, NamedFieldDocument,
+    ParseError, Passage, PassageHit, PassageSerp, PassageWork, SearchQuery as ApiSearchQuery, Serp,
+    SimilarQuery, SimilarSerp, Snippet, Synonyms, UnknownProfile, Value,
 };
-use ao3fti_queries::Pool;
+use ao3fti_queries::{Pool, User};
 use askama::Template;
 use axum::{
     error_handling::HandleErrorLayer,
-    extract::{Extension, Query},
+    extract::{Extension, Path, Query},
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
@@ -17,14 +27,28 @@ use axum::{
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 
+use crate::names::{Name, NameKind, Names};
+
 pub async fn run(conf: Arc<Conf>) -> Result<(), ao3fti_common::Report> {
     let pool = ao3fti_queries::init_database_connection(conf.clone()).await?;
-    let index_server = IndexServer::new()?;
+    let index_server = IndexServer::new(&conf)?;
+    let names = Arc::new(Names::load(pool.clone()).await?);
 
-    let app: _ = Router::new()
+    let app = Router::new()
         .route("/", get(index))
         .route("/search", get(search_html))
         .route("/api", get(search_api))
+        .route("/passages", get(passages_html))
+        .route("/api/passages", get(passages_api))
+        .route("/api/suggest", get(suggest_api))
+        .route("/tags/:id", get(tag_html))
+        .route("/series/:id", get(series_html))
+        .route("/users/:id", get(user_html))
+        .route("/works/:id", get(work_html))
+        .route("/works/:id/read", get(read_html))
+        .route("/api/works/:id", get(work_api))
+        .route("/api/works/:id/similar", get(similar_api))
+        .route("/api/duplicates", get(duplicates_api))
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(|error: BoxError| async move {
@@ -39,6 +63,7 @@ pub async fn run(conf: Arc<Conf>) -> Result<(), ao3fti_common::Report> {
                 .timeout(Duration::from_secs(10))
                 .layer(Extension(pool))
                 .layer(Extension(index_server))
+                .layer(Extension(names))
                 .layer(TraceLayer::new_for_http())
                 .into_inner(),
         );
@@ -75,9 +100,12 @@ async fn index(Extension(pool): Extension<Pool>) -> Result<impl IntoResponse, Er
 }
 
 async fn search_api(
+    Extension(pool): Extension<Pool>,
     Extension(index): Extension<Arc<IndexServer>>,
-    Query(search): Query<ApiSearchQuery>,
+    Query(mut search): Query<ApiSearchQuery>,
 ) -> Result<impl IntoResponse, Error> {
+    search.synonyms = tag_synonyms(pool, &search.query).await?;
+
     let serp = tokio::task::spawn_blocking(move || -> Result<Serp, ao3fti_common::Report> {
         ao3fti_indexer::serp(index, search)
     })
@@ -91,15 +119,50 @@ async fn search_api(
     Ok(Json(serp))
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct SuggestQuery {
+    /// Only complete names of this kind.
+    kind: Option<NameKind>,
+    q: String,
+}
+
+async fn suggest_api(
+    Extension(names): Extension<Arc<Names>>,
+    Query(suggest): Query<SuggestQuery>,
+) -> impl IntoResponse {
+    const SUGGEST_LIMIT: usize = 10;
+
+    let names = names
+        .complete(suggest.kind, &suggest.q, SUGGEST_LIMIT)
+        .into_iter()
+        .cloned()
+        .collect::<Vec<Name>>();
+
+    Json(names)
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct SearchQuery {
     query: String,
     page: usize,
+    #[serde(default)]
+    collapse_series: bool,
+    #[serde(default)]
+    merge_translations: bool,
+    #[serde(default)]
+    fuzzy: bool,
+    #[serde(default)]
+    profile: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize)]
 pub struct SearchQueryPart<'q> {
     query: &'q str,
+    collapse_series: bool,
+    merge_translations: bool,
+    fuzzy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'q str>,
 }
 
 #[derive(askama::Template)]
@@ -107,10 +170,59 @@ pub struct SearchQueryPart<'q> {
 struct Search {
     css: &'static str,
     query: String,
-    stories: Vec<Story>,
+    stories: Vec<SearchStory>,
+    collapse_series: bool,
+    collapse_href: String,
+    merge_translations: bool,
+    merge_href: String,
+    fuzzy: bool,
+    fuzzy_href: String,
+    passages_href: String,
+    profiles: Vec<SearchProfile>,
+    suggestions: Vec<SearchSuggestion>,
     pagination: Pagination,
 }
 
+/// A ranking profile the search can be switched to.
+struct SearchProfile {
+    name: String,
+    href: String,
+    active: bool,
+}
+
+struct SearchSuggestion {
+    href: String,
+    /// The suggested search, with what was changed marked.
+    html: String,
+}
+
+struct SearchStory {
+    story: Story,
+    /// Where the search matched the story's summary and text.
+    snippets: Vec<Snippet>,
+    /// Number of hits on this page hidden because they're in the same series as, or a
+    /// translation of, this story.
+    collapsed: usize,
+}
+
+impl SearchStory {
+    fn absorbs(&self, story: &Story, collapse_series: bool, merge_translations: bool) -> bool {
+        let same_series = collapse_series
+            && self
+                .story
+                .series
+                .iter()
+                .any(|part| story.series.iter().any(|other| part.id == other.id));
+
+        let translation = merge_translations
+            && self.story.relations.iter().any(|relation| {
+                relation.relation == WorkRelation::TranslationOf && relation.id == story.id as u64
+            });
+
+        same_series || translation
+    }
+}
+
 async fn search_html(
     Extension(pool): Extension<Pool>,
     Extension(index): Extension<Arc<IndexServer>>,
@@ -122,45 +234,288 @@ async fn search_html(
         query: search.query.clone(),
         offset: 20 * (search.page - 1),
         limit: SEARCH_LIMIT,
+        fuzzy: search.fuzzy,
+        profile: search.profile.clone(),
+        synonyms: tag_synonyms(pool.clone(), &search.query).await?,
     };
 
+    let active_profile = search
+        .profile
+        .clone()
+        .unwrap_or_else(|| index.profiles.default_name().to_string());
+    let profile_names = index
+        .profiles
+        .names()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
     let serp = tokio::task::spawn_blocking(move || -> Result<Serp, ao3fti_common::Report> {
         ao3fti_indexer::serp(index, api_search)
     })
     .await;
 
-    let Serp { hits, num_hits, .. } = match serp {
+    let Serp {
+        hits,
+        num_hits,
+        suggestions,
+        ..
+    } = match serp {
         Ok(serp) => serp?,
         Err(err) => return Err(Error::from_any(err)),
     };
 
-    let mut stories = Vec::with_capacity(hits.len());
+    let mut stories: Vec<SearchStory> = Vec::with_capacity(hits.len());
 
     for Hit {
         doc: NamedFieldDocument(map),
+        snippets,
         ..
     } in hits
     {
-        let story_id_value = map.get("story_id").and_then(|l| l.iter().next());
+        let story_id_value = map.get("id").and_then(|l| l.iter().next());
         if let Some(Value::U64(story_id)) = story_id_value {
-            stories.push(ao3fti_queries::get_story(pool.clone(), *story_id).await?);
+            let story = ao3fti_queries::get_story(pool.clone(), *story_id).await?;
+
+            let existing = stories.iter_mut().find(|existing| {
+                existing.absorbs(&story, search.collapse_series, search.merge_translations)
+            });
+
+            if let Some(existing) = existing {
+                existing.collapsed += 1;
+
+                continue;
+            }
+
+            stories.push(SearchStory {
+                story,
+                snippets,
+                collapsed: 0,
+            });
         }
     }
 
     let url_fragment = serde_urlencoded::to_string(&SearchQueryPart {
         query: &search.query,
+        collapse_series: search.collapse_series,
+        merge_translations: search.merge_translations,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    let collapse_href = serde_urlencoded::to_string(&SearchQueryPart {
+        query: &search.query,
+        collapse_series: !search.collapse_series,
+        merge_translations: search.merge_translations,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    let merge_href = serde_urlencoded::to_string(&SearchQueryPart {
+        query: &search.query,
+        collapse_series: search.collapse_series,
+        merge_translations: !search.merge_translations,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
     })
     .map_err(Error::from_any)?;
 
+    let fuzzy_href = serde_urlencoded::to_string(&SearchQueryPart {
+        query: &search.query,
+        collapse_series: search.collapse_series,
+        merge_translations: search.merge_translations,
+        fuzzy: !search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    let suggestions = suggestions
+        .into_iter()
+        .map(|suggestion| {
+            let href = serde_urlencoded::to_string(&SearchQueryPart {
+                query: &suggestion.query,
+                collapse_series: search.collapse_series,
+                merge_translations: search.merge_translations,
+                fuzzy: search.fuzzy,
+                profile: search.profile.as_deref(),
+            })
+            .map_err(Error::from_any)?;
+
+            Ok(SearchSuggestion {
+                href: format!("?{}&page=1", href),
+                html: suggestion.html,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let passages_href = serde_urlencoded::to_string(&PassagesQueryPart {
+        query: &search.query,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    let profiles = profile_names
+        .into_iter()
+        .map(|name| {
+            let href = serde_urlencoded::to_string(&SearchQueryPart {
+                query: &search.query,
+                collapse_series: search.collapse_series,
+                merge_translations: search.merge_translations,
+                fuzzy: search.fuzzy,
+                profile: Some(name.as_str()),
+            })
+            .map_err(Error::from_any)?;
+
+            Ok(SearchProfile {
+                href: format!("?{}&page=1", href),
+                active: name == active_profile,
+                name,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
     Ok(Html(
         Search {
             css: STYLE,
             query: search.query,
             stories,
+            collapse_series: search.collapse_series,
+            collapse_href: format!("?{}&page=1", collapse_href),
+            merge_translations: search.merge_translations,
+            merge_href: format!("?{}&page=1", merge_href),
+            fuzzy: search.fuzzy,
+            fuzzy_href: format!("?{}&page=1", fuzzy_href),
+            passages_href: format!("/passages?{}&page=1", passages_href),
+            profiles,
+            suggestions,
+            pagination: Pagination::new(url_fragment, search.page, num_hits.div_ceil(SEARCH_LIMIT)),
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
+async fn passages_api(
+    Extension(pool): Extension<Pool>,
+    Extension(index): Extension<Arc<IndexServer>>,
+    Query(mut search): Query<ApiSearchQuery>,
+) -> Result<impl IntoResponse, Error> {
+    search.synonyms = tag_synonyms(pool, &search.query).await?;
+
+    let serp =
+        tokio::task::spawn_blocking(move || -> Result<PassageSerp, ao3fti_common::Report> {
+            ao3fti_indexer::passages(index, search)
+        })
+        .await;
+
+    let serp = match serp {
+        Ok(serp) => serp?,
+        Err(err) => return Err(Error::from_any(err)),
+    };
+
+    Ok(Json(serp))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PassagesQuery {
+    query: String,
+    page: usize,
+    #[serde(default)]
+    fuzzy: bool,
+    #[serde(default)]
+    profile: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PassagesQueryPart<'q> {
+    query: &'q str,
+    fuzzy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'q str>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "passages.html")]
+struct PassagesPage {
+    css: &'static str,
+    query: String,
+    works: Vec<PassagesStory>,
+    stories_href: String,
+    pagination: Pagination,
+}
+
+struct PassagesStory {
+    story: Story,
+    passages: Vec<PassageHit>,
+}
+
+async fn passages_html(
+    Extension(pool): Extension<Pool>,
+    Extension(index): Extension<Arc<IndexServer>>,
+    Query(search): Query<PassagesQuery>,
+) -> Result<impl IntoResponse, Error> {
+    const PASSAGES_LIMIT: usize = 20;
+
+    let api_search = ApiSearchQuery {
+        query: search.query.clone(),
+        offset: PASSAGES_LIMIT * (search.page - 1),
+        limit: PASSAGES_LIMIT,
+        fuzzy: search.fuzzy,
+        profile: search.profile.clone(),
+        synonyms: tag_synonyms(pool.clone(), &search.query).await?,
+    };
+
+    let serp =
+        tokio::task::spawn_blocking(move || -> Result<PassageSerp, ao3fti_common::Report> {
+            ao3fti_indexer::passages(index, api_search)
+        })
+        .await;
+
+    let PassageSerp {
+        works: hits,
+        num_works,
+        ..
+    } = match serp {
+        Ok(serp) => serp?,
+        Err(err) => return Err(Error::from_any(err)),
+    };
+
+    let mut works = Vec::with_capacity(hits.len());
+    for PassageWork { id, passages, .. } in hits {
+        works.push(PassagesStory {
+            story: ao3fti_queries::get_story(pool.clone(), id).await?,
+            passages,
+        });
+    }
+
+    let url_fragment = serde_urlencoded::to_string(&PassagesQueryPart {
+        query: &search.query,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    let stories_href = serde_urlencoded::to_string(&SearchQueryPart {
+        query: &search.query,
+        collapse_series: false,
+        merge_translations: false,
+        fuzzy: search.fuzzy,
+        profile: search.profile.as_deref(),
+    })
+    .map_err(Error::from_any)?;
+
+    Ok(Html(
+        PassagesPage {
+            css: STYLE,
+            query: search.query,
+            works,
+            stories_href: format!("/search?{}&page=1", stories_href),
             pagination: Pagination::new(
                 url_fragment,
                 search.page,
-                (num_hits + SEARCH_LIMIT - 1) / SEARCH_LIMIT,
+                num_works.div_ceil(PASSAGES_LIMIT),
             ),
         }
         .render()
@@ -168,6 +523,388 @@ async fn search_html(
     ))
 }
 
+/// Looks up the synonyms of every quoted phrase that names a tag, for the parser to widen them to.
+async fn tag_synonyms(pool: Pool, query: &str) -> Result<Synonyms, Error> {
+    let mut synonyms = Synonyms::new();
+    let mut rest = query;
+
+    while let Some(start) = rest.find('"') {
+        let quoted = &rest[start + 1..];
+
+        let end = match quoted.find('"') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let phrase = &quoted[..end];
+        if !synonyms.contains_key(phrase) {
+            let names = ao3fti_queries::get_tag_synonyms(pool.clone(), phrase).await?;
+
+            if names.len() > 1 {
+                synonyms.insert(phrase.to_string(), names);
+            }
+        }
+
+        rest = &quoted[end + 1..];
+    }
+
+    Ok(synonyms)
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PageQuery {
+    #[serde(default = "first_page")]
+    page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+#[derive(askama::Template)]
+#[template(path = "tag.html")]
+struct TagPage {
+    css: &'static str,
+    query: String,
+    tag: Tag,
+    synonyms: Vec<String>,
+    count: i64,
+    stories: Vec<Story>,
+    pagination: Pagination,
+}
+
+async fn tag_html(
+    Extension(pool): Extension<Pool>,
+    Path(tag_id): Path<i32>,
+    Query(search): Query<PageQuery>,
+) -> Result<impl IntoResponse, Error> {
+    const TAG_LIMIT: usize = 20;
+
+    let tag = ao3fti_queries::get_canonical_tag(pool.clone(), tag_id).await?;
+    let synonyms = ao3fti_queries::get_tag_synonyms(pool.clone(), &tag.name)
+        .await?
+        .into_iter()
+        .filter(|name| *name != tag.name)
+        .collect();
+    let count = ao3fti_queries::get_tag_story_count(pool.clone(), tag.id).await?;
+
+    let story_ids = ao3fti_queries::get_tag_story_ids(
+        pool.clone(),
+        tag.id,
+        (TAG_LIMIT * search.page.saturating_sub(1)) as i64,
+        TAG_LIMIT as i64,
+    )
+    .await?;
+
+    let mut stories = Vec::with_capacity(story_ids.len());
+    for story_id in story_ids {
+        stories.push(ao3fti_queries::get_story(pool.clone(), story_id).await?);
+    }
+
+    Ok(Html(
+        TagPage {
+            css: STYLE,
+            query: String::new(),
+            tag,
+            synonyms,
+            count,
+            stories,
+            pagination: Pagination::new(
+                String::new(),
+                search.page,
+                (count as usize).div_ceil(TAG_LIMIT),
+            ),
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
+#[derive(askama::Template)]
+#[template(path = "user.html")]
+struct UserPage {
+    css: &'static str,
+    query: String,
+    user: User,
+    count: i64,
+    stories: Vec<Story>,
+    pagination: Pagination,
+}
+
+async fn user_html(
+    Extension(pool): Extension<Pool>,
+    Path(user_id): Path<i32>,
+    Query(search): Query<PageQuery>,
+) -> Result<impl IntoResponse, Error> {
+    const USER_LIMIT: usize = 20;
+
+    let user = ao3fti_queries::get_user(pool.clone(), user_id).await?;
+    let count = ao3fti_queries::get_user_story_count(pool.clone(), user.id).await?;
+
+    let story_ids = ao3fti_queries::get_user_story_ids(
+        pool.clone(),
+        user.id,
+        (USER_LIMIT * search.page.saturating_sub(1)) as i64,
+        USER_LIMIT as i64,
+    )
+    .await?;
+
+    let mut stories = Vec::with_capacity(story_ids.len());
+    for story_id in story_ids {
+        stories.push(ao3fti_queries::get_story(pool.clone(), story_id).await?);
+    }
+
+    Ok(Html(
+        UserPage {
+            css: STYLE,
+            query: String::new(),
+            user,
+            count,
+            stories,
+            pagination: Pagination::new(
+                String::new(),
+                search.page,
+                (count as usize).div_ceil(USER_LIMIT),
+            ),
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WorkQuery {
+    /// Leave works by the same authors out of the similar ones.
+    #[serde(default)]
+    exclude_authors: bool,
+}
+
+#[derive(askama::Template)]
+#[template(path = "work.html")]
+struct WorkPage {
+    css: &'static str,
+    query: String,
+    story: Story,
+    similar: Vec<Story>,
+    exclude_authors: bool,
+}
+
+async fn work_html(
+    Extension(pool): Extension<Pool>,
+    Extension(index): Extension<Arc<IndexServer>>,
+    Path(story_id): Path<u64>,
+    Query(search): Query<WorkQuery>,
+) -> Result<impl IntoResponse, Error> {
+    const SIMILAR_LIMIT: usize = 5;
+
+    let story = ao3fti_queries::get_story(pool.clone(), story_id).await?;
+
+    let serp = similar_works(index, story.clone(), SIMILAR_LIMIT, search.exclude_authors).await?;
+
+    let mut similar = Vec::with_capacity(serp.works.len());
+    for work in serp.works {
+        similar.push(ao3fti_queries::get_story(pool.clone(), work.id).await?);
+    }
+
+    Ok(Html(
+        WorkPage {
+            css: STYLE,
+            query: String::new(),
+            story,
+            similar,
+            exclude_authors: search.exclude_authors,
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
+async fn similar_api(
+    Extension(pool): Extension<Pool>,
+    Extension(index): Extension<Arc<IndexServer>>,
+    Path(story_id): Path<u64>,
+    Query(search): Query<WorkQuery>,
+) -> Result<impl IntoResponse, Error> {
+    const SIMILAR_LIMIT: usize = 20;
+
+    let story = ao3fti_queries::get_story(pool, story_id).await?;
+
+    let serp = similar_works(index, story, SIMILAR_LIMIT, search.exclude_authors).await?;
+
+    Ok(Json(serp))
+}
+
+async fn similar_works(
+    index: Arc<IndexServer>,
+    story: Story,
+    limit: usize,
+    exclude_authors: bool,
+) -> Result<SimilarSerp, Error> {
+    let serp =
+        tokio::task::spawn_blocking(move || -> Result<SimilarSerp, ao3fti_common::Report> {
+            ao3fti_indexer::similar(
+                index,
+                &story,
+                SimilarQuery {
+                    limit,
+                    exclude_authors,
+                },
+            )
+        })
+        .await;
+
+    match serp {
+        Ok(serp) => Ok(serp?),
+        Err(err) => Err(Error::from_any(err)),
+    }
+}
+
+struct ReadChapter {
+    number: u64,
+    /// Each paragraph, along with where it is in the chapter.
+    paragraphs: Vec<(u64, String)>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "read.html")]
+struct ReadPage {
+    css: &'static str,
+    query: String,
+    story: Story,
+    chapters: Vec<ReadChapter>,
+}
+
+async fn read_html(
+    Extension(pool): Extension<Pool>,
+    Extension(index): Extension<Arc<IndexServer>>,
+    Path(story_id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let story = ao3fti_queries::get_story(pool, story_id).await?;
+
+    let passages =
+        tokio::task::spawn_blocking(move || -> Result<Vec<Passage>, ao3fti_common::Report> {
+            ao3fti_indexer::story_text(index, story_id)
+        })
+        .await;
+
+    let passages = match passages {
+        Ok(passages) => passages?,
+        Err(err) => return Err(Error::from_any(err)),
+    };
+
+    let mut chapters: Vec<ReadChapter> = Vec::new();
+
+    for Passage {
+        chapter,
+        paragraph,
+        paragraphs,
+    } in passages
+    {
+        if chapters.last().map(|last| last.number) != Some(chapter) {
+            chapters.push(ReadChapter {
+                number: chapter,
+                paragraphs: Vec::new(),
+            });
+        }
+
+        if let Some(last) = chapters.last_mut() {
+            last.paragraphs.extend(
+                paragraphs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, text)| (paragraph + i as u64, text)),
+            );
+        }
+    }
+
+    Ok(Html(
+        ReadPage {
+            css: STYLE,
+            query: String::new(),
+            story,
+            chapters,
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
+async fn work_api(
+    Extension(pool): Extension<Pool>,
+    Path(story_id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let story = ao3fti_queries::get_story(pool, story_id).await?;
+
+    Ok(Json(story))
+}
+
+async fn duplicates_api(
+    Extension(index): Extension<Arc<IndexServer>>,
+    Query(search): Query<DuplicatesQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let duplicates =
+        tokio::task::spawn_blocking(move || -> Result<Duplicates, ao3fti_common::Report> {
+            ao3fti_indexer::duplicates(index, search)
+        })
+        .await;
+
+    let duplicates = match duplicates {
+        Ok(duplicates) => duplicates?,
+        Err(err) => return Err(Error::from_any(err)),
+    };
+
+    Ok(Json(duplicates))
+}
+
+struct SeriesStory {
+    position: i32,
+    story_id: u64,
+    story: Option<Story>,
+}
+
+#[derive(askama::Template)]
+#[template(path = "series.html")]
+struct SeriesPage {
+    css: &'static str,
+    query: String,
+    name: String,
+    stories: Vec<SeriesStory>,
+}
+
+async fn series_html(
+    Extension(pool): Extension<Pool>,
+    Path(series_id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let name = ao3fti_queries::get_series_name(pool.clone(), series_id).await?;
+    let entries = ao3fti_queries::get_series_works(pool.clone(), series_id).await?;
+
+    let mut stories = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let story = if entry.indexed {
+            Some(ao3fti_queries::get_story(pool.clone(), entry.story_id).await?)
+        } else {
+            None
+        };
+
+        stories.push(SeriesStory {
+            position: entry.position,
+            story_id: entry.story_id,
+            story,
+        });
+    }
+
+    Ok(Html(
+        SeriesPage {
+            css: STYLE,
+            query: String::new(),
+            name,
+            stories,
+        }
+        .render()
+        .map_err(Error::from_any)?,
+    ))
+}
+
 pub struct Pagination {
     prev: Link,
     parts: Vec<Link>,
@@ -302,10 +1039,40 @@ impl axum::response::IntoResponse for Error {
         struct ResErr {
             code: u16,
             status: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            message: Option<String>,
         }
 
         let err = self.0;
 
+        // a search that doesn't parse, or asks for a ranking or similarity that can't be had, is
+        // the user's mistake, so tell them what it was
+        let invalid = err
+            .downcast_ref::<ParseError>()
+            .map(ToString::to_string)
+            .or_else(|| {
+                err.downcast_ref::<UnknownProfile>()
+                    .map(ToString::to_string)
+            })
+            .or_else(|| {
+                err.downcast_ref::<InvalidSimilarity>()
+                    .map(ToString::to_string)
+            });
+
+        if let Some(message) = invalid {
+            let status = StatusCode::BAD_REQUEST;
+
+            let body = Res {
+                error: ResErr {
+                    code: status.as_u16(),
+                    status: "invalid search",
+                    message: Some(message),
+                },
+            };
+
+            return (status, Json(body)).into_response();
+        }
+
         tracing::error!(error = ?err, "error handling request");
 
         let (status, message) = (StatusCode::INTERNAL_SERVER_ERROR, "internal server error");
@@ -314,6 +1081,7 @@ impl axum::response::IntoResponse for Error {
             error: ResErr {
                 code: status.as_u16(),
                 status: message,
+                message: None,
             },
         };
 
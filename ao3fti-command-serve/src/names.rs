@@ -0,0 +1,173 @@
+//! Prefix search over every tag and author name, for completing them in the search form.
+//!
+//! Each name is found by the start of any of its words, so `potter` finds `Harry Potter` as well
+//! as `Potter Family`. The index is a sorted list of those word starts, searched by bisecting it,
+//! and is built when the server starts.
+
+use ao3fti_common::models::TagKind;
+use ao3fti_queries::Pool;
+
+/// What a name is, as the field it's searched with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum NameKind {
+    #[serde(rename = "author", alias = "creator")]
+    Author,
+    #[serde(rename = "fandom", alias = "origin")]
+    Fandom,
+    #[serde(rename = "ship", alias = "relationship", alias = "pairing")]
+    Ship,
+    #[serde(rename = "character", alias = "char")]
+    Character,
+    #[serde(rename = "tag", alias = "freeform", alias = "general")]
+    Tag,
+    #[serde(rename = "warning")]
+    Warning,
+}
+
+impl NameKind {
+    /// The field a name of this kind is searched in.
+    fn field(self) -> &'static str {
+        match self {
+            NameKind::Author => "author",
+            NameKind::Fandom => "fandom",
+            NameKind::Ship => "ship",
+            NameKind::Character => "char",
+            NameKind::Tag => "tag",
+            NameKind::Warning => "warning",
+        }
+    }
+}
+
+impl From<TagKind> for NameKind {
+    fn from(kind: TagKind) -> Self {
+        match kind {
+            TagKind::Origin => NameKind::Fandom,
+            TagKind::Warning => NameKind::Warning,
+            TagKind::Pairing => NameKind::Ship,
+            TagKind::Character => NameKind::Character,
+            TagKind::General => NameKind::Tag,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
+pub struct Name {
+    pub kind: NameKind,
+    pub name: String,
+    /// How many stories use it.
+    pub count: i64,
+    /// The name as a search clause, ie `char:"Harry Potter"`.
+    pub query: String,
+}
+
+impl Name {
+    pub fn new(kind: NameKind, name: String, count: i64) -> Name {
+        let query = format!("{}:\"{}\"", kind.field(), name.replace('"', ""));
+
+        Name {
+            kind,
+            name,
+            count,
+            query,
+        }
+    }
+}
+
+pub struct Names {
+    names: Vec<Name>,
+    /// Every name, lowercased.
+    lowercase: Vec<String>,
+    /// Where every word of every name starts, as which name it's in and the byte it's at, sorted
+    /// by the rest of the name from there on.
+    keys: Vec<(usize, usize)>,
+}
+
+/// The rest of a lowercased name from the start of one of its words.
+fn key(lowercase: &[String], (i, at): (usize, usize)) -> &str {
+    &lowercase[i][at..]
+}
+
+impl Names {
+    pub fn new(names: Vec<Name>) -> Names {
+        let lowercase = names
+            .iter()
+            .map(|name| name.name.to_lowercase())
+            .collect::<Vec<_>>();
+        let mut keys = Vec::new();
+
+        for (i, name) in lowercase.iter().enumerate() {
+            let mut word_start = true;
+            for (at, c) in name.char_indices() {
+                if word_start && c.is_alphanumeric() {
+                    keys.push((i, at));
+                }
+
+                word_start = !c.is_alphanumeric();
+            }
+        }
+
+        keys.sort_unstable_by(|a, b| {
+            key(&lowercase, *a)
+                .cmp(key(&lowercase, *b))
+                .then_with(|| a.cmp(b))
+        });
+
+        Names {
+            names,
+            lowercase,
+            keys,
+        }
+    }
+
+    #[tracing::instrument(skip(pool), err)]
+    pub async fn load(pool: Pool) -> Result<Names, ao3fti_common::Report> {
+        let mut names = Vec::new();
+
+        for (kind, name, count) in ao3fti_queries::get_tag_counts(pool.clone()).await? {
+            names.push(Name::new(kind.into(), name, count));
+        }
+
+        for (name, count) in ao3fti_queries::get_author_counts(pool).await? {
+            names.push(Name::new(NameKind::Author, name, count));
+        }
+
+        tracing::info!(names = names.len(), "loaded names to complete");
+
+        Ok(Names::new(names))
+    }
+
+    /// Finds the most used names with a word starting with `prefix`, ignoring case.
+    pub fn complete(&self, kind: Option<NameKind>, prefix: &str, limit: usize) -> Vec<&Name> {
+        let prefix = prefix.trim().to_lowercase();
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self
+            .keys
+            .partition_point(|at| key(&self.lowercase, *at) < prefix.as_str());
+
+        let mut found = self.keys[start..]
+            .iter()
+            .take_while(|at| key(&self.lowercase, **at).starts_with(&prefix))
+            .map(|(i, _)| *i)
+            .filter(|i| kind.is_none_or(|kind| self.names[*i].kind == kind))
+            .collect::<Vec<_>>();
+
+        // a name is found once for every word that starts with the prefix
+        found.sort_unstable();
+        found.dedup();
+
+        let mut found = found
+            .into_iter()
+            .map(|i| &self.names[i])
+            .collect::<Vec<_>>();
+
+        found.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        found.truncate(limit);
+
+        found
+    }
+}
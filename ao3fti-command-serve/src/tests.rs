@@ -0,0 +1,84 @@
+//! Completing tag and author names.
+
+use crate::{
+    names::{Name, NameKind, Names},
+    SuggestQuery,
+};
+
+fn names() -> Names {
+    Names::new(vec![
+        Name::new(NameKind::Character, "Harry Potter".to_string(), 120),
+        Name::new(NameKind::Character, "Hermione Granger".to_string(), 80),
+        Name::new(NameKind::Character, "Lily Evans Potter".to_string(), 15),
+        Name::new(NameKind::Ship, "Draco Malfoy/Harry Potter".to_string(), 60),
+        Name::new(
+            NameKind::Fandom,
+            "Harry Potter - J. K. Rowling".to_string(),
+            300,
+        ),
+        Name::new(NameKind::Author, "harriet".to_string(), 3),
+        Name::new(NameKind::Tag, "Fluff".to_string(), 40),
+    ])
+}
+
+fn complete(names: &Names, kind: Option<NameKind>, prefix: &str) -> Vec<String> {
+    names
+        .complete(kind, prefix, 10)
+        .into_iter()
+        .map(|name| name.name.clone())
+        .collect()
+}
+
+#[test]
+fn names_are_completed_most_used_first() {
+    let names = names();
+
+    assert_eq!(
+        complete(&names, None, "har"),
+        vec![
+            "Harry Potter - J. K. Rowling",
+            "Harry Potter",
+            "Draco Malfoy/Harry Potter",
+            "harriet"
+        ]
+    );
+    assert_eq!(
+        complete(&names, Some(NameKind::Character), "HAR"),
+        vec!["Harry Potter"]
+    );
+    assert_eq!(
+        complete(&names, Some(NameKind::Author), "har"),
+        vec!["harriet"]
+    );
+    assert_eq!(complete(&names, None, "  "), Vec::<String>::new());
+    assert_eq!(complete(&names, None, "zzz"), Vec::<String>::new());
+
+    assert_eq!(names.complete(None, "h", 2).len(), 2);
+}
+
+#[test]
+fn names_are_completed_from_any_word() {
+    let names = names();
+
+    assert_eq!(
+        complete(&names, Some(NameKind::Character), "potter"),
+        vec!["Harry Potter", "Lily Evans Potter"]
+    );
+    assert_eq!(
+        complete(&names, Some(NameKind::Ship), "harry potter"),
+        vec!["Draco Malfoy/Harry Potter"]
+    );
+    assert_eq!(complete(&names, None, "otter"), Vec::<String>::new());
+}
+
+#[test]
+fn names_are_search_clauses() {
+    let names = names();
+
+    let found = names.complete(Some(NameKind::Ship), "draco", 10);
+    assert_eq!(found[0].query, "ship:\"Draco Malfoy/Harry Potter\"");
+
+    let suggest = serde_urlencoded::from_str::<SuggestQuery>("kind=relationship&q=dra").unwrap();
+    assert_eq!(suggest.kind, Some(NameKind::Ship));
+    assert!(serde_urlencoded::from_str::<SuggestQuery>("kind=shipping&q=dra").is_err());
+}
@@ -0,0 +1,139 @@
+//! An on-disk cache of fetched pages.
+//!
+//! Bodies are stored under `bodies/` named by their SHA-256, so a page that hasn't changed
+//! between crawls is only stored once. Every url gets an entry under `urls/`, named by the hash
+//! of the url, pointing at its latest body along with the validators needed to make a
+//! conditional request for it.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{Context as _, Uri};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The hash of the body, and the name of its file.
+    pub body: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Cache {
+        Cache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn entry_path(&self, url: &Uri) -> PathBuf {
+        self.dir
+            .join("urls")
+            .join(format!("{}.json", hash(url.to_string().as_bytes())))
+    }
+
+    fn body_path(&self, body: &str) -> PathBuf {
+        self.dir.join("bodies").join(body)
+    }
+
+    /// Gets the entry and body cached for a url, a missing or unreadable entry is a miss.
+    #[tracing::instrument(skip(self, url), fields(url = %url.to_string()))]
+    pub async fn get(&self, url: &Uri) -> Option<(Entry, String)> {
+        let entry = match tokio::fs::read(self.entry_path(url)).await {
+            Ok(entry) => entry,
+            Err(_) => return None,
+        };
+
+        let entry = match serde_json::from_slice::<Entry>(&entry) {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!(error = %err, "unable to parse cache entry, ignoring it");
+
+                return None;
+            }
+        };
+
+        match tokio::fs::read_to_string(self.body_path(&entry.body)).await {
+            Ok(body) => Some((entry, body)),
+            Err(err) => {
+                tracing::warn!(error = %err, "cache entry is missing its body, ignoring it");
+
+                None
+            }
+        }
+    }
+
+    /// Stores a fetched body, replacing whatever was cached for the url before.
+    #[tracing::instrument(skip(self, url, etag, last_modified, body), fields(url = %url.to_string()), err)]
+    pub async fn put(
+        &self,
+        url: &Uri,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        body: &str,
+    ) -> Result<(), crate::Report> {
+        let entry = Entry {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            body: hash(body.as_bytes()),
+        };
+
+        let body_path = self.body_path(&entry.body);
+        if tokio::fs::metadata(&body_path).await.is_err() {
+            write(&body_path, body.as_bytes()).await?;
+        }
+
+        write(&self.entry_path(url), &serde_json::to_vec_pretty(&entry)?).await?;
+
+        Ok(())
+    }
+}
+
+fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Counts temporary files, to keep the names of ones written at the same time apart.
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes to a temporary file first so a crawl that's killed mid write never leaves a torn
+/// entry behind.
+///
+/// Works fetched at once can have the same body, so every write gets a temporary file of its
+/// own and whichever rename lands last wins, the contents are the same either way.
+async fn write(path: &Path, contents: &[u8]) -> Result<(), crate::Report> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("unable to create cache directory `{}`", parent.display()))?;
+    }
+
+    let temp = path.with_file_name(format!(
+        "{}.{}.{}.tmp",
+        path.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    tokio::fs::write(&temp, contents)
+        .await
+        .with_context(|| format!("unable to write cache file `{}`", temp.display()))?;
+    tokio::fs::rename(&temp, path)
+        .await
+        .with_context(|| format!("unable to write cache file `{}`", path.display()))?;
+
+    Ok(())
+}
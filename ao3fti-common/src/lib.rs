@@ -1,6 +1,8 @@
+pub mod cache;
 pub mod models;
 pub mod timer;
 pub mod utils;
+pub mod warc;
 
 use std::path::{Path, PathBuf};
 
@@ -19,6 +21,42 @@ pub struct Conf {
     /// Path to store indexed story data
     #[serde(default = "default_index")]
     pub index: PathBuf,
+    /// Scheme and host of the archive to scrape, without a trailing slash
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    /// Minimum number of seconds to wait between requests
+    #[serde(default = "default_delay_min")]
+    pub delay_min: u64,
+    /// Maximum number of seconds to wait between requests
+    #[serde(default = "default_delay_max")]
+    pub delay_max: u64,
+    /// Number of seconds before a request is given up on
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Number of times a rate limited or timed out request is retried
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Number of works fetched at once, their requests still share the delays between requests
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Path to keep fetched pages in, they're revalidated instead of fetched again
+    #[serde(default)]
+    pub cache: Option<PathBuf>,
+    /// Only read pages from the cache, never from the network
+    #[serde(default)]
+    pub cache_only: bool,
+    /// Path to write WARC files of every request and response to
+    #[serde(default)]
+    pub warc: Option<PathBuf>,
+    /// Size in bytes after which a new WARC file is started
+    #[serde(default = "default_warc_size")]
+    pub warc_size: u64,
+    /// Path to a JSON file of named ranking profiles, added to the built in ones
+    #[serde(default)]
+    pub ranking_profiles: Option<PathBuf>,
+    /// Ranking profile searches use when they don't pick one
+    #[serde(default = "default_ranking_profile")]
+    pub ranking_profile: String,
 }
 
 fn default_database() -> String {
@@ -28,3 +66,35 @@ fn default_database() -> String {
 fn default_index() -> PathBuf {
     Path::new("./index").to_path_buf()
 }
+
+fn default_base_url() -> String {
+    "https://archiveofourown.org".to_string()
+}
+
+fn default_delay_min() -> u64 {
+    3
+}
+
+fn default_delay_max() -> u64 {
+    7
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_warc_size() -> u64 {
+    1_000_000_000
+}
+
+fn default_ranking_profile() -> String {
+    "relevance".to_string()
+}
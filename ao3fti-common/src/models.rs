@@ -1,13 +1,17 @@
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
 pub struct Story {
     pub id: usize,
     pub name: String,
     pub summary: String,
-    pub authors: Vec<Entity>,
+    pub authors: Vec<Author>,
     pub origins: Vec<Entity>,
     pub warnings: Vec<Entity>,
     pub pairings: Vec<Entity>,
     pub characters: Vec<Entity>,
     pub generals: Vec<Entity>,
+    pub series: Vec<SeriesPart>,
+    pub relations: Vec<StoryRelation>,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -27,7 +31,123 @@ pub enum Rating {
     Unknown,
 }
 
+impl std::str::FromStr for Rating {
+    type Err = crate::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "explicit" => Ok(Rating::Explicit),
+            "mature" => Ok(Rating::Mature),
+            "teen" => Ok(Rating::Teen),
+            "general" => Ok(Rating::General),
+            "not-rated" => Ok(Rating::NotRated),
+            _ => Err(crate::err!(
+                "unknown rating `{}`, expected `explicit`, `mature`, `teen`, `general` or `not-rated`",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
 pub struct Entity {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
+pub struct Author {
+    pub user_id: i32,
+    pub user: String,
+    pub pseud: String,
+}
+
+impl Author {
+    /// The name as AO3 shows it, `pseud (user)` unless the pseud is the user's default.
+    pub fn name(&self) -> String {
+        if self.pseud == self.user {
+            self.user.clone()
+        } else {
+            format!("{} ({})", self.pseud, self.user)
+        }
+    }
+
+    /// Orphaned works keep the original pseud but are moved to AO3's shared orphan account.
+    pub fn is_orphaned(&self) -> bool {
+        self.user == "orphan_account"
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
+pub struct SeriesPart {
+    pub id: i32,
+    pub name: String,
+    pub position: i32,
+}
+
+/// A relation between two stories, from the point of view of the story it's attached to.
+///
+/// When `inverse` is set the other story is the one being inspired by, or translating, this one.
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
+pub struct StoryRelation {
+    pub id: u64,
+    pub name: Option<String>,
+    pub relation: WorkRelation,
+    pub inverse: bool,
+}
+
+impl StoryRelation {
+    pub fn label(&self) -> &'static str {
+        match (self.relation, self.inverse) {
+            (WorkRelation::InspiredBy, false) => "Inspired by",
+            (WorkRelation::InspiredBy, true) => "Inspired",
+            (WorkRelation::TranslationOf, false) => "Translation of",
+            (WorkRelation::TranslationOf, true) => "Translated into",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(serde::Serialize)]
+pub struct Tag {
+    pub id: i32,
+    pub kind: TagKind,
     pub name: String,
 }
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum TagKind {
+    #[serde(rename = "origin")]
+    Origin,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "pairing")]
+    Pairing,
+    #[serde(rename = "character")]
+    Character,
+    #[serde(rename = "general")]
+    General,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum TagRelation {
+    #[serde(rename = "parent")]
+    Parent,
+    #[serde(rename = "meta")]
+    Meta,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum WorkRelation {
+    #[serde(rename = "inspired-by")]
+    InspiredBy,
+    #[serde(rename = "translation-of")]
+    TranslationOf,
+}
@@ -1,11 +1,25 @@
+use std::{sync::Mutex, time::Duration};
+
+use http::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER},
+    HeaderMap, StatusCode,
+};
 use isahc::{
     config::{Configurable as _, RedirectPolicy},
-    AsyncReadResponseExt as _, HttpClient, Request,
+    AsyncReadResponseExt as _, HttpClient, Request, ResponseExt as _,
 };
+use once_cell::sync::Lazy;
 use rand::Rng;
-use tracing::{Instrument, Span};
+use tokio::time::Instant;
 
-use crate::Uri;
+use crate::{
+    bail,
+    cache::Cache,
+    warc::{self, Exchange},
+    Conf, Uri,
+};
+
+const COOKIE: &str = "view_adult=true";
 
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
 const USER_AGENT: &str = concat!(
@@ -54,45 +68,207 @@ const USER_AGENT: &str = concat!(
     " (txuritan@protonmail.com)"
 );
 
-#[tracing::instrument(err, skip(url), fields(url = %url.to_string()))]
-pub async fn req(url: &Uri) -> Result<String, crate::Report> {
+/// Fetches a page, retrying when the archive rate limits us or the request times out.
+///
+/// With `conf.cache` set, pages are kept on disk and refetched with a conditional request,
+/// with `conf.cache_only` they're only ever read from it.
+#[tracing::instrument(err, skip(conf, url), fields(url = %url.to_string()))]
+pub async fn req(conf: &Conf, url: &Uri) -> Result<String, crate::Report> {
+    let cache = conf.cache.as_deref().map(Cache::new);
+    let cached = match &cache {
+        Some(cache) => cache.get(url).await,
+        None => None,
+    };
+
+    if conf.cache_only {
+        return match cached {
+            Some((_, body)) => {
+                tracing::info!("read from cache");
+
+                Ok(body)
+            }
+            None => bail!("`{}` is not cached and the cache is read only", url),
+        };
+    }
+
     tracing::info!("fetching");
 
     let client = HttpClient::builder()
         .default_header("User-Agent", USER_AGENT)
-        .default_header("Cookie", "view_adult=true")
+        .default_header("Cookie", COOKIE)
+        .timeout(Duration::from_secs(conf.timeout))
         .build()?;
 
-    let req = Request::builder()
-        .redirect_policy(RedirectPolicy::Follow)
-        .uri(url)
-        .body(())?;
+    let mut retries = 0;
 
-    let mut res = client.send_async(req).await?;
+    loop {
+        let mut req = Request::builder()
+            .redirect_policy(RedirectPolicy::Follow)
+            .uri(url);
 
-    let html = res.text().await?;
+        if let Some((entry, _)) = &cached {
+            if let Some(etag) = &entry.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
 
-    Ok(html)
-}
+        let req = req.body(())?;
+
+        let request = request_head(url, req.headers());
+        let started = Instant::now();
 
-#[tracing::instrument(err)]
-pub async fn sleep() -> Result<(), crate::Report> {
-    tokio::task::spawn_blocking({
-        let span = Span::current();
+        let wait = match client.send_async(req).await {
+            Ok(mut res) => {
+                let body = res.bytes().await?;
 
-        move || {
-            let _ = span.enter();
+                warc::record_exchange(
+                    conf,
+                    Exchange {
+                        url,
+                        request,
+                        status: res.status(),
+                        headers: res.headers(),
+                        body: &body,
+                        cached: cached.as_ref().map(|(_, body)| body.as_bytes()),
+                        remote_addr: res.remote_addr(),
+                        retry: retries,
+                        duration: started.elapsed(),
+                    },
+                )
+                .await?;
 
-            let length = rand::thread_rng().gen_range(3..8);
+                match res.status() {
+                    StatusCode::TOO_MANY_REQUESTS => {
+                        let retry_after = res
+                            .headers()
+                            .get(RETRY_AFTER)
+                            .and_then(|value| value.to_str().ok())
+                            .and_then(|value| value.parse::<u64>().ok())
+                            .unwrap_or(conf.delay_max);
 
-            tracing::info!("[util] Sleeping for {} seconds", length);
+                        tracing::warn!(retry_after = retry_after, "rate limited");
 
-            std::thread::sleep(std::time::Duration::from_secs(length));
+                        retry_after
+                    }
+                    StatusCode::NOT_MODIFIED => {
+                        if let Some((_, body)) = cached {
+                            tracing::info!("not modified, using cache");
+
+                            return Ok(body);
+                        }
+
+                        bail!("`{}` was not modified but isn't cached", url);
+                    }
+                    status => {
+                        let html = String::from_utf8_lossy(&body).into_owned();
+
+                        if let Some(cache) = &cache {
+                            if status.is_success() {
+                                let header = |name| {
+                                    res.headers()
+                                        .get(name)
+                                        .and_then(|value| value.to_str().ok())
+                                        .map(str::to_string)
+                                };
+
+                                cache
+                                    .put(url, header(ETAG), header(LAST_MODIFIED), &html)
+                                    .await?;
+                            }
+                        }
+
+                        return Ok(html);
+                    }
+                }
+            }
+            Err(err) if err.is_timeout() => {
+                tracing::warn!(timeout = conf.timeout, "timed out");
+
+                conf.delay_max
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if retries >= conf.retries {
+            bail!("giving up on `{}` after {} retries", url, retries);
         }
-    })
-    .instrument(Span::current())
-    .await
-    .expect("Thread pool closed");
+
+        retries += 1;
+
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+    }
+}
+
+/// The request line and headers as they're sent, including the client's default headers.
+fn request_head(url: &Uri, headers: &HeaderMap) -> String {
+    let mut head = format!(
+        "GET {} HTTP/1.1\r\n",
+        url.path_and_query().map_or("/", |path| path.as_str())
+    );
+
+    if let Some(host) = url.authority() {
+        head.push_str(&format!("Host: {}\r\n", host));
+    }
+
+    head.push_str(&format!(
+        "User-Agent: {}\r\nCookie: {}\r\n",
+        USER_AGENT, COOKIE
+    ));
+
+    for (name, value) in headers {
+        head.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+
+    head.push_str("\r\n");
+
+    head
+}
+
+/// Waits for a turn to make a request.
+///
+/// Turns are shared by every task, each one is a random delay after the last, so fetching
+/// more at once never means hitting the archive any harder.
+#[tracing::instrument(skip(conf), err)]
+pub async fn sleep(conf: &Conf) -> Result<(), crate::Report> {
+    static LAST_TURN: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+    // nothing is requested, so there's no one to be polite to
+    if conf.cache_only {
+        return Ok(());
+    }
+
+    let min = conf.delay_min;
+    let max = conf.delay_max.max(min);
+
+    let length = Duration::from_secs(rand::thread_rng().gen_range(min..=max));
+
+    let turn = {
+        let mut last_turn = LAST_TURN.lock().expect("Turn lock poisoned");
+        let now = Instant::now();
+
+        let turn = match *last_turn {
+            Some(last_turn) => (last_turn + length).max(now),
+            None => now,
+        };
+
+        *last_turn = Some(turn);
+
+        turn
+    };
+
+    tracing::info!(
+        "[util] Sleeping for {} seconds",
+        turn.saturating_duration_since(Instant::now()).as_secs()
+    );
+
+    tokio::time::sleep_until(turn).await;
 
     Ok(())
 }
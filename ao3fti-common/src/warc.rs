@@ -0,0 +1,467 @@
+//! WARC files of every request made and response received, for archival and to replay a crawl
+//! later.
+//!
+//! Each exchange is a `request`, `response`, and `metadata` record, written together so a
+//! file never ends halfway through one. A page the cache revalidated gets a `revisit` record
+//! in place of the `response`, holding the cached body so the file replays on its own. Files
+//! are rotated once they grow past `conf.warc_size`. See <https://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/>.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead as _, BufReader, Read as _, Seek as _, SeekFrom, Write as _},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use http::{HeaderMap, StatusCode};
+use once_cell::sync::Lazy;
+use sha2::{Digest as _, Sha256};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{bail, err, Conf, Context as _, Uri};
+
+/// A request and the response it got back.
+pub struct Exchange<'e> {
+    pub url: &'e Uri,
+    /// The request line and headers as they were sent.
+    pub request: String,
+    pub status: StatusCode,
+    pub headers: &'e HeaderMap,
+    /// The decoded body.
+    pub body: &'e [u8],
+    /// The cached body a `304 Not Modified` stands in for.
+    pub cached: Option<&'e [u8]>,
+    pub remote_addr: Option<SocketAddr>,
+    /// Which try this was, starting at zero.
+    pub retry: u32,
+    pub duration: Duration,
+}
+
+struct Writer {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    serial: usize,
+}
+
+impl Writer {
+    fn open(dir: &Path, serial: usize) -> Result<Writer, crate::Report> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("unable to create warc directory `{}`", dir.display()))?;
+
+        let now = OffsetDateTime::now_utc();
+        let path = dir.join(format!(
+            "ao3fti-{:04}{:02}{:02}{:02}{:02}{:02}-{:05}.warc",
+            now.year(),
+            u8::from(now.month()),
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second(),
+            serial
+        ));
+
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("unable to create warc file `{}`", path.display()))?;
+
+        let mut writer = Writer {
+            dir: dir.to_path_buf(),
+            file,
+            size: 0,
+            serial,
+        };
+
+        let info = format!(
+            "software: ao3fti/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let record = record(
+            "warcinfo",
+            &record_id(),
+            &OffsetDateTime::now_utc().format(&Rfc3339)?,
+            &[
+                ("WARC-Filename", name),
+                ("Content-Type", "application/warc-fields".to_string()),
+            ],
+            info.as_bytes(),
+        );
+        writer.write(&record)?;
+
+        Ok(writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), crate::Report> {
+        self.file
+            .write_all(bytes)
+            .context("unable to write to warc file")?;
+        self.size += bytes.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// The profile of a revisit record for a page the server said hadn't changed.
+static NOT_MODIFIED_PROFILE: &str = "http://netpreserve.org/warc/1.1/revisit/server-not-modified";
+
+/// Writes an exchange to the current WARC file, if `conf.warc` is set.
+///
+/// The records are built here, and written on a blocking thread as the file is shared by every
+/// request.
+pub async fn record_exchange(conf: &Conf, exchange: Exchange<'_>) -> Result<(), crate::Report> {
+    let dir = match &conf.warc {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+
+    let date = OffsetDateTime::now_utc().format(&Rfc3339)?;
+    let url = exchange.url.to_string();
+
+    let request_id = record_id();
+    let request = record(
+        "request",
+        &request_id,
+        &date,
+        &[
+            ("WARC-Target-URI", url.clone()),
+            (
+                "Content-Type",
+                "application/http;msgtype=request".to_string(),
+            ),
+        ],
+        exchange.request.as_bytes(),
+    );
+
+    let mut block = format!(
+        "HTTP/1.1 {} {}\r\n",
+        exchange.status.as_u16(),
+        exchange.status.canonical_reason().unwrap_or_default()
+    );
+    for (name, value) in exchange.headers {
+        // the body is stored decoded, so these no longer describe it
+        if name == http::header::CONTENT_ENCODING
+            || name == http::header::TRANSFER_ENCODING
+            || name == http::header::CONTENT_LENGTH
+        {
+            continue;
+        }
+
+        block.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    // a revisit carries the body that was already had, so the page can be replayed from it
+    let (kind, body) = match exchange.cached {
+        Some(cached) if exchange.status == StatusCode::NOT_MODIFIED => ("revisit", cached),
+        _ => ("response", exchange.body),
+    };
+
+    block.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    let mut block = block.into_bytes();
+    block.extend_from_slice(body);
+
+    let response_id = record_id();
+    let mut response_headers = vec![
+        ("WARC-Target-URI", url.clone()),
+        ("WARC-Concurrent-To", request_id),
+        (
+            "Content-Type",
+            "application/http;msgtype=response".to_string(),
+        ),
+    ];
+    if let Some(addr) = exchange.remote_addr {
+        response_headers.push(("WARC-IP-Address", addr.ip().to_string()));
+    }
+    if kind == "revisit" {
+        response_headers.push(("WARC-Profile", NOT_MODIFIED_PROFILE.to_string()));
+    }
+    let response = record(kind, &response_id, &date, &response_headers, &block);
+
+    let fields = format!(
+        "retry: {}\r\nduration-ms: {}\r\n",
+        exchange.retry,
+        exchange.duration.as_millis()
+    );
+    let metadata = record(
+        "metadata",
+        &record_id(),
+        &date,
+        &[
+            ("WARC-Target-URI", url),
+            ("WARC-Concurrent-To", response_id),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ],
+        fields.as_bytes(),
+    );
+
+    let dir = dir.clone();
+    let max_size = conf.warc_size;
+
+    tokio::task::spawn_blocking(move || {
+        write_records(&dir, max_size, &[request, response, metadata])
+    })
+    .await?
+}
+
+/// Appends records to the current WARC file, rotating it first if they'd grow it past
+/// `max_size`.
+fn write_records(dir: &Path, max_size: u64, records: &[Vec<u8>]) -> Result<(), crate::Report> {
+    static WRITER: Lazy<Mutex<Option<Writer>>> = Lazy::new(|| Mutex::new(None));
+
+    let mut writer = WRITER.lock().expect("WARC lock poisoned");
+
+    let size = records
+        .iter()
+        .map(|record| record.len() as u64)
+        .sum::<u64>();
+    let rotate = match &*writer {
+        Some(writer) => writer.dir != dir || writer.size + size > max_size,
+        None => true,
+    };
+
+    if rotate {
+        let serial = writer.as_ref().map_or(0, |writer| writer.serial + 1);
+
+        *writer = Some(Writer::open(dir, serial)?);
+    }
+
+    let writer = writer.as_mut().expect("WARC writer was just opened");
+
+    for record in records {
+        writer.write(record)?;
+    }
+
+    Ok(())
+}
+
+fn record(kind: &str, id: &str, date: &str, headers: &[(&str, String)], block: &[u8]) -> Vec<u8> {
+    let mut head = format!(
+        "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+        kind, id, date
+    );
+
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    head.push_str(&format!(
+        "WARC-Block-Digest: sha256:{:x}\r\nContent-Length: {}\r\n\r\n",
+        Sha256::digest(block),
+        block.len()
+    ));
+
+    let mut bytes = head.into_bytes();
+    bytes.extend_from_slice(block);
+    bytes.extend_from_slice(b"\r\n\r\n");
+
+    bytes
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", uuid::Uuid::new_v4())
+}
+
+/// A record read back out of a WARC file.
+pub struct Record {
+    /// Where in its file the record starts.
+    pub offset: u64,
+    pub headers: Vec<(String, String)>,
+    pub block: Vec<u8>,
+}
+
+impl Record {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The body of a response record if it was successful, or of a revisit for a page that
+    /// hadn't changed.
+    pub fn body(&self) -> Option<String> {
+        let start = self.body_start()?;
+
+        Some(String::from_utf8_lossy(&self.block[start..]).into_owned())
+    }
+
+    /// Where the body of a successful response or a revisit starts in its block.
+    fn body_start(&self) -> Option<usize> {
+        let expected = match self.header("WARC-Type") {
+            Some("response") => 200,
+            Some("revisit") => 304,
+            _ => return None,
+        };
+
+        let split = self
+            .block
+            .windows(4)
+            .position(|bytes| bytes == b"\r\n\r\n")?;
+
+        let head = String::from_utf8_lossy(&self.block[..split]);
+        let status = head
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok());
+
+        if status != Some(expected) {
+            return None;
+        }
+
+        Some(split + 4)
+    }
+}
+
+/// The records of a WARC file, read one at a time so only a single block is ever in memory.
+pub struct Records {
+    path: PathBuf,
+    reader: BufReader<File>,
+    offset: u64,
+}
+
+impl Records {
+    fn open(path: &Path, offset: u64) -> Result<Records, crate::Report> {
+        let mut file = File::open(path)
+            .with_context(|| format!("unable to open warc file `{}`", path.display()))?;
+
+        if offset != 0 {
+            file.seek(SeekFrom::Start(offset))
+                .with_context(|| format!("unable to seek in warc file `{}`", path.display()))?;
+        }
+
+        Ok(Records {
+            path: path.to_path_buf(),
+            reader: BufReader::new(file),
+            offset,
+        })
+    }
+
+    fn read_line(&mut self, line: &mut String) -> Result<usize, crate::Report> {
+        line.clear();
+
+        let read = self
+            .reader
+            .read_line(line)
+            .with_context(|| format!("unable to read warc file `{}`", self.path.display()))?;
+        self.offset += read as u64;
+
+        Ok(read)
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, crate::Report> {
+        let mut line = String::new();
+
+        // records are separated by blank lines
+        let offset = loop {
+            let offset = self.offset;
+
+            if self.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            if !line.trim().is_empty() {
+                break offset;
+            }
+        };
+
+        if !line.starts_with("WARC/") {
+            bail!(
+                "expected a warc record in `{}`, found `{}`",
+                self.path.display(),
+                line.trim()
+            );
+        }
+
+        let mut headers = Vec::new();
+
+        loop {
+            if self.read_line(&mut line)? == 0 {
+                bail!("warc file `{}` ends mid record", self.path.display());
+            }
+
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| err!("invalid warc header `{}`", header))?;
+
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let length = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+            .and_then(|(_, value)| value.parse::<usize>().ok())
+            .ok_or_else(|| err!("warc record in `{}` has no length", self.path.display()))?;
+
+        let mut block = vec![0; length];
+        self.reader
+            .read_exact(&mut block)
+            .with_context(|| format!("warc file `{}` ends mid record", self.path.display()))?;
+        self.offset += length as u64;
+
+        Ok(Some(Record {
+            offset,
+            headers,
+            block,
+        }))
+    }
+}
+
+impl Iterator for Records {
+    type Item = Result<Record, crate::Report>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Reads the records of a WARC file in order.
+pub fn read(path: &Path) -> Result<Records, crate::Report> {
+    Records::open(path, 0)
+}
+
+/// Reads the record that starts at `offset`, as found by [`read`] or [`responses`].
+pub fn read_at(path: &Path, offset: u64) -> Result<Record, crate::Report> {
+    Records::open(path, offset)?
+        .read_record()?
+        .ok_or_else(|| err!("warc file `{}` has no record at {}", path.display(), offset))
+}
+
+/// Finds where the last successful response or revisit for every url in a WARC file starts.
+///
+/// Only the offsets are kept, the bodies are read again with [`read_at`] when they're needed.
+pub fn responses(path: &Path) -> Result<HashMap<String, u64>, crate::Report> {
+    let mut responses = HashMap::new();
+
+    for record in read(path)? {
+        let record = record?;
+
+        if record.body_start().is_none() {
+            continue;
+        }
+
+        if let Some(url) = record.header("WARC-Target-URI") {
+            responses.insert(url.to_string(), record.offset);
+        }
+    }
+
+    Ok(responses)
+}
@@ -0,0 +1,396 @@
+//! The analyzers a story's text is indexed with, one for each language it could be in.
+//!
+//! A story's title, summary and body only go in the fields of its own language's analyzer, and
+//! searches are analyzed by every analyzer, each searching its own fields. That way `running`
+//! finds `runs` in English works without every other language being stemmed as English.
+//!
+//! Languages tantivy has a stemmer for are stemmed and have their most common words left out,
+//! Chinese, Japanese and Korean are split into overlapping pairs of characters as there are no
+//! spaces between their words, and everything else is only lowercased.
+
+use tantivy::{
+    tokenizer::{
+        BoxTokenStream, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+        StopWordFilter, TextAnalyzer, Token, TokenStream, Tokenizer,
+    },
+    Index,
+};
+
+/// How many analyzers there are, and so how many fields each analyzed field is split into.
+pub const ANALYZERS: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Analyzer {
+    /// For languages there's nothing better for, and works whose language isn't known.
+    Default,
+    /// Chinese, Japanese and Korean.
+    Cjk,
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl Analyzer {
+    /// Every analyzer, in the order their fields are in.
+    pub const ALL: [Analyzer; ANALYZERS] = [
+        Analyzer::Default,
+        Analyzer::Cjk,
+        Analyzer::Arabic,
+        Analyzer::Danish,
+        Analyzer::Dutch,
+        Analyzer::English,
+        Analyzer::Finnish,
+        Analyzer::French,
+        Analyzer::German,
+        Analyzer::Greek,
+        Analyzer::Hungarian,
+        Analyzer::Italian,
+        Analyzer::Norwegian,
+        Analyzer::Portuguese,
+        Analyzer::Romanian,
+        Analyzer::Russian,
+        Analyzer::Spanish,
+        Analyzer::Swedish,
+        Analyzer::Tamil,
+        Analyzer::Turkish,
+    ];
+
+    /// Picks the analyzer for a language code, as AO3 gives them, ie `en` or `pt-BR`.
+    pub fn for_language(code: &str) -> Analyzer {
+        let primary = code.split('-').next().unwrap_or_default().to_lowercase();
+
+        match primary.as_str() {
+            "zh" | "ja" | "ko" | "yue" | "wuu" | "hak" | "nan" => Analyzer::Cjk,
+            "ar" => Analyzer::Arabic,
+            "da" => Analyzer::Danish,
+            "nl" => Analyzer::Dutch,
+            "en" => Analyzer::English,
+            "fi" => Analyzer::Finnish,
+            "fr" => Analyzer::French,
+            "de" => Analyzer::German,
+            "el" => Analyzer::Greek,
+            "hu" => Analyzer::Hungarian,
+            "it" => Analyzer::Italian,
+            "no" | "nb" | "nn" => Analyzer::Norwegian,
+            "pt" => Analyzer::Portuguese,
+            "ro" => Analyzer::Romanian,
+            "ru" => Analyzer::Russian,
+            "es" => Analyzer::Spanish,
+            "sv" => Analyzer::Swedish,
+            "ta" => Analyzer::Tamil,
+            "tr" => Analyzer::Turkish,
+            _ => Analyzer::Default,
+        }
+    }
+
+    /// What its fields and tokenizer are suffixed with.
+    pub fn name(self) -> &'static str {
+        match self {
+            Analyzer::Default => "default",
+            Analyzer::Cjk => "cjk",
+            Analyzer::Arabic => "ar",
+            Analyzer::Danish => "da",
+            Analyzer::Dutch => "nl",
+            Analyzer::English => "en",
+            Analyzer::Finnish => "fi",
+            Analyzer::French => "fr",
+            Analyzer::German => "de",
+            Analyzer::Greek => "el",
+            Analyzer::Hungarian => "hu",
+            Analyzer::Italian => "it",
+            Analyzer::Norwegian => "no",
+            Analyzer::Portuguese => "pt",
+            Analyzer::Romanian => "ro",
+            Analyzer::Russian => "ru",
+            Analyzer::Spanish => "es",
+            Analyzer::Swedish => "sv",
+            Analyzer::Tamil => "ta",
+            Analyzer::Turkish => "tr",
+        }
+    }
+
+    /// Where its fields are in [`Analyzer::ALL`].
+    pub(crate) fn position(self) -> usize {
+        self as usize
+    }
+
+    pub(crate) fn tokenizer(self) -> String {
+        format!("lang_{}", self.name())
+    }
+
+    /// Whether words are indexed as their stems, and so aren't what was written.
+    pub fn stems(self) -> bool {
+        self.stemmer().is_some()
+    }
+
+    fn stemmer(self) -> Option<Language> {
+        Some(match self {
+            Analyzer::Default | Analyzer::Cjk => return None,
+            Analyzer::Arabic => Language::Arabic,
+            Analyzer::Danish => Language::Danish,
+            Analyzer::Dutch => Language::Dutch,
+            Analyzer::English => Language::English,
+            Analyzer::Finnish => Language::Finnish,
+            Analyzer::French => Language::French,
+            Analyzer::German => Language::German,
+            Analyzer::Greek => Language::Greek,
+            Analyzer::Hungarian => Language::Hungarian,
+            Analyzer::Italian => Language::Italian,
+            Analyzer::Norwegian => Language::Norwegian,
+            Analyzer::Portuguese => Language::Portuguese,
+            Analyzer::Romanian => Language::Romanian,
+            Analyzer::Russian => Language::Russian,
+            Analyzer::Spanish => Language::Spanish,
+            Analyzer::Swedish => Language::Swedish,
+            Analyzer::Tamil => Language::Tamil,
+            Analyzer::Turkish => Language::Turkish,
+        })
+    }
+
+    /// The words too common to be worth searching for, only kept for the languages of most works.
+    fn stop_words(self) -> &'static [&'static str] {
+        match self {
+            Analyzer::English => &[
+                "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into",
+                "is", "it", "no", "not", "of", "on", "or", "such", "that", "the", "their", "then",
+                "there", "these", "they", "this", "to", "was", "will", "with",
+            ],
+            Analyzer::French => &[
+                "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "et",
+                "il", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "mes", "ne",
+                "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses",
+                "son", "sur", "ta", "te", "tes", "ton", "tu", "un", "une", "vous",
+            ],
+            Analyzer::German => &[
+                "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "das",
+                "dass", "dem", "den", "der", "des", "die", "doch", "du", "ein", "eine", "einem",
+                "einen", "einer", "er", "es", "für", "hat", "ich", "im", "in", "ist", "mit",
+                "nicht", "noch", "nur", "oder", "sich", "sie", "sind", "so", "und", "von", "war",
+                "wie", "wir", "zu",
+            ],
+            Analyzer::Spanish => &[
+                "a", "al", "con", "de", "del", "el", "en", "es", "la", "las", "lo", "los", "me",
+                "mi", "no", "o", "para", "pero", "por", "que", "se", "su", "sus", "te", "tu", "un",
+                "una", "y", "ya",
+            ],
+            Analyzer::Italian => &[
+                "a", "al", "alla", "che", "ci", "con", "da", "del", "della", "di", "e", "gli", "i",
+                "il", "in", "la", "le", "lo", "ma", "mi", "non", "per", "si", "su", "ti", "un",
+                "una", "uno",
+            ],
+            Analyzer::Portuguese => &[
+                "a", "ao", "as", "com", "da", "das", "de", "do", "dos", "e", "em", "na", "nas",
+                "no", "nos", "o", "os", "para", "por", "que", "se", "um", "uma",
+            ],
+            Analyzer::Dutch => &[
+                "de", "een", "en", "het", "in", "is", "je", "met", "niet", "of", "op", "te", "van",
+                "voor", "zijn",
+            ],
+            Analyzer::Russian => &[
+                "а", "в", "во", "да", "же", "и", "к", "как", "на", "не", "но", "о", "от", "по",
+                "с", "так", "то", "у", "что",
+            ],
+            _ => &[],
+        }
+    }
+
+    fn text_analyzer(self) -> TextAnalyzer {
+        let analyzer = match self {
+            Analyzer::Cjk => TextAnalyzer::from(CjkTokenizer),
+            _ => TextAnalyzer::from(SimpleTokenizer),
+        }
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser);
+
+        let stop_words = self.stop_words();
+        let analyzer = if stop_words.is_empty() {
+            analyzer
+        } else {
+            analyzer.filter(StopWordFilter::remove(
+                stop_words.iter().map(|word| word.to_string()).collect(),
+            ))
+        };
+
+        match self.stemmer() {
+            Some(language) => analyzer.filter(Stemmer::new(language)),
+            None => analyzer,
+        }
+    }
+}
+
+/// Registers every analyzer with an index, which has to be done every time it's opened.
+pub(crate) fn register(index: &Index) {
+    for analyzer in Analyzer::ALL {
+        index
+            .tokenizers()
+            .register(&analyzer.tokenizer(), analyzer.text_analyzer());
+    }
+}
+
+/// Guesses a language from the writing system of a text, for works AO3 didn't give one.
+///
+/// Only scripts that are mostly used by one language, or analyzed the same way, can be told
+/// apart, anything written in latin letters could be anything.
+pub(crate) fn detect(text: &str) -> Option<&'static str> {
+    let mut counts = [0usize; 7];
+    let mut letters = 0;
+
+    for c in text.chars().filter(|c| c.is_alphabetic()).take(2_000) {
+        letters += 1;
+
+        let script = match c {
+            '\u{3040}'..='\u{30ff}' => 0,
+            '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}' => 1,
+            _ if is_han(c) => 2,
+            '\u{0400}'..='\u{04ff}' => 3,
+            '\u{0370}'..='\u{03ff}' => 4,
+            '\u{0600}'..='\u{06ff}' => 5,
+            '\u{0b80}'..='\u{0bff}' => 6,
+            _ => continue,
+        };
+
+        counts[script] += 1;
+    }
+
+    if letters == 0 {
+        return None;
+    }
+
+    // japanese mixes kana in with its han, so any amount of it is telling
+    if counts[0] * 10 >= letters {
+        return Some("ja");
+    }
+
+    let (script, count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, count)| **count)
+        .unwrap_or((0, &0));
+
+    if count * 2 < letters {
+        return None;
+    }
+
+    Some(["ja", "ko", "zh", "ru", "el", "ar", "ta"][script])
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c,
+        '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2a6df}')
+}
+
+/// Characters of the scripts written without spaces between words.
+pub(crate) fn is_cjk(c: char) -> bool {
+    is_han(c)
+        || matches!(c,
+            '\u{3040}'..='\u{30ff}'
+            | '\u{1100}'..='\u{11ff}'
+            | '\u{3130}'..='\u{318f}'
+            | '\u{ac00}'..='\u{d7af}')
+}
+
+/// Splits runs of CJK characters into every pair of neighbouring characters, so a search for a
+/// word finds it wherever it's written without having to know where words start and end. Runs
+/// of anything else are split into words, as [`SimpleTokenizer`] does.
+#[derive(Clone)]
+pub(crate) struct CjkTokenizer;
+
+struct CjkTokenStream {
+    tokens: Vec<Token>,
+    /// One past the current token, zero before the first.
+    next: usize,
+}
+
+impl Tokenizer for CjkTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let mut tokens = Vec::new();
+
+        let mut push = |offset_from: usize, offset_to: usize| {
+            let position = tokens.len();
+
+            tokens.push(Token {
+                offset_from,
+                offset_to,
+                position,
+                text: text[offset_from..offset_to].to_string(),
+                ..Token::default()
+            });
+        };
+
+        let chars = text.char_indices().collect::<Vec<_>>();
+        let end_of = |i: usize| chars.get(i).map_or(text.len(), |(at, _)| *at);
+
+        let mut i = 0;
+        while i < chars.len() {
+            let (start, c) = chars[i];
+
+            if is_cjk(c) {
+                let run = chars[i..].iter().take_while(|(_, c)| is_cjk(*c)).count();
+
+                if run == 1 {
+                    push(start, end_of(i + 1));
+                } else {
+                    for pair in chars[i..i + run].windows(2) {
+                        let (start, _) = pair[0];
+                        let (at, c) = pair[1];
+
+                        push(start, at + c.len_utf8());
+                    }
+                }
+
+                i += run;
+            } else if c.is_alphanumeric() {
+                let run = chars[i..]
+                    .iter()
+                    .take_while(|(_, c)| c.is_alphanumeric() && !is_cjk(*c))
+                    .count();
+
+                push(start, end_of(i + run));
+
+                i += run;
+            } else {
+                i += 1;
+            }
+        }
+
+        BoxTokenStream::from(CjkTokenStream { tokens, next: 0 })
+    }
+}
+
+impl TokenStream for CjkTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.next >= self.tokens.len() {
+            return false;
+        }
+
+        self.next += 1;
+
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
@@ -0,0 +1,303 @@
+//! Works whose text is nearly the same, reposts, orphaned copies and the like.
+//!
+//! Each work's text gets a 64 bit SimHash signature when it's indexed: every run of a few words
+//! is hashed, and each bit of the signature is whichever that bit is in most of the hashes. Works
+//! that share most of their runs of words end up with signatures that differ in few bits, so how
+//! alike two works are is the share of bits their signatures agree on.
+//!
+//! Rather than comparing every work with every other, signatures are split into one more block
+//! than the bits they may differ in, and only works that share a whole block are compared, as two
+//! signatures that differ in fewer bits than there are blocks must have at least one in common.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+use ao3fti_common::timer::TimerTree;
+use tantivy::fastfield::FastFieldReader as _;
+
+use crate::{analyzer::is_cjk, IndexServer};
+
+/// How many words each hashed run is.
+const SHINGLE_WORDS: usize = 3;
+
+/// Works with fewer runs of words than this get no signature, a few lines say too little.
+const MIN_SHINGLES: usize = 16;
+
+/// How alike works need to be when a search doesn't say.
+pub const DEFAULT_MIN_SIMILARITY: f32 = 0.9;
+
+/// The SimHash signature of a work's paragraphs, if there's enough of them.
+///
+/// Zero means a work has no signature, so a signature that happens to come out as zero is
+/// nudged to one.
+pub(crate) fn signature<'p>(paragraphs: impl Iterator<Item = &'p str>) -> Option<u64> {
+    let text = paragraphs.collect::<Vec<_>>().join(" ").to_lowercase();
+    let words = words(&text);
+
+    let shingles = words.len().saturating_sub(SHINGLE_WORDS - 1);
+    if shingles < MIN_SHINGLES {
+        return None;
+    }
+
+    let mut counts = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let hash = hash(shingle);
+
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+
+    let signature = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .fold(0u64, |signature, (bit, _)| signature | (1 << bit));
+
+    Some(signature.max(1))
+}
+
+/// Splits text into words, each CJK character being a word of its own.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() && !is_cjk(c) {
+            start.get_or_insert(i);
+
+            continue;
+        }
+
+        if let Some(start) = start.take() {
+            words.push(&text[start..i]);
+        }
+
+        if is_cjk(c) {
+            words.push(&text[i..i + c.len_utf8()]);
+        }
+    }
+
+    if let Some(start) = start {
+        words.push(&text[start..]);
+    }
+
+    words
+}
+
+/// FNV-1a, with SplitMix64's finalizer to spread short inputs over every bit.
+///
+/// Signatures are stored, so the hash has to stay the same across builds, which std's doesn't
+/// promise.
+fn hash(words: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for (i, word) in words.iter().enumerate() {
+        if i != 0 {
+            hash ^= u64::from(b' ');
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        for byte in word.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// The share of bits two signatures agree on, from zero to one.
+pub fn similarity(a: u64, b: u64) -> f32 {
+    1.0 - (a ^ b).count_ones() as f32 / 64.0
+}
+
+/// A similarity that's asked for but can't be searched with.
+#[derive(Debug)]
+pub struct InvalidSimilarity(pub f32);
+
+impl fmt::Display for InvalidSimilarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // unrelated signatures already agree on about half their bits
+        write!(
+            f,
+            "similarity `{}` is out of range, expected between 0.5 and 1",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidSimilarity {}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DuplicatesQuery {
+    #[serde(default = "default_min_similarity")]
+    pub min_similarity: f32,
+}
+
+fn default_min_similarity() -> f32 {
+    DEFAULT_MIN_SIMILARITY
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Duplicates {
+    pub min_similarity: f32,
+    /// The largest clusters first.
+    pub clusters: Vec<DuplicateCluster>,
+    pub timings: TimerTree,
+}
+
+/// Works that are each alike enough to at least one other in the cluster.
+#[derive(Debug, serde::Serialize)]
+pub struct DuplicateCluster {
+    pub ids: Vec<u64>,
+    /// How alike the least alike pair that joined the cluster is.
+    pub similarity: f32,
+}
+
+/// Finds every cluster of works whose signatures are at least as alike as asked.
+pub fn duplicates(
+    index: Arc<IndexServer>,
+    search: DuplicatesQuery,
+) -> Result<Duplicates, ao3fti_common::Report> {
+    if !(0.5..=1.0).contains(&search.min_similarity) {
+        return Err(InvalidSimilarity(search.min_similarity).into());
+    }
+
+    let searcher = index.reader.searcher();
+    let mut timer_tree = TimerTree::default();
+
+    let fields = &index.fields;
+
+    let works = {
+        let _reading_timer = timer_tree.open("reading signatures");
+
+        let mut works = BTreeMap::new();
+
+        for segment_reader in searcher.segment_readers() {
+            let fast_fields = segment_reader.fast_fields();
+            let ids = fast_fields.u64(fields.id)?;
+            let passages = fast_fields.u64(fields.passage)?;
+            let signatures = fast_fields.u64(fields.signature)?;
+
+            for doc in segment_reader.doc_ids_alive() {
+                let signature = signatures.get(doc);
+
+                if passages.get(doc) == 0 && signature != 0 {
+                    works.insert(ids.get(doc), signature);
+                }
+            }
+        }
+
+        works.into_iter().collect::<Vec<_>>()
+    };
+
+    let max_distance = ((1.0 - search.min_similarity) * 64.0).floor() as usize;
+    let blocks = max_distance + 1;
+
+    let mut clusters = Clusters::new(works.len());
+
+    {
+        let _comparing_timer = timer_tree.open("comparing");
+
+        let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+
+        for (i, (_, signature)) in works.iter().enumerate() {
+            for block in 0..blocks {
+                let (start, end) = (block * 64 / blocks, (block + 1) * 64 / blocks);
+                let mask = u64::MAX >> (64 - (end - start));
+
+                buckets
+                    .entry((block, (signature >> start) & mask))
+                    .or_default()
+                    .push(i);
+            }
+        }
+
+        for bucket in buckets.values() {
+            for (n, &a) in bucket.iter().enumerate() {
+                for &b in &bucket[n + 1..] {
+                    let similarity = similarity(works[a].1, works[b].1);
+
+                    if similarity >= search.min_similarity {
+                        clusters.join(a, b, similarity);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut grouped: BTreeMap<usize, DuplicateCluster> = BTreeMap::new();
+    for (i, (id, _)) in works.iter().enumerate() {
+        let (root, similarity) = clusters.find(i);
+
+        grouped
+            .entry(root)
+            .or_insert_with(|| DuplicateCluster {
+                ids: Vec::new(),
+                similarity,
+            })
+            .ids
+            .push(*id);
+    }
+
+    let mut clusters = grouped
+        .into_values()
+        .filter(|cluster| cluster.ids.len() > 1)
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| b.ids.len().cmp(&a.ids.len()).then(a.ids.cmp(&b.ids)));
+
+    Ok(Duplicates {
+        min_similarity: search.min_similarity,
+        clusters,
+        timings: timer_tree,
+    })
+}
+
+/// A union-find over works, keeping the least similarity each cluster was joined with.
+struct Clusters {
+    parents: Vec<usize>,
+    similarities: Vec<f32>,
+}
+
+impl Clusters {
+    fn new(len: usize) -> Clusters {
+        Clusters {
+            parents: (0..len).collect(),
+            similarities: vec![1.0; len],
+        }
+    }
+
+    /// The cluster a work is in, and how alike its least alike pair is.
+    fn find(&mut self, mut i: usize) -> (usize, f32) {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+
+        (i, self.similarities[i])
+    }
+
+    fn join(&mut self, a: usize, b: usize, similarity: f32) {
+        let (a, a_similarity) = self.find(a);
+        let (b, b_similarity) = self.find(b);
+
+        if a == b {
+            return;
+        }
+
+        let joined = similarity.min(a_similarity).min(b_similarity);
+
+        self.parents[b] = a;
+        self.similarities[a] = joined;
+    }
+}
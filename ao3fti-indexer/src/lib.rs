@@ -1,23 +1,483 @@
-use std::sync::Arc;
+mod analyzer;
+mod duplicates;
+pub mod query;
+mod rank;
+mod similar;
+mod suggest;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ops::Range,
+    sync::Arc,
+};
 
 use ao3fti_common::{
+    bail,
     channel::{self, Receiver},
+    err,
+    models::Rating,
     timer::TimerTree,
     Conf,
 };
 use tantivy::{
-    collector::{Count, TopDocs},
-    query::QueryParser,
-    schema::{Field, FieldType, Schema, FAST, STORED, TEXT},
-    Document, Index, IndexReader, IndexWriter, Score,
+    collector::{Count, DocSetCollector},
+    query::{BooleanQuery, Occur, Query, TermQuery},
+    schema::{
+        Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, INDEXED, STORED,
+        STRING, TEXT,
+    },
+    Document, Index, IndexReader, IndexWriter, Score, Searcher, SnippetGenerator, Term,
 };
 
+pub use crate::{
+    analyzer::{Analyzer, ANALYZERS},
+    duplicates::{
+        duplicates, similarity, DuplicateCluster, Duplicates, DuplicatesQuery, InvalidSimilarity,
+        DEFAULT_MIN_SIMILARITY,
+    },
+    query::{ParseError, Synonyms},
+    rank::{Popularity, Profile, Profiles, UnknownProfile},
+    similar::{similar, SimilarQuery, SimilarSerp, SimilarWork},
+    suggest::Suggestion,
+};
 pub use tantivy::schema::{NamedFieldDocument, Value};
 
+/// A story as it gets indexed.
+#[derive(Debug)]
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct StoryData {
     pub id: usize,
-    pub contents: String,
+    /// The language code AO3 gives the work, ie `en`, its text is guessed at if there isn't one.
+    pub language: Option<String>,
+    pub title: String,
+    /// The pseud and user name of every author.
+    pub authors: Vec<String>,
+    pub summary: String,
+    pub fandoms: Vec<String>,
+    pub ships: Vec<String>,
+    pub characters: Vec<String>,
+    pub tags: Vec<String>,
+    pub warnings: Vec<String>,
+    pub categories: Vec<String>,
+    pub series: Vec<String>,
+    pub rating: Rating,
+    pub words: u64,
+    pub chapters: u64,
+    pub complete: bool,
+    pub comments: u64,
+    pub kudos: u64,
+    pub bookmarks: u64,
+    pub hits: u64,
+    /// The paragraphs of every chapter.
+    pub contents: Vec<Vec<String>>,
+}
+
+/// How many paragraphs each passage of a story is.
+const PASSAGE_PARAGRAPHS: usize = 3;
+
+fn schema() -> Schema {
+    let mut schema_builder = Schema::builder();
+
+    schema_builder.add_u64_field("id", INDEXED | FAST | STORED);
+
+    // stories are indexed whole and again as passages, which also carry where they're from
+    schema_builder.add_u64_field("passage", INDEXED | FAST);
+    schema_builder.add_u64_field("chapter", INDEXED | FAST | STORED);
+    schema_builder.add_u64_field("paragraph", FAST | STORED);
+
+    // the language code, and its primary subtag on its own so `lang:pt` finds `pt-BR`
+    schema_builder.add_text_field("lang", STRING | STORED);
+
+    for name in [
+        "author",
+        "fandom",
+        "ship",
+        "character",
+        "tag",
+        "warning",
+        "category",
+        "series",
+    ] {
+        schema_builder.add_text_field(name, TEXT);
+    }
+
+    for analyzer in Analyzer::ALL {
+        let options = |stored: bool| {
+            let options = TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(&analyzer.tokenizer())
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            );
+
+            if stored {
+                options.set_stored()
+            } else {
+                options
+            }
+        };
+
+        schema_builder.add_text_field(&format!("title_{}", analyzer.name()), options(false));
+
+        // stored for their snippets
+        schema_builder.add_text_field(&format!("summary_{}", analyzer.name()), options(true));
+        schema_builder.add_text_field(&format!("contents_{}", analyzer.name()), options(false));
+    }
+
+    // a passage's paragraphs as they were written, for its snippets and to read the story back,
+    // the whole story only indexes them so its text is only stored once
+    schema_builder.add_text_field("body", STORED);
+
+    for name in [
+        "rating",
+        "words",
+        "chapters",
+        "complete",
+        "comments",
+        "kudos",
+        "bookmarks",
+        "hits",
+    ] {
+        schema_builder.add_u64_field(name, INDEXED | FAST);
+    }
+
+    // the SimHash of a story's text, zero for passages and stories with too little of it
+    schema_builder.add_u64_field("signature", FAST);
+
+    schema_builder.build()
+}
+
+/// A text field split into one for each analyzer, a story's text only goes in its language's.
+#[derive(Clone, Copy, Debug)]
+pub struct AnalyzedField([Field; ANALYZERS]);
+
+impl AnalyzedField {
+    fn new(
+        field: impl Fn(&str) -> Result<Field, ao3fti_common::Report>,
+        name: &str,
+    ) -> Result<AnalyzedField, ao3fti_common::Report> {
+        let mut fields = [Field::from_field_id(0); ANALYZERS];
+
+        for analyzer in Analyzer::ALL {
+            fields[analyzer.position()] = field(&format!("{}_{}", name, analyzer.name()))?;
+        }
+
+        Ok(AnalyzedField(fields))
+    }
+
+    pub fn get(&self, analyzer: Analyzer) -> Field {
+        self.0[analyzer.position()]
+    }
+
+    pub fn all(&self) -> &[Field] {
+        &self.0
+    }
+
+    /// The analyzer of one of its fields.
+    pub fn analyzer(&self, field: Field) -> Option<Analyzer> {
+        Analyzer::ALL
+            .into_iter()
+            .find(|analyzer| self.get(*analyzer) == field)
+    }
+}
+
+/// The fields of the index, looked up once from its schema.
+#[derive(Clone, Copy, Debug)]
+pub struct Fields {
+    pub id: Field,
+    /// `1` for passages, `0` for whole stories.
+    pub passage: Field,
+    /// The chapter a passage is in, from one.
+    pub chapter: Field,
+    /// The paragraph of its chapter a passage starts at, from zero.
+    pub paragraph: Field,
+    pub lang: Field,
+    pub title: AnalyzedField,
+    pub author: Field,
+    pub summary: AnalyzedField,
+    pub fandom: Field,
+    pub ship: Field,
+    pub character: Field,
+    pub tag: Field,
+    pub warning: Field,
+    pub category: Field,
+    pub series: Field,
+    pub contents: AnalyzedField,
+    pub body: Field,
+    pub rating: Field,
+    pub words: Field,
+    pub chapters: Field,
+    pub complete: Field,
+    pub comments: Field,
+    pub kudos: Field,
+    pub bookmarks: Field,
+    pub hits: Field,
+    pub signature: Field,
+}
+
+impl Fields {
+    fn new(schema: &Schema) -> Result<Fields, ao3fti_common::Report> {
+        let field = |name: &str| {
+            schema
+                .get_field(name)
+                .ok_or_else(|| err!("index is missing the `{}` field", name))
+        };
+
+        Ok(Fields {
+            id: field("id")?,
+            passage: field("passage")?,
+            chapter: field("chapter")?,
+            paragraph: field("paragraph")?,
+            lang: field("lang")?,
+            title: AnalyzedField::new(field, "title")?,
+            author: field("author")?,
+            summary: AnalyzedField::new(field, "summary")?,
+            fandom: field("fandom")?,
+            ship: field("ship")?,
+            character: field("character")?,
+            tag: field("tag")?,
+            warning: field("warning")?,
+            category: field("category")?,
+            series: field("series")?,
+            contents: AnalyzedField::new(field, "contents")?,
+            body: field("body")?,
+            rating: field("rating")?,
+            words: field("words")?,
+            chapters: field("chapters")?,
+            complete: field("complete")?,
+            comments: field("comments")?,
+            kudos: field("kudos")?,
+            bookmarks: field("bookmarks")?,
+            hits: field("hits")?,
+            signature: field("signature")?,
+        })
+    }
+
+    /// The text fields a term without a field searches.
+    pub fn text(&self) -> Vec<Field> {
+        let mut fields = Vec::with_capacity(8 + ANALYZERS * 3);
+
+        fields.extend_from_slice(self.title.all());
+        fields.extend_from_slice(self.summary.all());
+        fields.extend_from_slice(self.contents.all());
+        fields.extend_from_slice(&[
+            self.author,
+            self.fandom,
+            self.ship,
+            self.character,
+            self.tag,
+            self.warning,
+            self.category,
+            self.series,
+        ]);
+
+        fields
+    }
+
+    /// Whether a field is indexed as the stems of its words.
+    pub fn stems(&self, field: Field) -> bool {
+        [self.title, self.summary, self.contents]
+            .iter()
+            .filter_map(|analyzed| analyzed.analyzer(field))
+            .any(Analyzer::stems)
+    }
+
+    /// A copy of a story or passage with `paragraphs` as its text, which isn't stored in the
+    /// index but is needed to make snippets of it.
+    fn with_contents<'p>(
+        &self,
+        doc: &Document,
+        paragraphs: impl IntoIterator<Item = &'p str>,
+    ) -> Document {
+        let mut doc = doc.clone();
+        let field = self.contents.get(self.analyzer(&doc));
+
+        for paragraph in paragraphs {
+            doc.add_text(field, paragraph);
+        }
+
+        doc
+    }
+
+    /// The analyzer a story or passage was indexed with.
+    pub fn analyzer(&self, doc: &Document) -> Analyzer {
+        doc.get_first(self.lang)
+            .and_then(Value::as_text)
+            .map_or(Analyzer::Default, Analyzer::for_language)
+    }
+
+    /// How many typos a fuzzy search allows in a field, names are misspelled far more often than
+    /// the rest of a story.
+    pub fn fuzzy_distance(&self, field: Field) -> u8 {
+        if self.title.all().contains(&field)
+            || [
+                self.author,
+                self.fandom,
+                self.ship,
+                self.character,
+                self.series,
+            ]
+            .contains(&field)
+        {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The documents of a story, the whole story followed by each of its passages.
+    fn documents(&self, story: StoryData) -> Vec<Document> {
+        let mut doc = Document::default();
+
+        let language = story.language.clone().or_else(|| {
+            let text = story.contents.iter().flatten().take(20);
+
+            analyzer::detect(
+                &std::iter::once(&story.title)
+                    .chain(std::iter::once(&story.summary))
+                    .chain(text)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+            .map(str::to_string)
+        });
+        let analyzer = language
+            .as_deref()
+            .map_or(Analyzer::Default, Analyzer::for_language);
+
+        doc.add_u64(self.id, story.id as u64);
+
+        if let Some(language) = &language {
+            let language = language.to_lowercase();
+
+            doc.add_text(self.lang, &language);
+            if let Some((primary, _)) = language.split_once('-') {
+                doc.add_text(self.lang, primary);
+            }
+        }
+
+        doc.add_text(self.title.get(analyzer), &story.title);
+        doc.add_text(self.summary.get(analyzer), &story.summary);
+
+        for (field, values) in [
+            (self.author, &story.authors),
+            (self.fandom, &story.fandoms),
+            (self.ship, &story.ships),
+            (self.character, &story.characters),
+            (self.tag, &story.tags),
+            (self.warning, &story.warnings),
+            (self.category, &story.categories),
+            (self.series, &story.series),
+        ] {
+            for value in values {
+                doc.add_text(field, value);
+            }
+        }
+
+        if let Some(rating) = rating_value(&story.rating) {
+            doc.add_u64(self.rating, rating);
+        }
+
+        doc.add_u64(self.words, story.words);
+        doc.add_u64(self.chapters, story.chapters);
+        doc.add_u64(self.complete, story.complete as u64);
+        doc.add_u64(self.comments, story.comments);
+        doc.add_u64(self.kudos, story.kudos);
+        doc.add_u64(self.bookmarks, story.bookmarks);
+        doc.add_u64(self.hits, story.hits);
+
+        // passages get everything but the text, so they can be filtered the same way
+        let metadata = doc.clone();
+
+        doc.add_u64(self.passage, 0);
+
+        for paragraph in story.contents.iter().flatten() {
+            doc.add_text(self.contents.get(analyzer), paragraph);
+        }
+
+        if let Some(signature) =
+            duplicates::signature(story.contents.iter().flatten().map(String::as_str))
+        {
+            doc.add_u64(self.signature, signature);
+        }
+
+        let mut docs = vec![doc];
+
+        for (chapter, paragraphs) in story.contents.iter().enumerate() {
+            for (window, passage) in paragraphs.chunks(PASSAGE_PARAGRAPHS).enumerate() {
+                let mut passage_doc = metadata.clone();
+
+                passage_doc.add_u64(self.passage, 1);
+                passage_doc.add_u64(self.chapter, chapter as u64 + 1);
+                passage_doc.add_u64(self.paragraph, (window * PASSAGE_PARAGRAPHS) as u64);
+
+                for paragraph in passage {
+                    passage_doc.add_text(self.contents.get(analyzer), paragraph);
+                    passage_doc.add_text(self.body, paragraph);
+                }
+
+                docs.push(passage_doc);
+            }
+        }
+
+        docs
+    }
+}
+
+/// Matches the stories or passages with a value, without counting towards their score.
+fn u64_query(field: Field, value: u64) -> Box<dyn Query> {
+    query::filter(Box::new(TermQuery::new(
+        Term::from_field_u64(field, value),
+        IndexRecordOption::Basic,
+    )))
+}
+
+/// Ratings are indexed in order, from not rated to explicit, so they can be searched as ranges.
+pub(crate) fn rating_value(rating: &Rating) -> Option<u64> {
+    match rating {
+        Rating::NotRated => Some(0),
+        Rating::General => Some(1),
+        Rating::Teen => Some(2),
+        Rating::Mature => Some(3),
+        Rating::Explicit => Some(4),
+        Rating::Unknown => None,
+    }
+}
+
+/// Opens the index, creating it if there isn't one yet.
+fn open_index(conf: &Conf) -> Result<Index, ao3fti_common::Report> {
+    let data_path = conf.index.as_path();
+    if !data_path.exists() {
+        tracing::debug!(path = %data_path.display(), "creating index directory");
+        std::fs::create_dir_all(data_path)?;
+    }
+
+    let schema = schema();
+
+    if std::fs::read_dir(data_path)?.next().is_none() {
+        tracing::debug!(path = %data_path.display(), "initializing index directory with default schema");
+
+        let index = Index::create_in_dir(data_path, schema)?;
+        analyzer::register(&index);
+
+        return Ok(index);
+    }
+
+    let index = Index::open_in_dir(data_path)?;
+    analyzer::register(&index);
+
+    // the stories' text isn't kept anywhere else, so an old index can't be migrated in place
+    if serde_json::to_value(index.schema())? != serde_json::to_value(&schema)? {
+        bail!(
+            "the index at `{}` was built by an older version, move it out of the way and scrape again, a cache only scrape or `import-warc` will rebuild it without refetching",
+            data_path.display()
+        );
+    }
+
+    Ok(index)
 }
 
 #[tracing::instrument(skip(conf, line_receiver), err)]
@@ -31,30 +491,15 @@ pub fn index(
 
     let (doc_sender, doc_receiver) = channel::bounded(10_000);
 
-    let data_path = conf.index.as_path();
-    if !data_path.exists() {
-        tracing::debug!(path = %data_path.display(), "creating index directory");
-        std::fs::create_dir_all(&data_path)?;
-    }
-    let index = if std::fs::read_dir(data_path)?.next().is_none() {
-        tracing::debug!(path = %data_path.display(), "initializing index directory with default schema");
-
-        let mut schema_builder = Schema::builder();
-        schema_builder.add_u64_field("id", FAST | STORED);
-        schema_builder.add_text_field("contents", TEXT);
-        let schema = schema_builder.build();
-
-        Index::create_in_dir(data_path, schema)?
-    } else {
-        Index::open_in_dir(data_path)?
-    };
-
-    let schema = index.schema();
+    let index = open_index(&conf)?;
+    let fields = Fields::new(&index.schema())?;
 
-    let num_threads_to_parse_json = std::cmp::max(1, num_threads / 4);
-    tracing::info!("Using {} threads to parse json", num_threads_to_parse_json);
-    for i in 0..num_threads_to_parse_json {
-        let schema_clone = schema.clone();
+    let num_threads_to_build_docs = std::cmp::max(1, num_threads / 4);
+    tracing::info!(
+        "Using {} threads to build documents",
+        num_threads_to_build_docs
+    );
+    for i in 0..num_threads_to_build_docs {
         let doc_sender_clone = doc_sender.clone();
         let line_receiver_clone = line_receiver.clone();
 
@@ -62,17 +507,10 @@ pub fn index(
         std::thread::spawn(move || {
             let _entered = child_span.entered();
 
-            for article_line in line_receiver_clone {
-                let article_line = serde_json::to_string(&article_line).unwrap();
-
-                match schema_clone.parse_document(&article_line) {
-                    Ok(doc) => {
-                        if let Err(err) = doc_sender_clone.send(doc) {
-                            tracing::error!(err = ?err, "unable to send document to be indexed");
-                        }
-                    }
-                    Err(err) => {
-                        tracing::error!("Failed to add document doc {:?}", err);
+            for story in line_receiver_clone {
+                for doc in fields.documents(story) {
+                    if let Err(err) = doc_sender_clone.send(doc) {
+                        tracing::error!(err = ?err, "unable to send document to be indexed");
                     }
                 }
             }
@@ -130,33 +568,27 @@ fn index_documents(
 }
 
 pub struct IndexServer {
+    pub index: Index,
     pub reader: IndexReader,
-    pub query_parser: QueryParser,
     pub schema: Schema,
+    pub fields: Fields,
+    pub profiles: Profiles,
 }
 
 impl IndexServer {
-    pub fn new() -> Result<Arc<Self>, ao3fti_common::Report> {
-        let index = Index::open_in_dir("chapter-index")?;
+    pub fn new(conf: &Conf) -> Result<Arc<Self>, ao3fti_common::Report> {
+        let index = open_index(conf)?;
         let schema = index.schema();
-        let default_fields: Vec<Field> = schema
-            .fields()
-            .filter(|&(_, field_entry)| match field_entry.field_type() {
-                FieldType::Str(ref text_field_options) => {
-                    text_field_options.get_indexing_options().is_some()
-                }
-                _ => false,
-            })
-            .map(|(field, _)| field)
-            .collect();
-        let query_parser =
-            QueryParser::new(schema.clone(), default_fields, index.tokenizers().clone());
+        let fields = Fields::new(&schema)?;
         let reader = index.reader()?;
+        let profiles = Profiles::load(conf.ranking_profiles.as_deref(), &conf.ranking_profile)?;
 
         let index_server = Arc::new(IndexServer {
+            index,
             reader,
-            query_parser,
             schema,
+            fields,
+            profiles,
         });
 
         Ok(index_server)
@@ -168,6 +600,8 @@ pub struct Serp {
     pub query: String,
     pub num_hits: usize,
     pub hits: Vec<Hit>,
+    /// Searches that find more, when this one found little or nothing.
+    pub suggestions: Vec<Suggestion>,
     pub timings: TimerTree,
 }
 
@@ -176,6 +610,117 @@ pub struct Hit {
     pub score: Score,
     pub doc: NamedFieldDocument,
     pub id: u32,
+    /// Where the search matched the summary and body, if it did.
+    pub snippets: Vec<Snippet>,
+}
+
+/// A piece of a story's text around where a search matched it.
+#[derive(Debug, serde::Serialize)]
+pub struct Snippet {
+    /// Either `summary` or `contents`.
+    pub field: &'static str,
+    pub fragment: String,
+    /// The byte ranges of `fragment` that matched.
+    pub highlighted: Vec<Range<usize>>,
+    /// The escaped fragment, with every match wrapped in a `<mark>`.
+    pub html: String,
+}
+
+impl Snippet {
+    fn new(field: &'static str, snippet: tantivy::Snippet) -> Option<Snippet> {
+        let fragment = snippet.fragment().to_string();
+        let highlighted = snippet.highlighted().to_vec();
+
+        if highlighted.is_empty() {
+            return None;
+        }
+
+        let mut html = String::with_capacity(fragment.len() + highlighted.len() * 13);
+        let mut end = 0;
+
+        for range in &highlighted {
+            escape(&mut html, &fragment[end..range.start]);
+            html.push_str("<mark>");
+            escape(&mut html, &fragment[range.clone()]);
+            html.push_str("</mark>");
+
+            end = range.end;
+        }
+        escape(&mut html, &fragment[end..]);
+
+        Some(Snippet {
+            field,
+            fragment,
+            highlighted,
+            html,
+        })
+    }
+}
+
+/// Makes the snippets of stories, with generators made for each analyzer the first time a story
+/// indexed with it needs them.
+struct Snippets<'s> {
+    searcher: &'s Searcher,
+    query: &'s dyn Query,
+    /// The name of each field snippets are made from, and how long they can be.
+    fields: Vec<(&'static str, AnalyzedField, usize)>,
+    generators: HashMap<Analyzer, Vec<(&'static str, SnippetGenerator)>>,
+}
+
+impl<'s> Snippets<'s> {
+    fn new(
+        searcher: &'s Searcher,
+        query: &'s dyn Query,
+        fields: Vec<(&'static str, AnalyzedField, usize)>,
+    ) -> Snippets<'s> {
+        Snippets {
+            searcher,
+            query,
+            fields,
+            generators: HashMap::new(),
+        }
+    }
+
+    fn snippets(
+        &mut self,
+        analyzer: Analyzer,
+        doc: &Document,
+    ) -> Result<Vec<Snippet>, ao3fti_common::Report> {
+        let generators = match self.generators.entry(analyzer) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut generators = Vec::with_capacity(self.fields.len());
+
+                for (name, field, max_num_chars) in &self.fields {
+                    let mut generator =
+                        SnippetGenerator::create(self.searcher, self.query, field.get(analyzer))?;
+                    generator.set_max_num_chars(*max_num_chars);
+
+                    generators.push((*name, generator));
+                }
+
+                entry.insert(generators)
+            }
+        };
+
+        Ok(generators
+            .iter()
+            .filter_map(|(name, generator)| Snippet::new(name, generator.snippet_from_doc(doc)))
+            .collect())
+    }
+}
+
+fn escape(buffer: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => buffer.push_str("&amp;"),
+            '<' => buffer.push_str("&lt;"),
+            '>' => buffer.push_str("&gt;"),
+            '"' => buffer.push_str("&quot;"),
+            '\'' => buffer.push_str("&#39;"),
+            c => buffer.push(c),
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -183,9 +728,46 @@ pub struct SearchQuery {
     pub query: String,
     pub offset: usize,
     pub limit: usize,
+    /// Allow typos in every word, not just those marked with `~`.
+    #[serde(default)]
+    pub fuzzy: bool,
+    /// The name of the ranking profile to use, instead of the default one.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The synonyms of the tags the search quotes, which the index doesn't know about.
+    #[serde(skip)]
+    pub synonyms: Synonyms,
+}
+
+/// Parses a search of whole stories, leaving out their passages.
+fn story_query(
+    index: &IndexServer,
+    q: &str,
+    fuzzy: bool,
+    profile: &Profile,
+    synonyms: &Synonyms,
+) -> Result<Box<dyn Query>, ParseError> {
+    Ok(Box::new(BooleanQuery::new(vec![
+        (
+            Occur::Must,
+            query::parse(
+                &index.index,
+                &index.fields,
+                &index.fields.text(),
+                fuzzy,
+                profile,
+                synonyms,
+                q,
+            )?,
+        ),
+        (Occur::Must, u64_query(index.fields.passage, 0)),
+    ])))
 }
 
 pub fn serp(index: Arc<IndexServer>, search: SearchQuery) -> Result<Serp, ao3fti_common::Report> {
+    /// Searches with fewer hits than this get suggestions.
+    const SUGGEST_BELOW: usize = 5;
+
     let searcher = index.reader.searcher();
     let mut timer_tree = TimerTree::default();
 
@@ -193,40 +775,296 @@ pub fn serp(index: Arc<IndexServer>, search: SearchQuery) -> Result<Serp, ao3fti
         query: q,
         offset,
         limit,
+        fuzzy,
+        profile,
+        synonyms,
     } = search;
 
-    let query = index.query_parser.parse_query(&q)?;
+    let profile = index.profiles.get(profile.as_deref())?;
+    let query = story_query(&index, &q, fuzzy, profile, &synonyms)?;
 
     let (top_docs, num_hits) = {
         let _search_timer = timer_tree.open("search");
 
         searcher.search(
             &query,
-            &(TopDocs::with_limit(limit).and_offset(offset), Count),
+            &(profile.collector(&index.fields, limit, offset), Count),
         )?
     };
 
+    // few hits is as likely to be a typo as there being few works
+    let suggestions = if num_hits < SUGGEST_BELOW {
+        let _suggest_timer = timer_tree.open("suggesting");
+
+        suggest::suggestions(&index, &searcher, &q, fuzzy, profile, &synonyms, num_hits)?
+    } else {
+        Vec::new()
+    };
+
+    let mut snippets = Snippets::new(
+        &searcher,
+        &*query,
+        vec![
+            ("summary", index.fields.summary, 150),
+            ("contents", index.fields.contents, 250),
+        ],
+    );
+
     let hits: Vec<Hit> = {
         let _fetching_timer = timer_tree.open("fetching docs");
 
-        top_docs
-            .iter()
-            .map(|(score, doc_address)| {
-                let doc: Document = searcher.doc(*doc_address).unwrap();
+        let mut hits = Vec::with_capacity(top_docs.len());
 
-                Hit {
-                    score: *score,
-                    doc: index.schema.to_named_doc(&doc),
-                    id: doc_address.doc_id,
-                }
-            })
-            .collect()
+        for (score, doc_address) in top_docs {
+            let doc: Document = searcher.doc(doc_address)?;
+            let analyzer = index.fields.analyzer(&doc);
+
+            // the text is only stored on the passages, so it's put back together for snippets
+            let passages = match doc.get_first(index.fields.id).and_then(Value::as_u64) {
+                Some(id) => read_passages(&index, &searcher, id)?,
+                None => Vec::new(),
+            };
+            let snippets = snippets.snippets(
+                analyzer,
+                &index.fields.with_contents(
+                    &doc,
+                    passages
+                        .iter()
+                        .flat_map(|passage| &passage.paragraphs)
+                        .map(String::as_str),
+                ),
+            )?;
+
+            let mut named_doc = index.schema.to_named_doc(&doc);
+            // the summary is only split up by language to be analyzed
+            if let Some(summary) = named_doc.0.remove(
+                index
+                    .schema
+                    .get_field_name(index.fields.summary.get(analyzer)),
+            ) {
+                named_doc.0.insert("summary".to_string(), summary);
+            }
+
+            hits.push(Hit {
+                score,
+                doc: named_doc,
+                id: doc_address.doc_id,
+                snippets,
+            });
+        }
+
+        hits
     };
 
     Ok(Serp {
         query: q,
         num_hits,
         hits,
+        suggestions,
         timings: timer_tree,
     })
 }
+
+#[derive(Debug, serde::Serialize)]
+pub struct PassageSerp {
+    pub query: String,
+    /// How many works the best passages are from, which is what gets paged over.
+    pub num_works: usize,
+    pub works: Vec<PassageWork>,
+    pub timings: TimerTree,
+}
+
+/// A work and its passages that matched best.
+#[derive(Debug, serde::Serialize)]
+pub struct PassageWork {
+    pub id: u64,
+    /// The score of its best passage.
+    pub score: Score,
+    pub passages: Vec<PassageHit>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PassageHit {
+    pub score: Score,
+    pub chapter: u64,
+    pub paragraph: u64,
+    pub snippet: Option<Snippet>,
+}
+
+/// Searches the passages of every story, words without a field only search their text.
+///
+/// Only the best thousand passages are looked at, grouped by the work they're from, with up to
+/// three kept for each. `offset` and `limit` count works rather than passages.
+pub fn passages(
+    index: Arc<IndexServer>,
+    search: SearchQuery,
+) -> Result<PassageSerp, ao3fti_common::Report> {
+    const PASSAGE_CANDIDATES: usize = 1_000;
+    const PASSAGES_PER_WORK: usize = 3;
+
+    let searcher = index.reader.searcher();
+    let mut timer_tree = TimerTree::default();
+
+    let SearchQuery {
+        query: q,
+        offset,
+        limit,
+        fuzzy,
+        profile,
+        synonyms,
+    } = search;
+
+    let profile = index.profiles.get(profile.as_deref())?;
+
+    let query: Box<dyn Query> = Box::new(BooleanQuery::new(vec![
+        (
+            Occur::Must,
+            query::parse(
+                &index.index,
+                &index.fields,
+                index.fields.contents.all(),
+                fuzzy,
+                profile,
+                &synonyms,
+                &q,
+            )?,
+        ),
+        (Occur::Must, u64_query(index.fields.passage, 1)),
+    ]));
+
+    let top_docs = {
+        let _search_timer = timer_tree.open("search");
+
+        searcher.search(
+            &query,
+            &profile.collector(&index.fields, PASSAGE_CANDIDATES, 0),
+        )?
+    };
+
+    let mut snippets = Snippets::new(
+        &searcher,
+        &*query,
+        vec![("contents", index.fields.contents, 250)],
+    );
+
+    let mut works: Vec<PassageWork> = Vec::new();
+
+    {
+        let _grouping_timer = timer_tree.open("grouping passages");
+
+        let mut positions = HashMap::new();
+
+        for (score, doc_address) in top_docs {
+            let doc: Document = searcher.doc(doc_address)?;
+
+            let (id, chapter, paragraph) = match (
+                doc.get_first(index.fields.id).and_then(Value::as_u64),
+                doc.get_first(index.fields.chapter).and_then(Value::as_u64),
+                doc.get_first(index.fields.paragraph)
+                    .and_then(Value::as_u64),
+            ) {
+                (Some(id), Some(chapter), Some(paragraph)) => (id, chapter, paragraph),
+                _ => continue,
+            };
+
+            let position = *positions.entry(id).or_insert_with(|| {
+                works.push(PassageWork {
+                    id,
+                    score,
+                    passages: Vec::with_capacity(PASSAGES_PER_WORK),
+                });
+
+                works.len() - 1
+            });
+
+            let work = &mut works[position];
+            if work.passages.len() >= PASSAGES_PER_WORK {
+                continue;
+            }
+
+            work.passages.push(PassageHit {
+                score,
+                chapter,
+                paragraph,
+                snippet: snippets
+                    .snippets(
+                        index.fields.analyzer(&doc),
+                        &index.fields.with_contents(
+                            &doc,
+                            doc.get_all(index.fields.body).filter_map(Value::as_text),
+                        ),
+                    )?
+                    .pop(),
+            });
+        }
+    }
+
+    let num_works = works.len();
+    let works = works.into_iter().skip(offset).take(limit).collect();
+
+    Ok(PassageSerp {
+        query: q,
+        num_works,
+        works,
+        timings: timer_tree,
+    })
+}
+
+/// A passage of a story's text.
+#[derive(Debug, serde::Serialize)]
+pub struct Passage {
+    pub chapter: u64,
+    pub paragraph: u64,
+    pub paragraphs: Vec<String>,
+}
+
+/// Gets the text of a story back out of its passages, in order.
+pub fn story_text(
+    index: Arc<IndexServer>,
+    story_id: u64,
+) -> Result<Vec<Passage>, ao3fti_common::Report> {
+    read_passages(&index, &index.reader.searcher(), story_id)
+}
+
+/// The passages of a story in order, the only place its text is stored.
+pub(crate) fn read_passages(
+    index: &IndexServer,
+    searcher: &Searcher,
+    story_id: u64,
+) -> Result<Vec<Passage>, ao3fti_common::Report> {
+    let query = BooleanQuery::new(vec![
+        (Occur::Must, u64_query(index.fields.id, story_id)),
+        (Occur::Must, u64_query(index.fields.passage, 1)),
+    ]);
+
+    let mut passages = Vec::new();
+
+    for doc_address in searcher.search(&query, &DocSetCollector)? {
+        let doc: Document = searcher.doc(doc_address)?;
+
+        let (chapter, paragraph) = match (
+            doc.get_first(index.fields.chapter).and_then(Value::as_u64),
+            doc.get_first(index.fields.paragraph)
+                .and_then(Value::as_u64),
+        ) {
+            (Some(chapter), Some(paragraph)) => (chapter, paragraph),
+            _ => continue,
+        };
+
+        let paragraphs = doc
+            .get_all(index.fields.body)
+            .filter_map(Value::as_text)
+            .map(str::to_string)
+            .collect();
+
+        passages.push(Passage {
+            chapter,
+            paragraph,
+            paragraphs,
+        });
+    }
+
+    passages.sort_unstable_by_key(|passage| (passage.chapter, passage.paragraph));
+
+    Ok(passages)
+}
@@ -0,0 +1,818 @@
+//! The search syntax, modelled on the one AO3 uses for its own work search.
+//!
+//! ```text
+//! dragons fandom:"Harry Potter" -tag:"Major Character Death"
+//! words>50000 kudos>=1000 rating:teen..general complete:yes
+//! (ship:drarry OR ship:"Draco Malfoy/Harry Potter") author:someone
+//! ```
+//!
+//! Every clause has to match unless they're joined with `OR`, and a `-` in front of one excludes
+//! the works it matches instead. Words and quoted phrases are searched for in every text field,
+//! unless they're given a field, `tag:(fluff OR angst)` gives a whole group one.
+//!
+//! A `~` after a word lets it be misspelled, `hermoine~` allows as many typos as its field does
+//! and `hermoine~1` exactly one. Searching fuzzily does that for every word.
+//!
+//! A quoted phrase that names a tag with synonyms finds works with any of them, as if it were a
+//! group of every synonym joined with `OR`.
+//!
+//! Words are analyzed the way each field they're searched in was, so a title, summary or body
+//! word is stemmed for the works in languages that are, and `lang:de` only finds works in German.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::{Bound, Range},
+    slice,
+    str::FromStr as _,
+};
+
+use ao3fti_common::models::Rating;
+use tantivy::{
+    query::{
+        AllQuery, BooleanQuery, BoostQuery, EmptyQuery, FuzzyTermQuery, Occur, PhraseQuery, Query,
+        RangeQuery, TermQuery,
+    },
+    schema::{Field, IndexRecordOption},
+    tokenizer::Token,
+    Index, Score, Term,
+};
+
+use crate::{rating_value, Fields, Profile};
+
+/// Why a query couldn't be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The character the error starts at, counting from zero.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Every name of a tag, by each of its names, for the tags a search quotes.
+pub type Synonyms = HashMap<String, Vec<String>>;
+
+/// Parses a search and compiles it into a query over the index's fields, words without a field
+/// are searched for in `defaults`, allowed typos if `fuzzy` is set, boosted by `profile`, and
+/// quoted tags widened to their `synonyms`.
+pub fn parse(
+    index: &Index,
+    fields: &Fields,
+    defaults: &[Field],
+    fuzzy: bool,
+    profile: &Profile,
+    synonyms: &Synonyms,
+    input: &str,
+) -> Result<Box<dyn Query>, ParseError> {
+    Parser::new(index, fields, defaults, fuzzy, profile, synonyms, input).parse()
+}
+
+/// A word of a search, as it was written.
+pub(crate) struct Word {
+    /// Where it is in the search, in characters.
+    pub(crate) span: Range<usize>,
+    pub(crate) text: String,
+    /// The fields it's searched for in.
+    pub(crate) fields: Vec<Field>,
+}
+
+/// Gets every word of a search, leaving out phrases and the values of numeric fields.
+pub(crate) fn words(
+    index: &Index,
+    fields: &Fields,
+    defaults: &[Field],
+    input: &str,
+) -> Result<Vec<Word>, ParseError> {
+    let profile = Profile::default();
+    let synonyms = Synonyms::new();
+    let mut parser = Parser::new(index, fields, defaults, false, &profile, &synonyms, input);
+    parser.parse()?;
+
+    Ok(parser.words)
+}
+
+/// The aliases fields can be searched by, for the unknown field error.
+static FIELD_NAMES: &str = "`title`, `author`, `summary`, `fandom`, `ship`, `char`, `tag`, `warning`, `category`, `series`, `body`, `lang`, `rating`, `words`, `chapters`, `complete`, `comments`, `kudos`, `bookmarks` or `hits`";
+
+#[derive(Clone, Copy)]
+enum Kind<'f> {
+    /// Text, split into a field for each analyzer or not.
+    Text(&'f [Field]),
+    /// A code, matched as it's written.
+    Code(Field),
+    Number(Field),
+    Rating(Field),
+    Flag(Field),
+}
+
+fn field_kind<'f>(fields: &'f Fields, name: &str) -> Option<Kind<'f>> {
+    Some(match name {
+        "title" => Kind::Text(fields.title.all()),
+        "author" | "creator" => Kind::Text(slice::from_ref(&fields.author)),
+        "summary" => Kind::Text(fields.summary.all()),
+        "fandom" => Kind::Text(slice::from_ref(&fields.fandom)),
+        "ship" | "relationship" => Kind::Text(slice::from_ref(&fields.ship)),
+        "char" | "character" => Kind::Text(slice::from_ref(&fields.character)),
+        "tag" | "freeform" => Kind::Text(slice::from_ref(&fields.tag)),
+        "warning" => Kind::Text(slice::from_ref(&fields.warning)),
+        "category" => Kind::Text(slice::from_ref(&fields.category)),
+        "series" => Kind::Text(slice::from_ref(&fields.series)),
+        "body" => Kind::Text(fields.contents.all()),
+        "lang" | "language" => Kind::Code(fields.lang),
+        "rating" => Kind::Rating(fields.rating),
+        "words" => Kind::Number(fields.words),
+        "chapters" => Kind::Number(fields.chapters),
+        "comments" => Kind::Number(fields.comments),
+        "kudos" => Kind::Number(fields.kudos),
+        "bookmarks" => Kind::Number(fields.bookmarks),
+        "hits" => Kind::Number(fields.hits),
+        "complete" => Kind::Flag(fields.complete),
+        _ => return None,
+    })
+}
+
+/// Which fields a word or phrase is searched in.
+#[derive(Clone, Copy)]
+enum Scope<'f> {
+    Any,
+    Fields(&'f [Field]),
+}
+
+/// How many typos a word can have.
+#[derive(Clone, Copy)]
+enum Fuzzy {
+    /// None, unless the whole search is fuzzy.
+    Default,
+    /// As many as the field allows, from `~`.
+    Field,
+    /// Exactly this many, from `~1` or `~2`.
+    Distance(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Eq => ":",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        }
+    }
+}
+
+/// Matches what `query` does without adding to the score, so filters like `complete:yes` leave
+/// the text clauses' boosts the only thing a hit is ranked by.
+pub(crate) fn filter(query: Box<dyn Query>) -> Box<dyn Query> {
+    Box::new(BoostQuery::new(query, 0.0))
+}
+
+/// How deep groups can be nested, each one is parsed with a few more frames of the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Characters that end a word.
+fn is_special(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '"' | ':' | '<' | '>' | '=' | '~')
+}
+
+struct Parser<'p> {
+    index: &'p Index,
+    fields: &'p Fields,
+    defaults: &'p [Field],
+    fuzzy: bool,
+    profile: &'p Profile,
+    synonyms: &'p Synonyms,
+    chars: Vec<char>,
+    position: usize,
+    /// How many groups the parser is inside of.
+    depth: usize,
+    words: Vec<Word>,
+}
+
+impl<'p> Parser<'p> {
+    fn new(
+        index: &'p Index,
+        fields: &'p Fields,
+        defaults: &'p [Field],
+        fuzzy: bool,
+        profile: &'p Profile,
+        synonyms: &'p Synonyms,
+        input: &str,
+    ) -> Parser<'p> {
+        Parser {
+            index,
+            fields,
+            defaults,
+            fuzzy,
+            profile,
+            synonyms,
+            chars: input.chars().collect(),
+            position: 0,
+            depth: 0,
+            words: Vec::new(),
+        }
+    }
+
+    fn parse(&mut self) -> Result<Box<dyn Query>, ParseError> {
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return Ok(Box::new(EmptyQuery));
+        }
+
+        let query = self.parse_or(Scope::Any)?;
+
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(query),
+            Some(')') => Err(self.error("unexpected `)`, there's no `(` for it to close")),
+            Some(c) => Err(self.error(format!("unexpected `{}`", c))),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.position, message)
+    }
+
+    fn error_at(&self, position: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            position,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Consumes a keyword, if it's next and stands on its own.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let end = self.position + keyword.len();
+
+        let matches = self.chars.len() >= end
+            && self.chars[self.position..end]
+                .iter()
+                .copied()
+                .eq(keyword.chars())
+            && self.chars.get(end).is_none_or(|c| is_special(*c));
+
+        if matches {
+            self.position = end;
+        }
+
+        matches
+    }
+
+    fn parse_or(&mut self, scope: Scope<'p>) -> Result<Box<dyn Query>, ParseError> {
+        let mut alternatives = vec![self.parse_and(scope)?];
+
+        loop {
+            self.skip_whitespace();
+
+            let start = self.position;
+            if !self.keyword("OR") {
+                break;
+            }
+
+            self.skip_whitespace();
+            if matches!(self.peek(), None | Some(')')) {
+                return Err(self.error_at(start, "expected something to search for after `OR`"));
+            }
+
+            alternatives.push(self.parse_and(scope)?);
+        }
+
+        if alternatives.len() == 1 {
+            return Ok(alternatives.remove(0));
+        }
+
+        Ok(Box::new(BooleanQuery::new(
+            alternatives
+                .into_iter()
+                .map(|query| (Occur::Should, query))
+                .collect(),
+        )))
+    }
+
+    fn parse_and(&mut self, scope: Scope<'p>) -> Result<Box<dyn Query>, ParseError> {
+        let mut clauses = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            let start = self.position;
+            match self.peek() {
+                None | Some(')') => break,
+                _ if self.keyword("OR") => {
+                    self.position = start;
+
+                    break;
+                }
+                _ if self.keyword("AND") => continue,
+                _ => {}
+            }
+
+            let occur = match self.peek() {
+                Some('-') => {
+                    self.position += 1;
+
+                    Occur::MustNot
+                }
+                Some('+') => {
+                    self.position += 1;
+
+                    Occur::Must
+                }
+                _ => Occur::Must,
+            };
+
+            if occur == Occur::MustNot && self.peek().is_none_or(char::is_whitespace) {
+                return Err(self.error_at(start, "expected something to exclude after `-`"));
+            }
+
+            clauses.push((occur, self.parse_atom(scope)?));
+        }
+
+        if clauses.is_empty() {
+            return Err(self.error("expected something to search for"));
+        }
+
+        if clauses.len() == 1 && clauses[0].0 == Occur::Must {
+            return Ok(clauses.remove(0).1);
+        }
+
+        // a query of only exclusions excludes them from everything
+        if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    fn parse_atom(&mut self, scope: Scope<'p>) -> Result<Box<dyn Query>, ParseError> {
+        let start = self.position;
+
+        match self.peek() {
+            Some('(') => return self.parse_group(scope),
+            Some('"') => return self.parse_phrase(scope, start),
+            _ => {}
+        }
+
+        let word = self.read_word();
+        if word.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => self.error(format!("unexpected `{}`", c)),
+                None => self.error("expected something to search for"),
+            });
+        }
+
+        match self.read_op() {
+            Some(op) => self.parse_field(start, &word, op),
+            None => {
+                self.word(scope, start, &word);
+                let fuzzy = self.read_fuzzy()?;
+
+                self.text(scope, start, &word, fuzzy)
+            }
+        }
+    }
+
+    fn parse_group(&mut self, scope: Scope<'p>) -> Result<Box<dyn Query>, ParseError> {
+        let start = self.position;
+        self.position += 1;
+
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            return Err(self.error_at(start, "empty group, `()` has nothing to search for"));
+        }
+
+        if self.depth == MAX_DEPTH {
+            return Err(self.error_at(
+                start,
+                format!("groups can't be nested more than {} deep", MAX_DEPTH),
+            ));
+        }
+
+        self.depth += 1;
+        let query = self.parse_or(scope)?;
+        self.depth -= 1;
+
+        self.skip_whitespace();
+        if self.peek() != Some(')') {
+            return Err(self.error_at(start, "this `(` is never closed"));
+        }
+        self.position += 1;
+
+        Ok(query)
+    }
+
+    fn parse_field(
+        &mut self,
+        start: usize,
+        name: &str,
+        op: Op,
+    ) -> Result<Box<dyn Query>, ParseError> {
+        let kind = field_kind(self.fields, name).ok_or_else(|| {
+            self.error_at(
+                start,
+                format!("unknown field `{}`, expected {}", name, FIELD_NAMES),
+            )
+        })?;
+
+        let value_start = self.position;
+
+        if let Kind::Text(fields) = kind {
+            if op != Op::Eq {
+                return Err(self.error_at(
+                    start,
+                    format!(
+                        "`{}` is text and can't be compared with `{}`, use `{}:`",
+                        name,
+                        op.as_str(),
+                        name
+                    ),
+                ));
+            }
+
+            return match self.peek() {
+                Some('(') => self.parse_group(Scope::Fields(fields)),
+                Some('"') => self.parse_phrase(Scope::Fields(fields), value_start),
+                _ => {
+                    let word = self.value(name, op)?;
+                    self.word(Scope::Fields(fields), value_start, &word);
+                    let fuzzy = self.read_fuzzy()?;
+
+                    self.text(Scope::Fields(fields), value_start, &word, fuzzy)
+                }
+            };
+        }
+
+        let value = self.value(name, op)?;
+
+        match kind {
+            Kind::Text(_) => unreachable!("text fields were handled above"),
+            Kind::Code(field) => {
+                if op != Op::Eq {
+                    return Err(self.error_at(
+                        start,
+                        format!(
+                            "`{}` is a code and can't be compared, use `{}:`",
+                            name, name
+                        ),
+                    ));
+                }
+
+                Ok(filter(Box::new(TermQuery::new(
+                    Term::from_field_text(field, &value.to_lowercase()),
+                    IndexRecordOption::Basic,
+                ))))
+            }
+            Kind::Number(field) => {
+                let number = |value: &str| {
+                    value.replace(',', "").parse::<u64>().map_err(|_| {
+                        self.error_at(value_start, format!("`{}` isn't a number", value))
+                    })
+                };
+
+                self.compare(field, op, value_start, &value, number)
+            }
+            Kind::Rating(field) => {
+                let rating = |value: &str| {
+                    Rating::from_str(value)
+                        .ok()
+                        .and_then(|rating| rating_value(&rating))
+                        .ok_or_else(|| {
+                            self.error_at(
+                                value_start,
+                                format!(
+                                    "unknown rating `{}`, expected `general`, `teen`, `mature`, `explicit` or `not-rated`",
+                                    value
+                                ),
+                            )
+                        })
+                };
+
+                self.compare(field, op, value_start, &value, rating)
+            }
+            Kind::Flag(field) => {
+                if op != Op::Eq {
+                    return Err(self.error_at(
+                        start,
+                        format!("`{}` can only be `{}:yes` or `{}:no`", name, name, name),
+                    ));
+                }
+
+                let flag = match value.as_str() {
+                    "yes" | "true" => 1,
+                    "no" | "false" => 0,
+                    _ => {
+                        return Err(self.error_at(
+                            value_start,
+                            format!("`{}` can only be `yes` or `no`, not `{}`", name, value),
+                        ))
+                    }
+                };
+
+                Ok(filter(Box::new(TermQuery::new(
+                    Term::from_field_u64(field, flag),
+                    IndexRecordOption::Basic,
+                ))))
+            }
+        }
+    }
+
+    /// Builds a comparison, or a range for `low..high` where either end can be left off.
+    fn compare(
+        &self,
+        field: Field,
+        op: Op,
+        value_start: usize,
+        value: &str,
+        parse: impl Fn(&str) -> Result<u64, ParseError>,
+    ) -> Result<Box<dyn Query>, ParseError> {
+        let (lower, upper) = match (op, value.split_once("..")) {
+            (Op::Eq, Some((low, high))) => {
+                if low.is_empty() && high.is_empty() {
+                    return Err(
+                        self.error_at(value_start, "a range needs at least one end, ie `10..`")
+                    );
+                }
+
+                let low = (!low.is_empty()).then(|| parse(low)).transpose()?;
+                let high = (!high.is_empty()).then(|| parse(high)).transpose()?;
+
+                // ranges can be written either way around, `rating:teen..general`
+                let (low, high) = match (low, high) {
+                    (Some(low), Some(high)) if low > high => (Some(high), Some(low)),
+                    bounds => bounds,
+                };
+
+                (
+                    low.map_or(Bound::Unbounded, Bound::Included),
+                    high.map_or(Bound::Unbounded, Bound::Included),
+                )
+            }
+            (_, Some(_)) => {
+                return Err(self.error_at(
+                    value_start,
+                    format!("a range can't be compared with `{}`", op.as_str()),
+                ))
+            }
+            (op, None) => {
+                let value = parse(value)?;
+
+                match op {
+                    Op::Eq => (Bound::Included(value), Bound::Included(value)),
+                    Op::Gt => (Bound::Excluded(value), Bound::Unbounded),
+                    Op::Ge => (Bound::Included(value), Bound::Unbounded),
+                    Op::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+                    Op::Le => (Bound::Unbounded, Bound::Included(value)),
+                }
+            }
+        };
+
+        Ok(filter(Box::new(RangeQuery::new_u64_bounds(
+            field, lower, upper,
+        ))))
+    }
+
+    /// Reads the value after a field and its operator.
+    fn value(&mut self, name: &str, op: Op) -> Result<String, ParseError> {
+        let value = self.read_word();
+
+        if value.is_empty() {
+            return Err(self.error(format!("expected a value after `{}{}`", name, op.as_str())));
+        }
+
+        Ok(value)
+    }
+
+    fn read_word(&mut self) -> String {
+        let start = self.position;
+
+        while self.peek().is_some_and(|c| !is_special(c)) {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Parses a quoted phrase, or any of the names of the tag it names.
+    fn parse_phrase(
+        &mut self,
+        scope: Scope<'p>,
+        start: usize,
+    ) -> Result<Box<dyn Query>, ParseError> {
+        let phrase = self.read_phrase()?;
+
+        let synonyms = match self.synonyms.get(&phrase) {
+            Some(synonyms) if synonyms.len() > 1 => synonyms,
+            _ => return self.text(scope, start, &phrase, Fuzzy::Default),
+        };
+
+        // errors are about the phrase as it was typed, its synonyms can only widen the search
+        let mut alternatives = vec![(
+            Occur::Should,
+            self.text(scope, start, &phrase, Fuzzy::Default)?,
+        )];
+        for synonym in synonyms.iter().filter(|synonym| **synonym != phrase) {
+            if let Ok(query) = self.text(scope, start, synonym, Fuzzy::Default) {
+                alternatives.push((Occur::Should, query));
+            }
+        }
+
+        Ok(Box::new(BooleanQuery::new(alternatives)))
+    }
+
+    fn read_phrase(&mut self) -> Result<String, ParseError> {
+        let start = self.position;
+        self.position += 1;
+
+        let end = self.chars[self.position..]
+            .iter()
+            .position(|c| *c == '"')
+            .map(|end| self.position + end)
+            .ok_or_else(|| self.error_at(start, "this `\"` is never closed"))?;
+
+        let phrase = self.chars[self.position..end].iter().collect();
+        self.position = end + 1;
+
+        if self.peek() == Some('~') {
+            return Err(self.error("only single words can have typos, not phrases"));
+        }
+
+        Ok(phrase)
+    }
+
+    /// Reads a `~` after a word, and how many typos it allows if it's given.
+    fn read_fuzzy(&mut self) -> Result<Fuzzy, ParseError> {
+        if self.peek() != Some('~') {
+            return Ok(Fuzzy::Default);
+        }
+        self.position += 1;
+
+        let start = self.position;
+        let distance = self.read_word();
+
+        if distance.is_empty() {
+            return Ok(Fuzzy::Field);
+        }
+
+        match distance.parse::<u8>() {
+            Ok(distance) if distance <= 2 => Ok(Fuzzy::Distance(distance)),
+            _ => Err(self.error_at(
+                start,
+                format!(
+                    "`~{}` isn't a number of typos, expected `~`, `~1` or `~2`",
+                    distance
+                ),
+            )),
+        }
+    }
+
+    /// Reads the operator after a field name, if there is one.
+    fn read_op(&mut self) -> Option<Op> {
+        let (op, len) = match (self.peek()?, self.chars.get(self.position + 1)) {
+            (':', _) | ('=', _) => (Op::Eq, 1),
+            ('>', Some('=')) => (Op::Ge, 2),
+            ('>', _) => (Op::Gt, 1),
+            ('<', Some('=')) => (Op::Le, 2),
+            ('<', _) => (Op::Lt, 1),
+            _ => return None,
+        };
+
+        self.position += len;
+
+        Some(op)
+    }
+
+    fn scope_fields(&self, scope: Scope<'p>) -> Vec<Field> {
+        match scope {
+            Scope::Any => self.defaults.to_vec(),
+            Scope::Fields(fields) => fields.to_vec(),
+        }
+    }
+
+    /// Remembers a word that was read, for [`words`].
+    fn word(&mut self, scope: Scope<'p>, start: usize, text: &str) {
+        self.words.push(Word {
+            span: start..start + text.chars().count(),
+            text: text.to_string(),
+            fields: self.scope_fields(scope),
+        });
+    }
+
+    /// Searches for a word or phrase, as it would have been tokenized when indexed.
+    fn text(
+        &self,
+        scope: Scope<'p>,
+        start: usize,
+        text: &str,
+        fuzzy: Fuzzy,
+    ) -> Result<Box<dyn Query>, ParseError> {
+        let fields = self.scope_fields(scope);
+
+        let mut queries = Vec::with_capacity(fields.len());
+
+        for field in fields {
+            let analyzer = self
+                .index
+                .tokenizer_for_field(field)
+                .map_err(|err| self.error_at(start, err.to_string()))?;
+
+            let mut terms = Vec::new();
+            analyzer.token_stream(text).process(&mut |token: &Token| {
+                terms.push((token.position, Term::from_field_text(field, &token.text)))
+            });
+
+            let query: Box<dyn Query> = match terms.len() {
+                // only words too common to be indexed, which they might not be in other fields
+                0 => continue,
+                1 => self.term(field, terms.remove(0).1, fuzzy),
+                _ if !matches!(fuzzy, Fuzzy::Default) => {
+                    return Err(self.error_at(
+                        start,
+                        format!(
+                            "`{}` is more than one word, only single words can have typos",
+                            text
+                        ),
+                    ))
+                }
+                // left out words still take up their place in a phrase
+                _ => Box::new(PhraseQuery::new_with_offset(terms)),
+            };
+
+            let boost = self.profile.boost(self.fields, field);
+            let query: Box<dyn Query> = if (boost - 1.0).abs() > Score::EPSILON {
+                Box::new(BoostQuery::new(query, boost))
+            } else {
+                query
+            };
+
+            queries.push((Occur::Should, query));
+        }
+
+        if queries.is_empty() {
+            return Err(self.error_at(start, format!("`{}` has nothing to search for", text)));
+        }
+
+        if queries.len() == 1 {
+            return Ok(queries.remove(0).1);
+        }
+
+        Ok(Box::new(BooleanQuery::new(queries)))
+    }
+
+    fn term(&self, field: Field, term: Term, fuzzy: Fuzzy) -> Box<dyn Query> {
+        // short words are too easily turned into other words to allow them many typos
+        let typos = |allowed: u8| match term.as_str().map_or(0, |text| text.chars().count()) {
+            0..=3 => 0,
+            4..=5 => allowed.min(1),
+            _ => allowed,
+        };
+
+        let distance = match fuzzy {
+            Fuzzy::Distance(distance) => distance,
+            Fuzzy::Field => typos(self.fields.fuzzy_distance(field)),
+            Fuzzy::Default if self.fuzzy => typos(self.fields.fuzzy_distance(field)),
+            Fuzzy::Default => 0,
+        };
+
+        let exact = Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs));
+
+        if distance == 0 {
+            return exact;
+        }
+
+        // every fuzzy match scores the same, so exact matches, which also score for the term
+        // itself, always rank above them
+        Box::new(BooleanQuery::new(vec![
+            (Occur::Should, exact),
+            (
+                Occur::Should,
+                Box::new(FuzzyTermQuery::new(term, distance, true)),
+            ),
+        ]))
+    }
+}
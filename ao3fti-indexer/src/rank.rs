@@ -0,0 +1,213 @@
+//! How search hits are ranked, picked per search by the name of a profile.
+//!
+//! A profile boosts matches in some fields over others, so a word in a title counts for more
+//! than the same word somewhere in a body, and can mix a work's popularity in with how well it
+//! matched. Popularity is the log of a work's kudos, hits or bookmarks, so a work with a
+//! thousand kudos ranks above one with ten without burying every other match under it.
+//!
+//! There are built in profiles, `relevance`, `popular` and `plain`, and more can be added, or
+//! the built in ones changed, with a JSON file of profiles by name:
+//!
+//! ```json
+//! { "bookmarked": { "title": 3.0, "summary": 2.0, "popularity": 0.3, "popularity_by": "bookmarks" } }
+//! ```
+
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use ao3fti_common::{bail, Context as _};
+use tantivy::{
+    collector::TopDocs, fastfield::FastFieldReader as _, schema::Field, DocId, Score, SegmentReader,
+};
+
+use crate::Fields;
+
+/// What a work's popularity is measured by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Popularity {
+    Kudos,
+    Hits,
+    Bookmarks,
+}
+
+impl Popularity {
+    fn field(self, fields: &Fields) -> Field {
+        match self {
+            Popularity::Kudos => fields.kudos,
+            Popularity::Hits => fields.hits,
+            Popularity::Bookmarks => fields.bookmarks,
+        }
+    }
+}
+
+/// How much matches in each field count, and how much popularity does.
+#[derive(Clone, Debug, PartialEq)]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Profile {
+    pub title: Score,
+    pub summary: Score,
+    /// Authors, fandoms, relationships, characters, tags, warnings, categories and series.
+    pub tags: Score,
+    pub contents: Score,
+    /// How much popularity multiplies a score by, zero leaves it out.
+    pub popularity: Score,
+    pub popularity_by: Popularity,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            title: 1.0,
+            summary: 1.0,
+            tags: 1.0,
+            contents: 1.0,
+            popularity: 0.0,
+            popularity_by: Popularity::Kudos,
+        }
+    }
+}
+
+impl Profile {
+    /// How much a match in a field counts.
+    pub(crate) fn boost(&self, fields: &Fields, field: Field) -> Score {
+        if fields.title.all().contains(&field) {
+            self.title
+        } else if fields.summary.all().contains(&field) {
+            self.summary
+        } else if fields.contents.all().contains(&field) {
+            self.contents
+        } else {
+            self.tags
+        }
+    }
+
+    /// Ranks the hits of a search, mixing in popularity when the profile has any.
+    pub(crate) fn collector(
+        &self,
+        fields: &Fields,
+        limit: usize,
+        offset: usize,
+    ) -> impl tantivy::collector::Collector<Fruit = Vec<(Score, tantivy::DocAddress)>> {
+        let weight = self.popularity;
+        let field = self.popularity_by.field(fields);
+
+        TopDocs::with_limit(limit).and_offset(offset).tweak_score(
+            move |segment_reader: &SegmentReader| {
+                let reader = (weight != 0.0)
+                    .then(|| segment_reader.fast_fields().u64(field).ok())
+                    .flatten();
+
+                move |doc: DocId, score: Score| match &reader {
+                    Some(reader) => popular(score, weight, reader.get(doc)),
+                    None => score,
+                }
+            },
+        )
+    }
+}
+
+/// A score, scaled up by how popular a work is.
+pub(crate) fn popular(score: Score, weight: Score, popularity: u64) -> Score {
+    score * (1.0 + weight * (popularity as Score).ln_1p())
+}
+
+/// A profile that was asked for but doesn't exist.
+#[derive(Debug)]
+pub struct UnknownProfile {
+    pub name: String,
+    pub known: Vec<String>,
+}
+
+impl fmt::Display for UnknownProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown ranking profile `{}`, expected `{}`",
+            self.name,
+            self.known.join("`, `")
+        )
+    }
+}
+
+impl std::error::Error for UnknownProfile {}
+
+/// Every profile by name, and the one searches use when they don't pick one.
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    profiles: BTreeMap<String, Profile>,
+    default: String,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        let relevance = Profile {
+            title: 3.0,
+            summary: 2.0,
+            tags: 1.5,
+            ..Profile::default()
+        };
+
+        let popular = Profile {
+            popularity: 0.2,
+            ..relevance.clone()
+        };
+
+        Profiles {
+            profiles: BTreeMap::from([
+                ("relevance".to_string(), relevance),
+                ("popular".to_string(), popular),
+                ("plain".to_string(), Profile::default()),
+            ]),
+            default: "relevance".to_string(),
+        }
+    }
+}
+
+impl Profiles {
+    /// Adds the profiles of a JSON file to the built in ones, and picks the default one.
+    pub fn load(path: Option<&Path>, default: &str) -> Result<Profiles, ao3fti_common::Report> {
+        let mut profiles = Profiles::default();
+
+        if let Some(path) = path {
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("unable to read ranking profiles `{}`", path.display()))?;
+            let added: BTreeMap<String, Profile> =
+                serde_json::from_str(&json).with_context(|| {
+                    format!("unable to parse ranking profiles `{}`", path.display())
+                })?;
+
+            profiles.profiles.extend(added);
+        }
+
+        if !profiles.profiles.contains_key(default) {
+            bail!("{}", profiles.unknown(default));
+        }
+        profiles.default = default.to_string();
+
+        Ok(profiles)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    /// Gets a profile by name, or the default one.
+    pub fn get(&self, name: Option<&str>) -> Result<&Profile, UnknownProfile> {
+        let name = name.unwrap_or(&self.default);
+
+        self.profiles.get(name).ok_or_else(|| self.unknown(name))
+    }
+
+    fn unknown(&self, name: &str) -> UnknownProfile {
+        UnknownProfile {
+            name: name.to_string(),
+            known: self.names().map(str::to_string).collect(),
+        }
+    }
+}
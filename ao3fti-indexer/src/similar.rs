@@ -0,0 +1,195 @@
+//! "Similar works", found by the words and names that set a work apart.
+//!
+//! A work's summary and body come from the index, and its title, fandoms, relationships,
+//! characters, tags and series from the database, as the index only keeps the text it makes
+//! snippets from. The terms of each that are most common in the work and rarest in the index are
+//! searched for, each counting for as much as it sets the work apart.
+
+use std::sync::Arc;
+
+use ao3fti_common::{models::Story, timer::TimerTree};
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, MoreLikeThisQuery, Occur, PhraseQuery, Query, TermQuery},
+    schema::{Field, IndexRecordOption},
+    tokenizer::Token,
+    Document, Score, Term,
+};
+
+use crate::{read_passages, u64_query, Analyzer, IndexServer, Value};
+
+/// How many of a work's terms are searched for at most.
+const MAX_QUERY_TERMS: usize = 40;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SimilarQuery {
+    pub limit: usize,
+    /// Leave out works by any of the work's authors.
+    #[serde(default)]
+    pub exclude_authors: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SimilarSerp {
+    /// The work the others are like.
+    pub id: u64,
+    pub works: Vec<SimilarWork>,
+    pub timings: TimerTree,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SimilarWork {
+    pub id: u64,
+    pub score: Score,
+}
+
+/// Finds the works most like a story, leaving out the story itself.
+pub fn similar(
+    index: Arc<IndexServer>,
+    story: &Story,
+    search: SimilarQuery,
+) -> Result<SimilarSerp, ao3fti_common::Report> {
+    let searcher = index.reader.searcher();
+    let mut timer_tree = TimerTree::default();
+
+    let fields = &index.fields;
+    let story_id = story.id as u64;
+
+    let doc = {
+        let _fetching_timer = timer_tree.open("fetching work");
+
+        let query = BooleanQuery::new(vec![
+            (Occur::Must, u64_query(fields.id, story_id)),
+            (Occur::Must, u64_query(fields.passage, 0)),
+        ]);
+
+        match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => Some(searcher.doc(*doc_address)?),
+            None => None,
+        }
+    };
+
+    // the text is only stored on the passages
+    let body = read_passages(&index, &searcher, story_id)?
+        .into_iter()
+        .flat_map(|passage| passage.paragraphs)
+        .map(Value::Str)
+        .collect::<Vec<_>>();
+
+    // a work that was stored but hasn't been indexed still has its names
+    let analyzer = doc
+        .as_ref()
+        .map_or(Analyzer::Default, |doc| fields.analyzer(doc));
+    let stored = |field: Field| -> Vec<Value> {
+        doc.as_ref()
+            .map(|doc: &Document| doc.get_all(field).cloned().collect())
+            .unwrap_or_default()
+    };
+    let names = |names: Vec<&String>| -> Vec<Value> {
+        names
+            .into_iter()
+            .map(|name| Value::Str(name.clone()))
+            .collect()
+    };
+
+    let doc_fields = vec![
+        (fields.title.get(analyzer), names(vec![&story.name])),
+        (
+            fields.summary.get(analyzer),
+            stored(fields.summary.get(analyzer)),
+        ),
+        (fields.contents.get(analyzer), body),
+        (
+            fields.fandom,
+            names(story.origins.iter().map(|tag| &tag.name).collect()),
+        ),
+        (
+            fields.ship,
+            names(story.pairings.iter().map(|tag| &tag.name).collect()),
+        ),
+        (
+            fields.character,
+            names(story.characters.iter().map(|tag| &tag.name).collect()),
+        ),
+        (
+            fields.tag,
+            names(story.generals.iter().map(|tag| &tag.name).collect()),
+        ),
+        (
+            fields.series,
+            names(story.series.iter().map(|part| &part.name).collect()),
+        ),
+    ];
+
+    // terms only the work itself has can't find anything else
+    let like_this = MoreLikeThisQuery::builder()
+        .with_min_doc_frequency(2)
+        .with_min_term_frequency(1)
+        .with_min_word_length(3)
+        .with_max_query_terms(MAX_QUERY_TERMS)
+        .with_document_fields(doc_fields);
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+        (Occur::Must, Box::new(like_this)),
+        (Occur::Must, u64_query(fields.passage, 0)),
+        (Occur::MustNot, u64_query(fields.id, story_id)),
+    ];
+
+    if search.exclude_authors {
+        // orphaned works all share the same account, which says nothing about who wrote them
+        for author in story.authors.iter().filter(|author| !author.is_orphaned()) {
+            for name in [&author.pseud, &author.user] {
+                if let Some(query) = author_query(&index, name)? {
+                    clauses.push((Occur::MustNot, query));
+                }
+            }
+        }
+    }
+
+    let query = BooleanQuery::new(clauses);
+
+    let works = {
+        let _search_timer = timer_tree.open("search");
+
+        let mut works = Vec::with_capacity(search.limit);
+
+        for (score, doc_address) in searcher.search(&query, &TopDocs::with_limit(search.limit))? {
+            let doc: Document = searcher.doc(doc_address)?;
+
+            if let Some(id) = doc.get_first(fields.id).and_then(Value::as_u64) {
+                works.push(SimilarWork { id, score });
+            }
+        }
+
+        works
+    };
+
+    Ok(SimilarSerp {
+        id: story_id,
+        works,
+        timings: timer_tree,
+    })
+}
+
+/// Matches an author's name as a whole, rather than any work by someone sharing a word of it.
+fn author_query(
+    index: &IndexServer,
+    name: &str,
+) -> Result<Option<Box<dyn Query>>, ao3fti_common::Report> {
+    let field = index.fields.author;
+    let analyzer = index.index.tokenizer_for_field(field)?;
+
+    let mut terms = Vec::new();
+    analyzer
+        .token_stream(name)
+        .process(&mut |token: &Token| terms.push(Term::from_field_text(field, &token.text)));
+
+    Ok(match terms.len() {
+        0 => None,
+        1 => Some(Box::new(TermQuery::new(
+            terms.remove(0),
+            IndexRecordOption::Basic,
+        ))),
+        _ => Some(Box::new(PhraseQuery::new(terms))),
+    })
+}
@@ -0,0 +1,246 @@
+//! "Did you mean" suggestions, for searches that find little or nothing.
+//!
+//! Each word of a search is looked up in the term dictionaries of the fields it searches, and
+//! words close to it that are more common than it are offered in its place. Only the
+//! suggestions that find more than the search did are kept.
+//!
+//! Fields indexed as stems are left out, a stem isn't a word anyone would have meant to write.
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use tantivy::{collector::Count, schema::Field, tokenizer::Token, Searcher};
+
+use crate::{escape, query, story_query, IndexServer, Profile, Synonyms};
+
+/// How many searches are suggested at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// A search to try instead.
+#[derive(Debug, serde::Serialize)]
+pub struct Suggestion {
+    pub query: String,
+    /// The escaped search, with every corrected word wrapped in a `<mark>`.
+    pub html: String,
+    pub num_hits: usize,
+}
+
+struct Correction {
+    term: String,
+    distance: usize,
+    doc_freq: u64,
+}
+
+pub(crate) fn suggestions(
+    index: &IndexServer,
+    searcher: &Searcher,
+    input: &str,
+    fuzzy: bool,
+    profile: &Profile,
+    synonyms: &Synonyms,
+    num_hits: usize,
+) -> Result<Vec<Suggestion>, ao3fti_common::Report> {
+    let words = query::words(&index.index, &index.fields, &index.fields.text(), input)?;
+
+    let mut corrected = Vec::new();
+    for word in words {
+        let corrections = corrections(index, searcher, &word.text, &word.fields)?;
+
+        if !corrections.is_empty() {
+            corrected.push((word, corrections));
+        }
+    }
+
+    if corrected.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // every word's best correction first, then each word's next best on their own
+    let best = vec![0; corrected.len()];
+    let mut choices = vec![best.clone()];
+    for (i, (_, corrections)) in corrected.iter().enumerate() {
+        for rank in 1..corrections.len() {
+            let mut choice = best.clone();
+            choice[i] = rank;
+
+            choices.push(choice);
+        }
+    }
+
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut suggestions = Vec::with_capacity(MAX_SUGGESTIONS);
+
+    for choice in choices {
+        let mut query = String::with_capacity(input.len());
+        let mut html = String::with_capacity(input.len() * 2);
+        let mut end = 0;
+
+        for ((word, corrections), rank) in corrected.iter().zip(choice) {
+            let before = chars[end..word.span.start].iter().collect::<String>();
+            let term = &corrections[rank].term;
+
+            query.push_str(&before);
+            query.push_str(term);
+
+            escape(&mut html, &before);
+            html.push_str("<mark>");
+            escape(&mut html, term);
+            html.push_str("</mark>");
+
+            end = word.span.end;
+        }
+
+        let rest = chars[end..].iter().collect::<String>();
+        query.push_str(&rest);
+        escape(&mut html, &rest);
+
+        let suggested_hits = searcher.search(
+            &story_query(index, &query, fuzzy, profile, synonyms)?,
+            &Count,
+        )?;
+        if suggested_hits <= num_hits {
+            continue;
+        }
+
+        suggestions.push(Suggestion {
+            query,
+            html,
+            num_hits: suggested_hits,
+        });
+
+        if suggestions.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+
+    Ok(suggestions)
+}
+
+/// Finds the words a word could have been meant to be, closest and then most common first.
+fn corrections(
+    index: &IndexServer,
+    searcher: &Searcher,
+    text: &str,
+    fields: &[Field],
+) -> Result<Vec<Correction>, ao3fti_common::Report> {
+    let mut found = HashMap::<String, (usize, u64)>::new();
+    let mut doc_freq = 0;
+
+    for field in fields {
+        if index.fields.stems(*field) {
+            continue;
+        }
+
+        let analyzer = index.index.tokenizer_for_field(*field)?;
+
+        let mut tokens = Vec::new();
+        analyzer
+            .token_stream(text)
+            .process(&mut |token: &Token| tokens.push(token.text.clone()));
+
+        let token = match tokens.as_slice() {
+            [token] => token,
+            _ => continue,
+        };
+
+        // short words are too easily turned into other words to be corrected
+        let max_distance = match token.chars().count() {
+            0..=3 => continue,
+            4..=5 => 1,
+            _ => 2,
+        };
+
+        // typos are rarely in the first letter, which saves going through the whole dictionary
+        let prefix = match token.chars().next() {
+            Some(first) => first.to_string(),
+            None => continue,
+        };
+
+        for segment in searcher.segment_readers() {
+            let inverted_index = segment.inverted_index(*field)?;
+            let mut terms = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream()?;
+
+            while terms.advance() {
+                if !terms.key().starts_with(prefix.as_bytes()) {
+                    break;
+                }
+
+                let term = match std::str::from_utf8(terms.key()) {
+                    Ok(term) => term,
+                    Err(_) => continue,
+                };
+                let term_freq = u64::from(terms.value().doc_freq);
+
+                if term == token {
+                    doc_freq += term_freq;
+
+                    continue;
+                }
+
+                let length = term.chars().count();
+                if length.abs_diff(token.chars().count()) > max_distance {
+                    continue;
+                }
+
+                let distance = edit_distance(token, term);
+                if distance <= max_distance {
+                    found.entry(term.to_string()).or_insert((distance, 0)).1 += term_freq;
+                }
+            }
+        }
+    }
+
+    // a word that's already common isn't a typo for a rarer one
+    let mut corrections = found
+        .into_iter()
+        .filter(|(_, (_, term_freq))| *term_freq > doc_freq)
+        .map(|(term, (distance, doc_freq))| Correction {
+            term,
+            distance,
+            doc_freq,
+        })
+        .collect::<Vec<_>>();
+
+    corrections.sort_by(|a, b| {
+        (a.distance, Reverse(a.doc_freq), &a.term).cmp(&(b.distance, Reverse(b.doc_freq), &b.term))
+    });
+    corrections.truncate(MAX_SUGGESTIONS);
+
+    Ok(corrections)
+}
+
+/// The number of insertions, deletions, substitutions and swaps of neighbouring characters it
+/// takes to turn one word into another.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
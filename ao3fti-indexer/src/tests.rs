@@ -0,0 +1,1077 @@
+//! Searches against a small in memory index.
+
+use std::sync::Arc;
+
+use ao3fti_common::models::{Author, Entity, Rating, SeriesPart, Story};
+use tantivy::Index;
+
+use crate::{
+    analyzer, duplicates, passages, query, rank::popular, schema, serp, similar, similarity,
+    story_text, suggest::edit_distance, Analyzer, DuplicatesQuery, Fields, IndexServer,
+    InvalidSimilarity, Profile, Profiles, SearchQuery, SimilarQuery, StoryData, Synonyms,
+    UnknownProfile, Value,
+};
+
+fn story(id: usize) -> StoryData {
+    StoryData {
+        id,
+        language: None,
+        title: String::new(),
+        authors: Vec::new(),
+        summary: String::new(),
+        fandoms: Vec::new(),
+        ships: Vec::new(),
+        characters: Vec::new(),
+        tags: Vec::new(),
+        warnings: Vec::new(),
+        categories: Vec::new(),
+        series: Vec::new(),
+        rating: Rating::Unknown,
+        words: 0,
+        chapters: 1,
+        complete: true,
+        comments: 0,
+        kudos: 0,
+        bookmarks: 0,
+        hits: 0,
+        contents: Vec::new(),
+    }
+}
+
+fn index(stories: Vec<StoryData>) -> Arc<IndexServer> {
+    let index = Index::create_in_ram(schema());
+    analyzer::register(&index);
+    let fields = Fields::new(&index.schema()).unwrap();
+
+    let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+    for story in stories {
+        for doc in fields.documents(story) {
+            writer.add_document(doc).unwrap();
+        }
+    }
+    writer.commit().unwrap();
+
+    Arc::new(IndexServer {
+        schema: index.schema(),
+        reader: index.reader().unwrap(),
+        index,
+        fields,
+        profiles: Profiles::default(),
+    })
+}
+
+fn library() -> Arc<IndexServer> {
+    index(vec![
+        StoryData {
+            title: "Dragon Riders".to_string(),
+            authors: vec!["someone".to_string()],
+            fandoms: vec!["Harry Potter - J. K. Rowling".to_string()],
+            ships: vec!["Draco Malfoy/Harry Potter".to_string()],
+            characters: vec!["Harry Potter".to_string(), "Draco Malfoy".to_string()],
+            tags: vec!["Fluff".to_string()],
+            warnings: vec!["No Archive Warnings Apply".to_string()],
+            rating: Rating::General,
+            words: 1_200,
+            kudos: 40,
+            contents: vec![vec!["The dragons circled the castle.".to_string()]],
+            ..story(1)
+        },
+        StoryData {
+            title: "Aftermath".to_string(),
+            authors: vec!["Pen Name".to_string(), "writer".to_string()],
+            summary: "After the war, <everyone> & their owls move on.".to_string(),
+            fandoms: vec!["Harry Potter - J. K. Rowling".to_string()],
+            characters: vec!["Harry Potter".to_string()],
+            tags: vec!["Angst".to_string()],
+            warnings: vec!["Major Character Death".to_string()],
+            rating: Rating::Mature,
+            words: 85_000,
+            chapters: 12,
+            complete: false,
+            kudos: 2_500,
+            contents: vec![vec!["Nobody expected the war to end like this.".to_string()]],
+            ..story(2)
+        },
+        StoryData {
+            title: "Tea".to_string(),
+            authors: vec!["someone".to_string()],
+            fandoms: vec!["Original Work".to_string()],
+            tags: vec!["Fluff".to_string(), "Slice of Life".to_string()],
+            rating: Rating::Teen,
+            words: 50_000,
+            kudos: 1_000,
+            contents: vec![vec!["A quiet afternoon with tea.".to_string()]],
+            ..story(3)
+        },
+    ])
+}
+
+/// The ids of the stories a search finds, best first.
+fn ranked(index: &Arc<IndexServer>, query: &str, fuzzy: bool) -> Vec<u64> {
+    let serp = serp(
+        index.clone(),
+        SearchQuery {
+            query: query.to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap_or_else(|err| panic!("`{}` failed: {:?}", query, err));
+
+    serp.hits
+        .into_iter()
+        .filter_map(
+            |hit| match hit.doc.0.get("id").and_then(|values| values.first()) {
+                Some(Value::U64(id)) => Some(*id),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+fn search(index: &Arc<IndexServer>, query: &str) -> Vec<u64> {
+    let mut ids = ranked(index, query, false);
+
+    ids.sort_unstable();
+
+    ids
+}
+
+fn error(query: &str) -> (usize, String) {
+    let index = library();
+
+    match query::parse(
+        &index.index,
+        &index.fields,
+        &index.fields.text(),
+        false,
+        &Profile::default(),
+        &Synonyms::new(),
+        query,
+    ) {
+        Ok(parsed) => panic!("`{}` should not parse, got {:?}", query, parsed),
+        Err(err) => (err.position, err.message),
+    }
+}
+
+#[test]
+fn terms_are_all_required() {
+    let index = library();
+
+    assert_eq!(search(&index, "harry"), vec![1, 2]);
+    assert_eq!(search(&index, "harry dragons"), vec![1]);
+    assert_eq!(search(&index, "harry OR tea"), vec![1, 2, 3]);
+    assert_eq!(search(&index, "harry AND (angst OR tea)"), vec![2]);
+    assert_eq!(search(&index, "\"the war\""), vec![2]);
+    assert_eq!(search(&index, ""), Vec::<u64>::new());
+}
+
+#[test]
+fn fields_have_aliases() {
+    let index = library();
+
+    assert_eq!(search(&index, "fandom:\"harry potter\""), vec![1, 2]);
+    assert_eq!(search(&index, "ship:draco"), vec![1]);
+    assert_eq!(search(&index, "char:draco"), vec![1]);
+    assert_eq!(search(&index, "tag:fluff"), vec![1, 3]);
+    assert_eq!(
+        search(&index, "tag:(angst OR \"slice of life\")"),
+        vec![2, 3]
+    );
+    assert_eq!(search(&index, "author:writer"), vec![2]);
+    assert_eq!(search(&index, "title:tea"), vec![3]);
+    assert_eq!(search(&index, "body:tea"), vec![3]);
+    assert_eq!(search(&index, "title:dragons"), Vec::<u64>::new());
+}
+
+#[test]
+fn clauses_can_be_excluded() {
+    let index = library();
+
+    assert_eq!(
+        search(&index, "harry -warning:\"Major Character Death\""),
+        vec![1]
+    );
+    assert_eq!(search(&index, "-tag:fluff"), vec![2]);
+    assert_eq!(search(&index, "-(tag:fluff OR harry)"), Vec::<u64>::new());
+}
+
+#[test]
+fn numbers_are_compared() {
+    let index = library();
+
+    assert_eq!(search(&index, "words>50000"), vec![2]);
+    assert_eq!(search(&index, "words>=50000"), vec![2, 3]);
+    assert_eq!(search(&index, "words<50,000"), vec![1]);
+    assert_eq!(search(&index, "words<=1200"), vec![1]);
+    assert_eq!(search(&index, "kudos:1000"), vec![3]);
+    assert_eq!(search(&index, "kudos=1000"), vec![3]);
+    assert_eq!(search(&index, "kudos:100..2000"), vec![3]);
+    assert_eq!(search(&index, "kudos:1000.."), vec![2, 3]);
+    assert_eq!(search(&index, "chapters>1"), vec![2]);
+}
+
+#[test]
+fn ratings_are_ranges() {
+    let index = library();
+
+    assert_eq!(search(&index, "rating:teen"), vec![3]);
+    assert_eq!(search(&index, "rating:teen..general"), vec![1, 3]);
+    assert_eq!(search(&index, "rating:general..teen"), vec![1, 3]);
+    assert_eq!(search(&index, "rating>teen"), vec![2]);
+    assert_eq!(search(&index, "rating:not-rated"), Vec::<u64>::new());
+}
+
+#[test]
+fn completion_is_a_flag() {
+    let index = library();
+
+    assert_eq!(search(&index, "complete:yes"), vec![1, 3]);
+    assert_eq!(search(&index, "complete:no"), vec![2]);
+}
+
+#[test]
+fn typos_are_opt_in() {
+    let index = library();
+
+    assert_eq!(search(&index, "dargons"), Vec::<u64>::new());
+    assert_eq!(search(&index, "dargons~"), vec![1]);
+    assert_eq!(search(&index, "dargons~1"), vec![1]);
+    assert_eq!(search(&index, "dargons~0"), Vec::<u64>::new());
+    assert_eq!(search(&index, "char:malfoi~"), vec![1]);
+
+    let mut fuzzy = ranked(&index, "malfoi aftermath~ OR dargons", true);
+    fuzzy.sort_unstable();
+    assert_eq!(fuzzy, vec![1]);
+
+    // short words would match too much with typos
+    assert_eq!(ranked(&index, "tee", true), Vec::<u64>::new());
+}
+
+#[test]
+fn typos_rank_below_exact_matches() {
+    let index = index(vec![
+        StoryData {
+            title: "Hairy".to_string(),
+            ..story(1)
+        },
+        StoryData {
+            title: "Harry".to_string(),
+            ..story(2)
+        },
+        StoryData {
+            title: "Larry".to_string(),
+            ..story(3)
+        },
+    ]);
+
+    assert_eq!(ranked(&index, "harry", false), vec![2]);
+
+    let fuzzy = ranked(&index, "harry", true);
+    assert_eq!(fuzzy[0], 2);
+    assert_eq!(fuzzy.len(), 3);
+}
+
+fn suggested(index: &Arc<IndexServer>, query: &str) -> Vec<String> {
+    serp(
+        index.clone(),
+        SearchQuery {
+            query: query.to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap_or_else(|err| panic!("`{}` failed: {:?}", query, err))
+    .suggestions
+    .into_iter()
+    .map(|suggestion| suggestion.query)
+    .collect()
+}
+
+#[test]
+fn typos_get_suggestions() {
+    let index = library();
+
+    assert_eq!(suggested(&index, "dargons")[0], "dragons");
+    assert_eq!(suggested(&index, "harry dargons")[0], "harry dragons");
+    assert_eq!(suggested(&index, "tag:flufy"), vec!["tag:fluff"]);
+    assert_eq!(suggested(&index, "flufy harry"), vec!["fluff harry"]);
+    assert_eq!(suggested(&index, "harry"), Vec::<String>::new());
+    assert_eq!(suggested(&index, "zzzzzz"), Vec::<String>::new());
+
+    let serp = serp(
+        index,
+        SearchQuery {
+            query: "dargons <".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    );
+    assert!(
+        serp.is_err(),
+        "searches that don't parse get an error, not suggestions"
+    );
+}
+
+#[test]
+fn suggestions_mark_corrections() {
+    let index = library();
+
+    let serp = serp(
+        index,
+        SearchQuery {
+            query: "tag:flufy  dargons".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(serp.num_hits, 0);
+    assert_eq!(serp.suggestions[0].query, "tag:fluff  dragons");
+    assert_eq!(
+        serp.suggestions[0].html,
+        "tag:<mark>fluff</mark>  <mark>dragons</mark>"
+    );
+    assert_eq!(serp.suggestions[0].num_hits, 1);
+}
+
+#[test]
+fn edit_distances_count_swaps() {
+    assert_eq!(edit_distance("dragons", "dargons"), 1);
+    assert_eq!(edit_distance("harry", "hairy"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "tea"), 3);
+    assert_eq!(edit_distance("tea", "tea"), 0);
+}
+
+#[test]
+fn snippets_mark_matches() {
+    let index = library();
+
+    let serp = serp(
+        index,
+        SearchQuery {
+            query: "war -title:tea".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(serp.hits.len(), 1);
+
+    let hit = &serp.hits[0];
+    assert!(
+        !hit.doc.0.contains_key("contents"),
+        "the body shouldn't be sent with every hit"
+    );
+
+    let fields = hit
+        .snippets
+        .iter()
+        .map(|snippet| snippet.field)
+        .collect::<Vec<_>>();
+    assert_eq!(fields, vec!["summary", "contents"]);
+
+    let summary = &hit.snippets[0].html;
+    assert!(
+        summary.starts_with("After the <mark>war</mark>, &lt;everyone&gt; &amp; their owls"),
+        "{}",
+        summary
+    );
+
+    let body = &hit.snippets[1].html;
+    assert!(
+        body.starts_with("Nobody expected the <mark>war</mark> to end"),
+        "{}",
+        body
+    );
+
+    let summary = &hit.snippets[0];
+    assert_eq!(
+        summary
+            .highlighted
+            .iter()
+            .map(|range| &summary.fragment[range.clone()])
+            .collect::<Vec<_>>(),
+        vec!["war"]
+    );
+}
+
+#[test]
+fn errors_point_at_the_problem() {
+    assert_eq!(error("tag:\"Major").0, 4);
+    assert!(error("tag:\"Major").1.contains("never closed"));
+
+    let (position, message) = error("fluff foo:bar");
+    assert_eq!(position, 6);
+    assert!(message.starts_with("unknown field `foo`"), "{}", message);
+
+    assert_eq!(
+        error("words>lots"),
+        (6, "`lots` isn't a number".to_string())
+    );
+    assert_eq!(error("tea (harry OR dragons").0, 4);
+    assert_eq!(error("tea )").0, 4);
+    assert_eq!(error("words:").0, 6);
+    assert_eq!(error("harry OR").0, 6);
+    assert_eq!(error("harry - tea").0, 6);
+    assert_eq!(error("()").0, 0);
+
+    let (position, message) = error("tag>3");
+    assert_eq!(position, 0);
+    assert!(message.contains("can't be compared"), "{}", message);
+
+    let (position, message) = error("rating:teen..adult");
+    assert_eq!(position, 7);
+    assert!(message.starts_with("unknown rating `adult`"), "{}", message);
+
+    let (position, message) = error("harry~3");
+    assert_eq!(position, 6);
+    assert!(message.contains("isn't a number of typos"), "{}", message);
+
+    let (position, message) = error("\"the war\"~1");
+    assert_eq!(position, 9);
+    assert!(message.contains("not phrases"), "{}", message);
+
+    let (position, message) = error("complete:maybe");
+    assert_eq!(position, 9);
+    assert!(message.contains("`yes` or `no`"), "{}", message);
+
+    let (position, message) = error(&"(".repeat(100_000));
+    assert_eq!(position, 32);
+    assert!(message.contains("nested more than 32 deep"), "{}", message);
+    assert_eq!(error(&"tag:(".repeat(100)).0, 32 * 5 + 4);
+
+    assert_eq!(
+        query::parse(
+            &library().index,
+            &library().fields,
+            &library().fields.text(),
+            false,
+            &Profile::default(),
+            &Synonyms::new(),
+            "words>lots"
+        )
+        .unwrap_err()
+        .to_string(),
+        "`lots` isn't a number, at column 7"
+    );
+}
+
+#[test]
+fn quoted_tags_find_their_synonyms() {
+    let index = library();
+    let synonyms = Synonyms::from([(
+        "Comfort".to_string(),
+        vec!["Comfort".to_string(), "Fluff".to_string()],
+    )]);
+    let parse = |query: &str| {
+        query::parse(
+            &index.index,
+            &index.fields,
+            &index.fields.text(),
+            false,
+            &Profile::default(),
+            &synonyms,
+            query,
+        )
+    };
+
+    assert_eq!(search(&index, "tag:\"Comfort\""), Vec::<u64>::new());
+
+    let mut ids = serp(
+        index.clone(),
+        SearchQuery {
+            query: "tag:\"Comfort\"".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: synonyms.clone(),
+        },
+    )
+    .unwrap()
+    .hits
+    .into_iter()
+    .filter_map(
+        |hit| match hit.doc.0.get("id").and_then(|values| values.first()) {
+            Some(Value::U64(id)) => Some(*id),
+            _ => None,
+        },
+    )
+    .collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 3]);
+
+    // errors point into the search as typed, not with its synonyms written out
+    let err = parse("tag:\"Comfort\" words>lots").unwrap_err();
+    assert_eq!(
+        (err.position, err.message.as_str()),
+        (20, "`lots` isn't a number")
+    );
+}
+
+fn paragraphs(paragraphs: &[&str]) -> Vec<String> {
+    paragraphs
+        .iter()
+        .map(|paragraph| paragraph.to_string())
+        .collect()
+}
+
+fn novel() -> Arc<IndexServer> {
+    index(vec![
+        StoryData {
+            title: "The Long Road".to_string(),
+            tags: vec!["Angst".to_string()],
+            chapters: 2,
+            contents: vec![
+                paragraphs(&[
+                    "They set out at dawn.",
+                    "The road was long.",
+                    "Nobody spoke.",
+                    "At noon they found the lighthouse.",
+                ]),
+                paragraphs(&[
+                    "The lighthouse keeper was asleep.",
+                    "They waited by the lighthouse until dark.",
+                ]),
+            ],
+            ..story(1)
+        },
+        StoryData {
+            title: "Lighthouse".to_string(),
+            tags: vec!["Fluff".to_string()],
+            contents: vec![paragraphs(&["A short one about a lighthouse."])],
+            ..story(2)
+        },
+    ])
+}
+
+fn passage_search(index: &Arc<IndexServer>, query: &str) -> Vec<(u64, Vec<(u64, u64)>)> {
+    passages(
+        index.clone(),
+        SearchQuery {
+            query: query.to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap_or_else(|err| panic!("`{}` failed: {:?}", query, err))
+    .works
+    .into_iter()
+    .map(|work| {
+        let mut passages = work
+            .passages
+            .iter()
+            .map(|passage| (passage.chapter, passage.paragraph))
+            .collect::<Vec<_>>();
+        passages.sort_unstable();
+
+        (work.id, passages)
+    })
+    .collect()
+}
+
+#[test]
+fn passages_are_grouped_by_work() {
+    let index = novel();
+
+    assert_eq!(search(&index, "lighthouse"), vec![1, 2]);
+
+    let mut works = passage_search(&index, "lighthouse");
+    works.sort_unstable();
+    assert_eq!(works, vec![(1, vec![(1, 3), (2, 0)]), (2, vec![(1, 0)])]);
+
+    // words only search a passage's text, but fields still narrow down which works they're from
+    assert_eq!(passage_search(&index, "long road"), vec![(1, vec![(1, 0)])]);
+    assert_eq!(
+        passage_search(&index, "title:\"long road\""),
+        vec![(1, vec![(1, 0), (1, 3), (2, 0)])]
+    );
+    assert_eq!(passage_search(&index, "title:lighthouse tea"), Vec::new());
+    assert_eq!(
+        passage_search(&index, "lighthouse tag:fluff"),
+        vec![(2, vec![(1, 0)])]
+    );
+}
+
+#[test]
+fn passages_have_snippets() {
+    let index = novel();
+
+    let serp = passages(
+        index,
+        SearchQuery {
+            query: "keeper".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(serp.num_works, 1);
+
+    let snippet = serp.works[0].passages[0].snippet.as_ref().unwrap();
+    assert!(
+        snippet.html.contains("lighthouse <mark>keeper</mark>"),
+        "{}",
+        snippet.html
+    );
+}
+
+#[test]
+fn stories_can_be_read_back() {
+    let index = novel();
+
+    let text = story_text(index, 1).unwrap();
+
+    assert_eq!(
+        text.iter()
+            .map(|passage| (passage.chapter, passage.paragraph, passage.paragraphs.len()))
+            .collect::<Vec<_>>(),
+        vec![(1, 0, 3), (1, 3, 1), (2, 0, 2)]
+    );
+    assert_eq!(
+        text[2].paragraphs[1],
+        "They waited by the lighthouse until dark."
+    );
+}
+
+fn languages() -> Arc<IndexServer> {
+    index(vec![
+        StoryData {
+            language: Some("en".to_string()),
+            title: "Running Home".to_string(),
+            contents: vec![paragraphs(&["The dragons were flying over the castle."])],
+            ..story(1)
+        },
+        StoryData {
+            language: Some("de".to_string()),
+            title: "Die Burg".to_string(),
+            contents: vec![paragraphs(&["Die Drachen flogen über die Burg."])],
+            ..story(2)
+        },
+        StoryData {
+            language: Some("pt-BR".to_string()),
+            title: "O Castelo".to_string(),
+            contents: vec![paragraphs(&["Os dragões voavam sobre o castelo."])],
+            ..story(3)
+        },
+        StoryData {
+            title: "東京の物語".to_string(),
+            contents: vec![paragraphs(&["東京都に住んでいる。"])],
+            ..story(4)
+        },
+        StoryData {
+            title: "Flying".to_string(),
+            contents: vec![paragraphs(&["Dragons everywhere."])],
+            ..story(5)
+        },
+    ])
+}
+
+#[test]
+fn words_are_stemmed_by_language() {
+    let index = languages();
+
+    assert_eq!(search(&index, "flies"), vec![1]);
+    assert_eq!(search(&index, "title:runs"), vec![1]);
+    assert_eq!(search(&index, "drache"), vec![2]);
+    assert_eq!(search(&index, "flying"), vec![1, 5]);
+
+    // left out words still keep the rest of a phrase apart
+    assert_eq!(search(&index, "\"flying over the castle\""), vec![1]);
+    assert_eq!(search(&index, "\"flying over castle\""), Vec::<u64>::new());
+
+    let serp = serp(
+        index,
+        SearchQuery {
+            query: "flies".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: None,
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap();
+    assert!(
+        serp.hits[0].snippets[0]
+            .html
+            .contains("were <mark>flying</mark> over"),
+        "{}",
+        serp.hits[0].snippets[0].html
+    );
+}
+
+#[test]
+fn cjk_is_split_into_pairs() {
+    let index = languages();
+
+    assert_eq!(search(&index, "東京"), vec![4]);
+    assert_eq!(search(&index, "京都"), vec![4]);
+    assert_eq!(search(&index, "title:物語"), vec![4]);
+    assert_eq!(search(&index, "大阪"), Vec::<u64>::new());
+}
+
+#[test]
+fn works_can_be_filtered_by_language() {
+    let index = languages();
+
+    assert_eq!(search(&index, "lang:de"), vec![2]);
+    assert_eq!(search(&index, "lang:pt"), vec![3]);
+    assert_eq!(search(&index, "language:PT-BR"), vec![3]);
+    assert_eq!(search(&index, "lang:ja"), vec![4]);
+    assert_eq!(search(&index, "dragons -lang:en"), vec![5]);
+}
+
+#[test]
+fn languages_pick_analyzers() {
+    assert_eq!(Analyzer::for_language("en"), Analyzer::English);
+    assert_eq!(Analyzer::for_language("pt-BR"), Analyzer::Portuguese);
+    assert_eq!(Analyzer::for_language("zh"), Analyzer::Cjk);
+    assert_eq!(Analyzer::for_language("tlh"), Analyzer::Default);
+
+    assert_eq!(
+        analyzer::detect("Гарри Поттер и философский камень"),
+        Some("ru")
+    );
+    assert_eq!(analyzer::detect("東京都に住んでいる"), Some("ja"));
+    assert_eq!(analyzer::detect("哈利波特"), Some("zh"));
+    assert_eq!(analyzer::detect("Harry Potter"), None);
+    assert_eq!(analyzer::detect(""), None);
+
+    for (position, analyzer) in Analyzer::ALL.into_iter().enumerate() {
+        assert_eq!(analyzer.position(), position, "{:?}", analyzer);
+    }
+}
+
+/// The ids and scores of the stories a search finds with a ranking profile, best first.
+fn scored(index: &Arc<IndexServer>, query: &str, profile: &str) -> Vec<(u64, f32)> {
+    serp(
+        index.clone(),
+        SearchQuery {
+            query: query.to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: Some(profile.to_string()),
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap_or_else(|err| panic!("`{}` failed: {:?}", query, err))
+    .hits
+    .into_iter()
+    .filter_map(
+        |hit| match hit.doc.0.get("id").and_then(|values| values.first()) {
+            Some(Value::U64(id)) => Some((*id, hit.score)),
+            _ => None,
+        },
+    )
+    .collect()
+}
+
+fn score_of(scores: &[(u64, f32)], id: u64) -> f32 {
+    scores
+        .iter()
+        .find(|(found, _)| *found == id)
+        .map(|(_, score)| *score)
+        .unwrap_or_else(|| panic!("story {} wasn't found", id))
+}
+
+#[test]
+fn titles_are_boosted_over_bodies() {
+    let index = index(vec![
+        StoryData {
+            title: "Lighthouse".to_string(),
+            ..story(1)
+        },
+        StoryData {
+            title: "Keeper".to_string(),
+            contents: vec![paragraphs(&["They found the lighthouse at noon."])],
+            ..story(2)
+        },
+    ]);
+
+    let plain = scored(&index, "lighthouse", "plain");
+    let relevance = scored(&index, "lighthouse", "relevance");
+
+    assert_eq!(relevance[0].0, 1);
+    assert!((score_of(&relevance, 1) / score_of(&plain, 1) - 3.0).abs() < 0.001);
+    assert!((score_of(&relevance, 2) / score_of(&plain, 2) - 1.0).abs() < 0.001);
+}
+
+#[test]
+fn popularity_is_opt_in() {
+    let index = index(vec![
+        StoryData {
+            title: "Tea".to_string(),
+            kudos: 10,
+            ..story(1)
+        },
+        StoryData {
+            title: "Tea".to_string(),
+            kudos: 1_000,
+            ..story(2)
+        },
+    ]);
+
+    let relevance = scored(&index, "tea", "relevance");
+    assert_eq!(score_of(&relevance, 1), score_of(&relevance, 2));
+
+    let by_kudos = scored(&index, "tea", "popular");
+    assert_eq!(by_kudos[0].0, 2);
+    assert!(score_of(&by_kudos, 2) > score_of(&by_kudos, 1));
+    assert!(score_of(&by_kudos, 1) > score_of(&relevance, 1));
+
+    assert_eq!(popular(2.0, 0.0, 1_000), 2.0);
+    assert_eq!(popular(2.0, 0.5, 0), 2.0);
+}
+
+#[test]
+fn profiles_are_picked_by_name() {
+    let err = serp(
+        library(),
+        SearchQuery {
+            query: "harry".to_string(),
+            offset: 0,
+            limit: 10,
+            fuzzy: false,
+            profile: Some("loudest".to_string()),
+            synonyms: Synonyms::new(),
+        },
+    )
+    .unwrap_err();
+
+    let unknown = err.downcast_ref::<UnknownProfile>().unwrap();
+    assert_eq!(unknown.name, "loudest");
+    assert_eq!(unknown.known, vec!["plain", "popular", "relevance"]);
+
+    assert!(Profiles::load(None, "loudest").is_err());
+    assert_eq!(
+        Profiles::load(None, "popular").unwrap().default_name(),
+        "popular"
+    );
+}
+
+/// A story as the database has it, for the parts of it the index doesn't keep.
+fn stored(story: &StoryData) -> Story {
+    let entities = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| Entity {
+                id: 0,
+                name: name.clone(),
+            })
+            .collect()
+    };
+
+    Story {
+        id: story.id,
+        name: story.title.clone(),
+        summary: story.summary.clone(),
+        authors: story
+            .authors
+            .iter()
+            .map(|name| Author {
+                user_id: 0,
+                user: name.clone(),
+                pseud: name.clone(),
+            })
+            .collect(),
+        origins: entities(&story.fandoms),
+        warnings: entities(&story.warnings),
+        pairings: entities(&story.ships),
+        characters: entities(&story.characters),
+        generals: entities(&story.tags),
+        series: story
+            .series
+            .iter()
+            .map(|name| SeriesPart {
+                id: 0,
+                name: name.clone(),
+                position: 1,
+            })
+            .collect(),
+        relations: Vec::new(),
+    }
+}
+
+fn similar_to(index: &Arc<IndexServer>, story: &StoryData, exclude_authors: bool) -> Vec<u64> {
+    similar(
+        index.clone(),
+        &stored(story),
+        SimilarQuery {
+            limit: 10,
+            exclude_authors,
+        },
+    )
+    .unwrap()
+    .works
+    .into_iter()
+    .map(|work| work.id)
+    .collect()
+}
+
+fn lighthouse() -> StoryData {
+    StoryData {
+        title: "The Lighthouse Keeper".to_string(),
+        authors: vec!["someone".to_string()],
+        fandoms: vec!["Original Work".to_string()],
+        tags: vec!["Lighthouses".to_string(), "Fluff".to_string()],
+        contents: vec![paragraphs(&[
+            "The keeper climbed the lighthouse stairs every night.",
+        ])],
+        ..story(1)
+    }
+}
+
+#[test]
+fn similar_works_share_words_and_tags() {
+    let index = index(vec![
+        lighthouse(),
+        StoryData {
+            title: "Stairs".to_string(),
+            authors: vec!["someone".to_string()],
+            tags: vec!["Lighthouses".to_string()],
+            contents: vec![paragraphs(&["Another night, another keeper."])],
+            ..story(2)
+        },
+        StoryData {
+            title: "Beacon".to_string(),
+            authors: vec!["another".to_string()],
+            fandoms: vec!["Original Work".to_string()],
+            tags: vec!["Fluff".to_string()],
+            contents: vec![paragraphs(&["The lighthouse was dark for a week."])],
+            ..story(3)
+        },
+        StoryData {
+            title: "Dragon Riders".to_string(),
+            authors: vec!["nobody".to_string()],
+            fandoms: vec!["Harry Potter - J. K. Rowling".to_string()],
+            contents: vec![paragraphs(&["Dragons circled a castle."])],
+            ..story(4)
+        },
+    ]);
+
+    let mut similar = similar_to(&index, &lighthouse(), false);
+    similar.sort_unstable();
+    assert_eq!(similar, vec![2, 3]);
+
+    assert_eq!(similar_to(&index, &lighthouse(), true), vec![3]);
+}
+
+#[test]
+fn unindexed_works_are_similar_by_name() {
+    let index = index(vec![
+        StoryData {
+            title: "Lighthouse".to_string(),
+            tags: vec!["Lighthouses".to_string()],
+            ..story(2)
+        },
+        StoryData {
+            title: "Beacon".to_string(),
+            tags: vec!["Lighthouses".to_string()],
+            ..story(3)
+        },
+    ]);
+
+    let mut similar = similar_to(&index, &lighthouse(), false);
+    similar.sort_unstable();
+    assert_eq!(similar, vec![2, 3]);
+}
+
+fn clusters(index: &Arc<IndexServer>, min_similarity: f32) -> Vec<(Vec<u64>, f32)> {
+    duplicates(index.clone(), DuplicatesQuery { min_similarity })
+        .unwrap()
+        .clusters
+        .into_iter()
+        .map(|cluster| (cluster.ids, cluster.similarity))
+        .collect()
+}
+
+#[test]
+fn reposts_are_clustered() {
+    let keeper = |word: &str| {
+        paragraphs(&[
+            format!("The keeper climbed the lighthouse stairs every night, counting each step {} the wind pulled at the windows.", word).as_str(),
+            "Far below, the sea kept its own count, wave after wave against the rocks, patient as the light that swept over it.",
+        ])
+    };
+
+    let index = index(vec![
+        StoryData {
+            contents: vec![keeper("as")],
+            ..story(1)
+        },
+        StoryData {
+            contents: vec![keeper("while")],
+            ..story(2)
+        },
+        StoryData {
+            contents: vec![keeper("as")],
+            ..story(3)
+        },
+        StoryData {
+            contents: vec![paragraphs(&[
+                "Dragons circled the castle at dawn, their wings loud as thunder over the courtyard where the students gathered.",
+                "Nobody had expected them to return after the war, least of all the headmaster, who watched from the tallest tower.",
+            ])],
+            ..story(4)
+        },
+        StoryData {
+            contents: vec![keeper("as")[..1].to_vec()],
+            ..story(5)
+        },
+        StoryData {
+            contents: vec![paragraphs(&["Too short to say much."])],
+            ..story(6)
+        },
+        StoryData {
+            contents: vec![paragraphs(&["Too short to say much."])],
+            ..story(7)
+        },
+    ]);
+
+    assert_eq!(clusters(&index, 0.9), vec![(vec![1, 3], 1.0)]);
+    assert_eq!(clusters(&index, 0.85), vec![(vec![1, 2, 3], 0.875)]);
+}
+
+#[test]
+fn similarities_are_checked() {
+    assert_eq!(similarity(0b1011, 0b1011), 1.0);
+    assert_eq!(similarity(0, u64::MAX), 0.0);
+    assert_eq!(similarity(0, 0xffff_ffff), 0.5);
+
+    for min_similarity in [0.4, 1.5] {
+        let err = duplicates(library(), DuplicatesQuery { min_similarity }).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<InvalidSimilarity>().unwrap().0,
+            min_similarity
+        );
+    }
+}
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS story_tags (
+    story_id INTEGER NOT NULL REFERENCES stories(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (story_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS story_tags_tag_id ON story_tags(tag_id);
@@ -0,0 +1,77 @@
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'author', name, MIN(created) FROM authors GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_authors AS link
+    JOIN authors AS old ON old.id = link.author_id
+    JOIN tags ON tags.kind = 'author' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_authors;
+DROP TABLE IF EXISTS authors;
+
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'origin', name, MIN(created) FROM origins GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_origins AS link
+    JOIN origins AS old ON old.id = link.origin_id
+    JOIN tags ON tags.kind = 'origin' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_origins;
+DROP TABLE IF EXISTS origins;
+
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'warning', name, MIN(created) FROM warnings GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_warnings AS link
+    JOIN warnings AS old ON old.id = link.warning_id
+    JOIN tags ON tags.kind = 'warning' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_warnings;
+DROP TABLE IF EXISTS warnings;
+
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'pairing', name, MIN(created) FROM pairings GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_pairings AS link
+    JOIN pairings AS old ON old.id = link.pairing_id
+    JOIN tags ON tags.kind = 'pairing' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_pairings;
+DROP TABLE IF EXISTS pairings;
+
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'character', name, MIN(created) FROM characters GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_characters AS link
+    JOIN characters AS old ON old.id = link.character_id
+    JOIN tags ON tags.kind = 'character' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_characters;
+DROP TABLE IF EXISTS characters;
+
+INSERT OR IGNORE INTO tags(kind, name, created)
+    SELECT 'general', name, MIN(created) FROM generals GROUP BY name;
+
+INSERT OR IGNORE INTO story_tags(story_id, tag_id, created)
+    SELECT link.story_id, tags.id, link.created
+    FROM story_generals AS link
+    JOIN generals AS old ON old.id = link.general_id
+    JOIN tags ON tags.kind = 'general' AND tags.name = old.name
+    WHERE link.story_id IN (SELECT id FROM stories);
+
+DROP TABLE IF EXISTS story_generals;
+DROP TABLE IF EXISTS generals;
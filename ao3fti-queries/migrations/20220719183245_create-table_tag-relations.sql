@@ -0,0 +1,12 @@
+ALTER TABLE tags ADD COLUMN canonical_id INTEGER REFERENCES tags(id) ON DELETE SET NULL;
+ALTER TABLE tags ADD COLUMN checked DATETIME;
+
+CREATE INDEX IF NOT EXISTS tags_canonical_id ON tags(canonical_id);
+
+CREATE TABLE IF NOT EXISTS tag_relations (
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    related_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    relation TEXT NOT NULL,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (tag_id, related_id, relation)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS series (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    checked DATETIME,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc'))
+);
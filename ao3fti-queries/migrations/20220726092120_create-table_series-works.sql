@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS series_works (
+    series_id INTEGER NOT NULL REFERENCES series(id) ON DELETE CASCADE,
+    story_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (series_id, story_id)
+);
+
+CREATE INDEX IF NOT EXISTS series_works_story_id ON series_works(story_id);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS story_relations (
+    story_id INTEGER NOT NULL,
+    related_id INTEGER NOT NULL,
+    relation TEXT NOT NULL,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (story_id, related_id, relation)
+);
+
+CREATE INDEX IF NOT EXISTS story_relations_related_id ON story_relations(related_id);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS story_pseuds (
+    story_id INTEGER NOT NULL REFERENCES stories(id) ON DELETE CASCADE,
+    pseud_id INTEGER NOT NULL REFERENCES pseuds(id) ON DELETE CASCADE,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (story_id, pseud_id)
+);
+
+CREATE INDEX IF NOT EXISTS story_pseuds_pseud_id ON story_pseuds(pseud_id);
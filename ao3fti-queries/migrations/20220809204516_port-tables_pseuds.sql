@@ -0,0 +1,39 @@
+-- author tags were stored as `pseud (user)`, or just `user` when the pseud is the default one
+CREATE TEMPORARY TABLE author_names AS
+    SELECT
+        id AS tag_id,
+        created,
+        CASE WHEN name LIKE '% (%)'
+            THEN substr(name, instr(name, ' (') + 2, length(name) - instr(name, ' (') - 2)
+            ELSE name
+        END AS user,
+        CASE WHEN name LIKE '% (%)'
+            THEN substr(name, 1, instr(name, ' (') - 1)
+            ELSE name
+        END AS pseud
+    FROM tags
+    WHERE kind = 'author';
+
+INSERT OR IGNORE INTO users(name, url, created)
+    SELECT user, 'https://archiveofourown.org/users/' || user, MIN(created)
+    FROM author_names
+    GROUP BY user;
+
+INSERT OR IGNORE INTO pseuds(user_id, name, url, created)
+    SELECT users.id, author_names.pseud, users.url || '/pseuds/' || author_names.pseud, MIN(author_names.created)
+    FROM author_names
+    JOIN users ON users.name = author_names.user
+    GROUP BY users.id, author_names.pseud;
+
+INSERT OR IGNORE INTO story_pseuds(story_id, pseud_id, created)
+    SELECT story_tags.story_id, pseuds.id, story_tags.created
+    FROM story_tags
+    JOIN author_names ON author_names.tag_id = story_tags.tag_id
+    JOIN users ON users.name = author_names.user
+    JOIN pseuds ON pseuds.user_id = users.id AND pseuds.name = author_names.pseud;
+
+DELETE FROM story_tags WHERE tag_id IN (SELECT tag_id FROM author_names);
+DELETE FROM tag_relations WHERE tag_id IN (SELECT tag_id FROM author_names) OR related_id IN (SELECT tag_id FROM author_names);
+DELETE FROM tags WHERE kind = 'author';
+
+DROP TABLE author_names;
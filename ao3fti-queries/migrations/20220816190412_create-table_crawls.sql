@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS crawls (
+    id INTEGER NOT NULL PRIMARY KEY,
+    url TEXT NOT NULL,
+    search TEXT,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc'))
+);
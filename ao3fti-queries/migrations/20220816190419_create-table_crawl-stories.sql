@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS crawl_stories (
+    crawl_id INTEGER NOT NULL REFERENCES crawls(id) ON DELETE CASCADE,
+    story_id INTEGER NOT NULL,
+    created DATETIME NOT NULL DEFAULT (datetime(CURRENT_TIMESTAMP, 'utc')),
+    PRIMARY KEY (crawl_id, story_id)
+);
+
+CREATE INDEX IF NOT EXISTS crawl_stories_story_id ON crawl_stories(story_id);
@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use ao3fti_common::{
-    models::{Entity, Rating, Story},
+    models::{
+        Author, Entity, Rating, SeriesPart, Story, StoryRelation, Tag, TagKind, TagRelation,
+        WorkRelation,
+    },
     Conf,
 };
 
@@ -22,14 +25,14 @@ pub async fn init_database_connection(conf: Arc<Conf>) -> Result<Pool, ao3fti_co
     Ok(pool)
 }
 
-#[tracing::instrument(skip(trans), err)]
+#[tracing::instrument(skip(pool), err)]
 pub async fn check_story_if_exists(
-    trans: &mut Transaction<'_, Sqlite>,
+    pool: Pool,
     story_id: usize,
 ) -> Result<bool, ao3fti_common::Report> {
     let story_id = story_id as i64;
     let existing = sqlx::query!("SELECT id FROM stories WHERE id = ?", story_id)
-        .fetch_optional(&mut *trans)
+        .fetch_optional(&pool)
         .await?;
 
     Ok(existing.is_some())
@@ -47,78 +50,87 @@ pub async fn get_story_count(pool: Pool) -> Result<i64, ao3fti_common::Report> {
     Ok(count.estimate as i64)
 }
 
-macro_rules! get_or_create {
-    ($fn_name:ident, $select:expr, $insert:expr) => {
-        #[tracing::instrument(skip(trans), err)]
-        pub async fn $fn_name(
-            trans: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
-            name: &str,
-        ) -> Result<i32, ao3fti_common::Report> {
-            let find = sqlx::query!($select, name)
-                .fetch_optional(&mut *trans)
-                .await?;
+#[tracing::instrument(skip(trans), err)]
+pub async fn get_or_create_tag(
+    trans: &mut Transaction<'_, Sqlite>,
+    kind: TagKind,
+    name: &str,
+) -> Result<i32, ao3fti_common::Report> {
+    let kind = serde_plain::to_string(&kind).unwrap();
 
-            if let Some(record) = find {
-                tracing::debug!("found existing entity");
+    sqlx::query!(
+        "INSERT INTO tags(kind, name) VALUES (?, ?) ON CONFLICT(kind, name) DO NOTHING",
+        kind,
+        name,
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    let record = sqlx::query!(
+        "SELECT id FROM tags WHERE kind = ? AND name = ?",
+        kind,
+        name
+    )
+    .fetch_one(&mut *trans)
+    .await?;
+
+    Ok(record.id as i32)
+}
+
+#[tracing::instrument(skip(trans), err)]
+pub async fn get_or_create_pseud(
+    trans: &mut Transaction<'_, Sqlite>,
+    author: &AuthorInfo,
+) -> Result<i32, ao3fti_common::Report> {
+    sqlx::query!(
+        "INSERT INTO users(name, url) VALUES (?, ?) ON CONFLICT(name) DO NOTHING",
+        author.user,
+        author.user_url,
+    )
+    .execute(&mut *trans)
+    .await?;
 
-                Ok(record.id as i32)
-            } else {
-                tracing::debug!("creating entity");
+    let user = sqlx::query!("SELECT id FROM users WHERE name = ?", author.user)
+        .fetch_one(&mut *trans)
+        .await?;
 
-                sqlx::query!($insert, name).execute(&mut *trans).await?;
+    sqlx::query!(
+        "INSERT INTO pseuds(user_id, name, url) VALUES (?, ?, ?) ON CONFLICT(user_id, name) DO NOTHING",
+        user.id,
+        author.pseud,
+        author.pseud_url,
+    )
+    .execute(&mut *trans)
+    .await?;
 
-                let record = sqlx::query!($select, name).fetch_one(&mut *trans).await?;
+    let pseud = sqlx::query!(
+        "SELECT id FROM pseuds WHERE user_id = ? AND name = ?",
+        user.id,
+        author.pseud,
+    )
+    .fetch_one(&mut *trans)
+    .await?;
 
-                Ok(record.id as i32)
-            }
-        }
-    };
+    Ok(pseud.id as i32)
 }
 
-get_or_create!(
-    get_or_create_author,
-    "SELECT id FROM authors WHERE name = ?",
-    "INSERT INTO authors(name) VALUES (?)"
-);
-
-get_or_create!(
-    get_or_create_origin,
-    "SELECT id FROM origins WHERE name = ?",
-    "INSERT INTO origins(name) VALUES (?)"
-);
-
-get_or_create!(
-    get_or_create_warning,
-    "SELECT id FROM warnings WHERE name = ?",
-    "INSERT INTO warnings(name) VALUES (?)"
-);
-
-get_or_create!(
-    get_or_create_pairing,
-    "SELECT id FROM pairings WHERE name = ?",
-    "INSERT INTO pairings(name) VALUES (?)"
-);
-
-get_or_create!(
-    get_or_create_character,
-    "SELECT id FROM characters WHERE name = ?",
-    "INSERT INTO characters(name) VALUES (?)"
-);
-
-get_or_create!(
-    get_or_create_general,
-    "SELECT id FROM generals WHERE name = ?",
-    "INSERT INTO generals(name) VALUES (?)"
-);
-
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Info {
     pub name: String,
-    pub authors: Vec<String>,
+    pub authors: Vec<AuthorInfo>,
     pub summary: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct AuthorInfo {
+    pub user: String,
+    pub user_url: String,
+    pub pseud: String,
+    pub pseud_url: String,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Meta {
@@ -129,23 +141,23 @@ pub struct Meta {
     pub pairings: Vec<String>,
     pub characters: Vec<String>,
     pub generals: Vec<String>,
+    pub series: Vec<SeriesInfo>,
 }
 
-macro_rules! for_tag {
-    ($pool:ident, $story_id:ident, [ $( $member:expr => ($get_or_create:ident, $insert:expr); )* ]) => {{
-        $(
-            for entity in &$member {
-                let id = $get_or_create(&mut *$pool, entity).await?;
-
-                tracing::debug!(story_id = %$story_id, entity_id = %id, "linking entity to story");
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SeriesInfo {
+    pub id: usize,
+    pub name: String,
+    pub position: usize,
+}
 
-                let story_id = $story_id as i32;
-                sqlx::query!($insert, story_id, id)
-                    .execute(&mut *$pool)
-                    .await?;
-            }
-        )*
-    }};
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct RelationInfo {
+    pub story_id: usize,
+    pub related_id: usize,
+    pub relation: WorkRelation,
 }
 
 #[tracing::instrument(skip(trans, info, meta), err)]
@@ -167,49 +179,154 @@ pub async fn insert_story(
     .execute(&mut *trans)
     .await?;
 
-    #[rustfmt::skip]
-    for_tag!(trans, story_id, [
-        info.authors => (get_or_create_author, "INSERT INTO story_authors(story_id, author_id) VALUES (?, ?)");
-    ]);
+    for (kind, entities) in [
+        (TagKind::Origin, &meta.origins),
+        (TagKind::Warning, &meta.warnings),
+        (TagKind::Pairing, &meta.pairings),
+        (TagKind::Character, &meta.characters),
+        (TagKind::General, &meta.generals),
+    ] {
+        for entity in entities {
+            let tag_id = get_or_create_tag(&mut *trans, kind, entity).await?;
+
+            tracing::debug!(story_id = %story_id, tag_id = %tag_id, "linking tag to story");
+
+            sqlx::query!(
+                "INSERT OR IGNORE INTO story_tags(story_id, tag_id) VALUES (?, ?)",
+                story_id,
+                tag_id,
+            )
+            .execute(&mut *trans)
+            .await?;
+        }
+    }
+
+    for author in &info.authors {
+        let pseud_id = get_or_create_pseud(&mut *trans, author).await?;
+
+        tracing::debug!(story_id = %story_id, pseud_id = %pseud_id, "linking pseud to story");
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO story_pseuds(story_id, pseud_id) VALUES (?, ?)",
+            story_id,
+            pseud_id,
+        )
+        .execute(&mut *trans)
+        .await?;
+    }
+
+    for part in &meta.series {
+        let series_id = part.id as i64;
+        let position = part.position as i64;
 
-    #[rustfmt::skip]
-    for_tag!(trans, story_id, [
-        meta.origins => (get_or_create_origin, "INSERT INTO story_origins(story_id, origin_id) VALUES (?, ?)");
-        meta.warnings => (get_or_create_warning, "INSERT INTO story_warnings(story_id, warning_id) VALUES (?, ?)");
-        meta.pairings => (get_or_create_pairing, "INSERT INTO story_pairings(story_id, pairing_id) VALUES (?, ?)");
-        meta.characters => (get_or_create_character, "INSERT INTO story_characters(story_id, character_id) VALUES (?, ?)");
-        meta.generals => (get_or_create_general, "INSERT INTO story_generals(story_id, general_id) VALUES (?, ?)");
-    ]);
+        tracing::debug!(story_id = %story_id, series_id = %series_id, "linking series to story");
+
+        sqlx::query!(
+            "INSERT INTO series(id, name) VALUES (?, ?) ON CONFLICT(id) DO NOTHING",
+            series_id,
+            part.name,
+        )
+        .execute(&mut *trans)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO series_works(series_id, story_id, position) VALUES (?, ?, ?) ON CONFLICT(series_id, story_id) DO UPDATE SET position = excluded.position",
+            series_id,
+            story_id,
+            position,
+        )
+        .execute(&mut *trans)
+        .await?;
+    }
 
     Ok(false)
 }
 
-#[rustfmt::skip]
 #[tracing::instrument(skip(pool), err)]
 pub async fn get_story(pool: Pool, story_id: u64) -> Result<Story, ao3fti_common::Report> {
     let id = story_id as i32;
 
-    let story = sqlx::query!("SELECT name, summary, rating FROM stories WHERE id = ?", id).fetch_one(&pool).await?;
-
-    let authors: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM authors WHERE id IN (SELECT author_id as id FROM story_authors WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
-        .await?;
-    let origins: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM origins WHERE id IN (SELECT origin_id as id FROM story_origins WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
-        .await?;
-    let warnings: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM warnings WHERE id IN (SELECT warning_id as id FROM story_warnings WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
-        .await?;
-    let pairings: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM pairings WHERE id IN (SELECT pairing_id as id FROM story_pairings WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
-        .await?;
-    let characters: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM characters WHERE id IN (SELECT character_id as id FROM story_characters WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
-        .await?;
-    let generals: Vec<Entity> = sqlx::query_as!(Entity, "SELECT name FROM generals WHERE id IN (SELECT general_id as id FROM story_generals WHERE story_id = ? ORDER BY created DESC)", id)
-        .fetch_all(&pool)
+    let story = sqlx::query!("SELECT name, summary, rating FROM stories WHERE id = ?", id)
+        .fetch_one(&pool)
         .await?;
 
+    // synonyms are shown as their canonical tag, so browsing always lands on the merged tag
+    let tags = sqlx::query!(
+        r#"SELECT DISTINCT canonical.id as "id!: i32", canonical.kind, canonical.name FROM story_tags JOIN tags ON tags.id = story_tags.tag_id JOIN tags AS canonical ON canonical.id = COALESCE(tags.canonical_id, tags.id) WHERE story_tags.story_id = ? ORDER BY story_tags.created DESC"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let authors = sqlx::query_as!(
+        Author,
+        r#"SELECT users.id as "user_id!: i32", users.name as user, pseuds.name as pseud FROM story_pseuds JOIN pseuds ON pseuds.id = story_pseuds.pseud_id JOIN users ON users.id = pseuds.user_id WHERE story_pseuds.story_id = ? ORDER BY story_pseuds.created"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut origins = Vec::new();
+    let mut warnings = Vec::new();
+    let mut pairings = Vec::new();
+    let mut characters = Vec::new();
+    let mut generals = Vec::new();
+
+    for tag in tags {
+        let list = match serde_plain::from_str::<TagKind>(&tag.kind)? {
+            TagKind::Origin => &mut origins,
+            TagKind::Warning => &mut warnings,
+            TagKind::Pairing => &mut pairings,
+            TagKind::Character => &mut characters,
+            TagKind::General => &mut generals,
+        };
+
+        list.push(Entity {
+            id: tag.id,
+            name: tag.name,
+        });
+    }
+
+    let series = sqlx::query_as!(
+        SeriesPart,
+        r#"SELECT series.id as "id!: i32", series.name, series_works.position as "position!: i32" FROM series_works JOIN series ON series.id = series_works.series_id WHERE series_works.story_id = ? ORDER BY series.name"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut relations = Vec::new();
+
+    let outgoing = sqlx::query!(
+        r#"SELECT story_relations.related_id as "id!: i64", story_relations.relation, stories.name as "name?" FROM story_relations LEFT JOIN stories ON stories.id = story_relations.related_id WHERE story_relations.story_id = ? ORDER BY story_relations.created"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+    for record in outgoing {
+        relations.push(StoryRelation {
+            id: record.id as u64,
+            name: record.name,
+            relation: serde_plain::from_str(&record.relation)?,
+            inverse: false,
+        });
+    }
+
+    let incoming = sqlx::query!(
+        r#"SELECT story_relations.story_id as "id!: i64", story_relations.relation, stories.name as "name?" FROM story_relations LEFT JOIN stories ON stories.id = story_relations.story_id WHERE story_relations.related_id = ? ORDER BY story_relations.created"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+    for record in incoming {
+        relations.push(StoryRelation {
+            id: record.id as u64,
+            name: record.name,
+            relation: serde_plain::from_str(&record.relation)?,
+            inverse: true,
+        });
+    }
+
     Ok(Story {
         id: id as usize,
         name: story.name,
@@ -220,9 +337,502 @@ pub async fn get_story(pool: Pool, story_id: u64) -> Result<Story, ao3fti_common
         pairings,
         characters,
         generals,
+        series,
+        relations,
+    })
+}
+
+/// Stores relations between stories, either end of which may not have been scraped yet.
+#[tracing::instrument(skip(trans, relations), err)]
+pub async fn insert_story_relations(
+    trans: &mut Transaction<'_, Sqlite>,
+    relations: &[RelationInfo],
+) -> Result<(), ao3fti_common::Report> {
+    for relation in relations {
+        let story_id = relation.story_id as i64;
+        let related_id = relation.related_id as i64;
+        let kind = serde_plain::to_string(&relation.relation).unwrap();
+
+        tracing::debug!(story_id = %story_id, related_id = %related_id, relation = %kind, "linking related stories");
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO story_relations(story_id, related_id, relation) VALUES (?, ?, ?)",
+            story_id,
+            related_id,
+            kind,
+        )
+        .execute(&mut *trans)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TagRelations {
+    pub canonical: Option<String>,
+    pub synonyms: Vec<String>,
+    pub parents: Vec<String>,
+    pub metas: Vec<String>,
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_unchecked_tags(pool: Pool) -> Result<Vec<Tag>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        r#"SELECT id as "id!: i32", kind, name FROM tags WHERE checked IS NULL ORDER BY id"#
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    records
+        .into_iter()
+        .map(|record| -> Result<Tag, ao3fti_common::Report> {
+            Ok(Tag {
+                id: record.id,
+                kind: serde_plain::from_str(&record.kind)?,
+                name: record.name,
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(skip(trans, relations), err)]
+pub async fn insert_tag_relations(
+    trans: &mut Transaction<'_, Sqlite>,
+    tag: &Tag,
+    relations: TagRelations,
+) -> Result<(), ao3fti_common::Report> {
+    if let Some(canonical) = relations
+        .canonical
+        .as_deref()
+        .filter(|name| *name != tag.name)
+    {
+        let canonical_id = get_or_create_tag(&mut *trans, tag.kind, canonical).await?;
+
+        tracing::debug!(tag_id = %tag.id, canonical_id = %canonical_id, "marking tag as synonym");
+
+        sqlx::query!(
+            "UPDATE tags SET canonical_id = ? WHERE id = ?",
+            canonical_id,
+            tag.id,
+        )
+        .execute(&mut *trans)
+        .await?;
+    } else {
+        for synonym in relations.synonyms.iter().filter(|name| **name != tag.name) {
+            let synonym_id = get_or_create_tag(&mut *trans, tag.kind, synonym).await?;
+
+            sqlx::query!(
+                "UPDATE tags SET canonical_id = ? WHERE id = ?",
+                tag.id,
+                synonym_id,
+            )
+            .execute(&mut *trans)
+            .await?;
+        }
+    }
+
+    // parent tags of anything but a fandom are fandoms, a fandom's parents are media which we don't track
+    let parents = if tag.kind == TagKind::Origin {
+        &[][..]
+    } else {
+        &relations.parents[..]
+    };
+
+    for (relation, kind, names) in [
+        (TagRelation::Parent, TagKind::Origin, parents),
+        (TagRelation::Meta, tag.kind, &relations.metas[..]),
+    ] {
+        let relation = serde_plain::to_string(&relation).unwrap();
+
+        for name in names {
+            let related_id = get_or_create_tag(&mut *trans, kind, name).await?;
+
+            sqlx::query!(
+                "INSERT OR IGNORE INTO tag_relations(tag_id, related_id, relation) VALUES (?, ?, ?)",
+                tag.id,
+                related_id,
+                relation,
+            )
+            .execute(&mut *trans)
+            .await?;
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE tags SET checked = datetime(CURRENT_TIMESTAMP, 'utc') WHERE id = ?",
+        tag.id
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns every name that shares a canonical tag with `name`, including the canonical name itself.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_tag_synonyms(
+    pool: Pool,
+    name: &str,
+) -> Result<Vec<String>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        "SELECT DISTINCT name FROM tags WHERE COALESCE(canonical_id, id) IN (SELECT COALESCE(canonical_id, id) FROM tags WHERE name = ? COLLATE NOCASE)",
+        name
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records.into_iter().map(|record| record.name).collect())
+}
+
+/// Gets a tag by id, resolving synonyms to their canonical tag.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_canonical_tag(pool: Pool, tag_id: i32) -> Result<Tag, ao3fti_common::Report> {
+    let record = sqlx::query!(
+        r#"SELECT canonical.id as "id!: i32", canonical.kind, canonical.name FROM tags JOIN tags AS canonical ON canonical.id = COALESCE(tags.canonical_id, tags.id) WHERE tags.id = ?"#,
+        tag_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(Tag {
+        id: record.id,
+        kind: serde_plain::from_str(&record.kind)?,
+        name: record.name,
+    })
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_tag_story_count(pool: Pool, tag_id: i32) -> Result<i64, ao3fti_common::Report> {
+    let record = sqlx::query!(
+        r#"SELECT COUNT(DISTINCT story_id) as "estimate!: i64" FROM story_tags WHERE tag_id IN (SELECT id FROM tags WHERE id = ? OR canonical_id = ?)"#,
+        tag_id,
+        tag_id,
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(record.estimate)
+}
+
+/// Lists the stories tagged with a canonical tag or any of its synonyms, newest first.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_tag_story_ids(
+    pool: Pool,
+    tag_id: i32,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<u64>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        r#"SELECT DISTINCT story_id as "story_id!: i64" FROM story_tags WHERE tag_id IN (SELECT id FROM tags WHERE id = ? OR canonical_id = ?) ORDER BY story_id DESC LIMIT ? OFFSET ?"#,
+        tag_id,
+        tag_id,
+        limit,
+        offset,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| record.story_id as u64)
+        .collect())
+}
+
+#[derive(Clone, Debug)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub url: String,
+    pub pseuds: Vec<String>,
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_user(pool: Pool, user_id: i32) -> Result<User, ao3fti_common::Report> {
+    let user = sqlx::query!(
+        r#"SELECT id as "id!: i32", name, url FROM users WHERE id = ?"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    let pseuds = sqlx::query!(
+        "SELECT name FROM pseuds WHERE user_id = ? ORDER BY name",
+        user_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(User {
+        id: user.id,
+        name: user.name,
+        url: user.url,
+        pseuds: pseuds.into_iter().map(|pseud| pseud.name).collect(),
     })
 }
 
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_user_story_count(pool: Pool, user_id: i32) -> Result<i64, ao3fti_common::Report> {
+    let record = sqlx::query!(
+        r#"SELECT COUNT(DISTINCT story_id) as "estimate!: i64" FROM story_pseuds WHERE pseud_id IN (SELECT id FROM pseuds WHERE user_id = ?)"#,
+        user_id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    Ok(record.estimate)
+}
+
+/// Lists the stories written under any of a user's pseuds, newest first.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_user_story_ids(
+    pool: Pool,
+    user_id: i32,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<u64>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        r#"SELECT DISTINCT story_id as "story_id!: i64" FROM story_pseuds WHERE pseud_id IN (SELECT id FROM pseuds WHERE user_id = ?) ORDER BY story_id DESC LIMIT ? OFFSET ?"#,
+        user_id,
+        limit,
+        offset,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| record.story_id as u64)
+        .collect())
+}
+
+/// Counts the stories using each tag, synonyms included as their own names.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_tag_counts(
+    pool: Pool,
+) -> Result<Vec<(TagKind, String, i64)>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        r#"SELECT tags.kind, tags.name, COUNT(DISTINCT story_tags.story_id) as "count!: i64" FROM tags JOIN story_tags ON story_tags.tag_id = tags.id GROUP BY tags.id"#
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let mut counts = Vec::with_capacity(records.len());
+    for record in records {
+        counts.push((
+            serde_plain::from_str(&record.kind)?,
+            record.name,
+            record.count,
+        ));
+    }
+
+    Ok(counts)
+}
+
+/// Counts the stories written under each user and pseud name.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_author_counts(pool: Pool) -> Result<Vec<(String, i64)>, ao3fti_common::Report> {
+    let records = sqlx::query!(
+        r#"SELECT name as "name!: String", COUNT(DISTINCT story_id) as "count!: i64" FROM (SELECT pseuds.name AS name, story_pseuds.story_id AS story_id FROM story_pseuds JOIN pseuds ON pseuds.id = story_pseuds.pseud_id UNION SELECT users.name AS name, story_pseuds.story_id AS story_id FROM story_pseuds JOIN pseuds ON pseuds.id = story_pseuds.pseud_id JOIN users ON users.id = pseuds.user_id) GROUP BY name"#
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| (record.name, record.count))
+        .collect())
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_unchecked_series(pool: Pool) -> Result<Vec<usize>, ao3fti_common::Report> {
+    let records =
+        sqlx::query!(r#"SELECT id as "id!: i64" FROM series WHERE checked IS NULL ORDER BY id"#)
+            .fetch_all(&pool)
+            .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| record.id as usize)
+        .collect())
+}
+
+/// Stores the works of a series in reading order, including works that haven't been scraped.
+#[tracing::instrument(skip(trans, name, story_ids), err)]
+pub async fn insert_series_works(
+    trans: &mut Transaction<'_, Sqlite>,
+    series_id: usize,
+    name: &str,
+    story_ids: &[usize],
+) -> Result<(), ao3fti_common::Report> {
+    let series_id = series_id as i64;
+
+    sqlx::query!(
+        "INSERT INTO series(id, name, checked) VALUES (?, ?, datetime(CURRENT_TIMESTAMP, 'utc')) ON CONFLICT(id) DO UPDATE SET name = excluded.name, checked = excluded.checked",
+        series_id,
+        name,
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    for (index, story_id) in story_ids.iter().enumerate() {
+        let story_id = *story_id as i64;
+        let position = index as i64 + 1;
+
+        sqlx::query!(
+            "INSERT INTO series_works(series_id, story_id, position) VALUES (?, ?, ?) ON CONFLICT(series_id, story_id) DO UPDATE SET position = excluded.position",
+            series_id,
+            story_id,
+            position,
+        )
+        .execute(&mut *trans)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct SeriesEntry {
+    pub story_id: u64,
+    pub position: i32,
+    pub indexed: bool,
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_series_name(pool: Pool, series_id: u64) -> Result<String, ao3fti_common::Report> {
+    let id = series_id as i64;
+
+    let record = sqlx::query!("SELECT name FROM series WHERE id = ?", id)
+        .fetch_one(&pool)
+        .await?;
+
+    Ok(record.name)
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_series_works(
+    pool: Pool,
+    series_id: u64,
+) -> Result<Vec<SeriesEntry>, ao3fti_common::Report> {
+    let id = series_id as i64;
+
+    let records = sqlx::query!(
+        r#"SELECT series_works.story_id as "story_id!: i64", series_works.position as "position!: i32", stories.id IS NOT NULL as "indexed!: bool" FROM series_works LEFT JOIN stories ON stories.id = series_works.story_id WHERE series_works.series_id = ? ORDER BY series_works.position"#,
+        id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| SeriesEntry {
+            story_id: record.story_id as u64,
+            position: record.position,
+            indexed: record.indexed,
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_story_summaries(pool: Pool) -> Result<Vec<(u64, String)>, ao3fti_common::Report> {
+    let records = sqlx::query!(r#"SELECT id as "id!: i64", summary FROM stories ORDER BY id"#)
+        .fetch_all(&pool)
+        .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| (record.id as u64, record.summary))
+        .collect())
+}
+
+#[tracing::instrument(skip(trans, summary), err)]
+pub async fn update_story_summary(
+    trans: &mut Transaction<'_, Sqlite>,
+    story_id: u64,
+    summary: &str,
+) -> Result<(), ao3fti_common::Report> {
+    let story_id = story_id as i64;
+
+    sqlx::query!(
+        "UPDATE stories SET summary = ? WHERE id = ?",
+        summary,
+        story_id,
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the start of a crawl, `search` is the serialized filters the url was built from, if
+/// it was built from any.
+#[tracing::instrument(skip(pool, search), err)]
+pub async fn insert_crawl(
+    pool: Pool,
+    url: &str,
+    search: Option<&str>,
+) -> Result<i64, ao3fti_common::Report> {
+    let result = sqlx::query!("INSERT INTO crawls(url, search) VALUES (?, ?)", url, search)
+        .execute(&pool)
+        .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Links a story to a crawl that came across it, whether or not the story was already stored.
+#[tracing::instrument(skip(trans), err)]
+pub async fn insert_crawl_story(
+    trans: &mut Transaction<'_, Sqlite>,
+    crawl_id: i64,
+    story_id: usize,
+) -> Result<(), ao3fti_common::Report> {
+    let story_id = story_id as i64;
+
+    sqlx::query!(
+        "INSERT OR IGNORE INTO crawl_stories(crawl_id, story_id) VALUES (?, ?)",
+        crawl_id,
+        story_id,
+    )
+    .execute(&mut *trans)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct Crawl {
+    pub id: i64,
+    pub url: String,
+    pub search: Option<String>,
+}
+
+/// Gets every crawl that came across a story, oldest first.
+#[tracing::instrument(skip(pool), err)]
+pub async fn get_story_crawls(
+    pool: Pool,
+    story_id: u64,
+) -> Result<Vec<Crawl>, ao3fti_common::Report> {
+    let story_id = story_id as i64;
+
+    let records = sqlx::query!(
+        r#"SELECT crawls.id as "id!: i64", crawls.url, crawls.search FROM crawls INNER JOIN crawl_stories ON crawl_stories.crawl_id = crawls.id WHERE crawl_stories.story_id = ? ORDER BY crawls.id"#,
+        story_id
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|record| Crawl {
+            id: record.id,
+            url: record.url,
+            search: record.search,
+        })
+        .collect())
+}
+
 #[tracing::instrument(skip(pool, uris), err)]
 pub async fn queue_insert(pool: Pool, uris: &[String]) -> Result<(), ao3fti_common::Report> {
     let mut conn = pool.acquire().await?;
@@ -250,16 +860,16 @@ pub async fn queue_next(pool: Pool) -> Result<Option<String>, ao3fti_common::Rep
 }
 
 // https://github.com/ayrat555/fang
-pub async fn queue_task(pool: Pool) -> Result<(), ao3fti_common::Report> {
+pub async fn queue_task(conf: Arc<Conf>, pool: Pool) -> Result<(), ao3fti_common::Report> {
     use tracing::Instrument as _;
 
-    async fn inner(pool: Pool) -> Result<(), ao3fti_common::Report> {
+    async fn inner(conf: Arc<Conf>, pool: Pool) -> Result<(), ao3fti_common::Report> {
         loop {
             if let Some(_uri) = queue_next(pool.clone()).await? {}
 
-            ao3fti_common::utils::sleep().await?;
+            ao3fti_common::utils::sleep(&conf).await?;
         }
     }
 
-    inner(pool).in_current_span().await
+    inner(conf, pool).in_current_span().await
 }
@@ -1,9 +1,14 @@
 mod verbose;
 
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use ao3fti_common::Conf;
-use clap::{FromArgMatches as _, IntoApp as _, Parser, Subcommand};
+use ao3fti_command_scrape::{
+    search::{Completion, Search, Sort, Warning},
+    Limits, Slice, SliceBy,
+};
+use ao3fti_common::{err, models::Rating, Conf};
+use clap::{Args, FromArgMatches as _, IntoApp as _, Parser, Subcommand};
+use time::Date;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{prelude::*, EnvFilter, Registry};
 use twelf::Layer;
@@ -21,17 +26,180 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// The URL to scrape and index
-    Scrape { url: String },
+    /// Scrape and index a search, from its URL or built from filters
+    Scrape(Box<ScrapeArgs>),
+    /// Scrape the synonyms, parents, and meta tags of every stored tag
+    ScrapeTags,
+    /// Scrape the works of every stored series in reading order
+    ScrapeSeries,
+    /// Re-sanitize the HTML of every stored story summary
+    Sanitize,
+    /// Replay WARC files of earlier crawls into the database and index
+    ImportWarc {
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// List clusters of indexed works whose text is nearly the same
+    Duplicates {
+        /// The share of their signatures two works agree on, from 0.5 to 1
+        #[clap(long, default_value_t = ao3fti_indexer::DEFAULT_MIN_SIMILARITY)]
+        min_similarity: f32,
+    },
     /// Start the built-in web server
     Serve,
 }
 
+#[derive(Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+#[clap(subcommand_negates_reqs = true)]
+struct ScrapeArgs {
+    #[clap(subcommand)]
+    command: Option<ScrapeCommands>,
+
+    /// The URL to scrape and index
+    #[clap(required = true)]
+    url: Option<String>,
+
+    #[clap(flatten)]
+    slice: SliceArgs,
+
+    #[clap(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(Subcommand)]
+enum ScrapeCommands {
+    /// Build the AO3 works search URL from filters and scrape it
+    Search {
+        #[clap(flatten)]
+        search: SearchArgs,
+
+        #[clap(flatten)]
+        slice: SliceArgs,
+
+        #[clap(flatten)]
+        limits: LimitArgs,
+    },
+}
+
+#[derive(Args)]
+struct SliceArgs {
+    /// Split the search into date windows of `created-at` or `revised-at`
+    #[clap(long)]
+    slice: Option<SliceBy>,
+    /// Number of works after which a listing stops paginating, windows with more are split
+    #[clap(long, default_value_t = 100_000)]
+    slice_cap: usize,
+}
+
+impl SliceArgs {
+    fn into_slice(self) -> Option<Slice> {
+        self.slice.map(|by| Slice {
+            by,
+            cap: self.slice_cap,
+        })
+    }
+}
+
+#[derive(Args)]
+struct LimitArgs {
+    /// Stop after this many listing pages
+    #[clap(long)]
+    max_pages: Option<usize>,
+    /// Stop after this many works, including ones that are already stored
+    #[clap(long)]
+    max_works: Option<usize>,
+    /// Skip works last updated before this `YYYY-MM-DD` date
+    #[clap(long, parse(try_from_str = ao3fti_command_scrape::parse_date))]
+    since: Option<Date>,
+    /// Print the works that would be scraped without storing or indexing anything
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl From<LimitArgs> for Limits {
+    fn from(args: LimitArgs) -> Self {
+        Limits {
+            max_pages: args.max_pages,
+            max_works: args.max_works,
+            since: args.since,
+            dry_run: args.dry_run,
+        }
+    }
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Free text to search every field for
+    #[clap(long)]
+    query: Option<String>,
+    /// A fandom the works must be in, can be repeated
+    #[clap(long)]
+    fandom: Vec<String>,
+    /// A relationship the works must have, can be repeated
+    #[clap(long)]
+    ship: Vec<String>,
+    /// A character the works must have, can be repeated
+    #[clap(long)]
+    character: Vec<String>,
+    /// An additional tag the works must have, can be repeated
+    #[clap(long)]
+    tag: Vec<String>,
+    /// A tag the works must not have, can be repeated
+    #[clap(long)]
+    exclude_tag: Vec<String>,
+    /// One of `general`, `teen`, `mature`, `explicit` or `not-rated`
+    #[clap(long)]
+    rating: Option<Rating>,
+    /// An archive warning the works must have, can be repeated
+    #[clap(long)]
+    warning: Vec<Warning>,
+    /// The language code of the works, such as `en`
+    #[clap(long)]
+    language: Option<String>,
+    /// The least words the works can have
+    #[clap(long)]
+    words_min: Option<u32>,
+    /// The most words the works can have
+    #[clap(long)]
+    words_max: Option<u32>,
+    /// Either `complete` or `in-progress`
+    #[clap(long)]
+    completion: Option<Completion>,
+    /// What to sort by, such as `updated`, `kudos` or `words`
+    #[clap(long)]
+    sort: Option<Sort>,
+    /// Sort in ascending instead of descending order
+    #[clap(long)]
+    ascending: bool,
+}
+
+impl From<SearchArgs> for Search {
+    fn from(args: SearchArgs) -> Self {
+        Search {
+            query: args.query,
+            fandoms: args.fandom,
+            ships: args.ship,
+            characters: args.character,
+            tags: args.tag,
+            excluded_tags: args.exclude_tag,
+            rating: args.rating,
+            warnings: args.warning,
+            language: args.language,
+            words_min: args.words_min,
+            words_max: args.words_max,
+            completion: args.completion,
+            sort: args.sort,
+            ascending: args.ascending,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), ao3fti_common::Report> {
     ao3fti_common::install()?;
 
-    let matches = Cli::command().args(&Conf::clap_args()).get_matches();
+    let matches = Cli::command().args(Conf::clap_args()).get_matches();
     let cli = Cli::from_arg_matches(&matches)?;
     let conf = Conf::with_layers(&[
         Layer::Env(Some("AO3FTI_".to_string())),
@@ -47,7 +215,36 @@ async fn main() -> Result<(), ao3fti_common::Report> {
     tracing::subscriber::set_global_default(subscriber)?;
 
     match cli.command {
-        Commands::Scrape { url } => ao3fti_command_scrape::run(conf, &url).await?,
+        Commands::Scrape(args) => match args.command {
+            Some(ScrapeCommands::Search {
+                search,
+                slice,
+                limits,
+            }) => {
+                ao3fti_command_scrape::search::run(
+                    conf,
+                    search.into(),
+                    slice.into_slice(),
+                    limits.into(),
+                )
+                .await?
+            }
+            None => {
+                let url = args
+                    .url
+                    .ok_or_else(|| err!("a url or `search` is required"))?;
+
+                ao3fti_command_scrape::run(conf, &url, args.slice.into_slice(), args.limits.into())
+                    .await?
+            }
+        },
+        Commands::ScrapeTags => ao3fti_command_scrape::tags::run(conf).await?,
+        Commands::ScrapeSeries => ao3fti_command_scrape::series::run(conf).await?,
+        Commands::Sanitize => ao3fti_command_scrape::sanitize::run(conf).await?,
+        Commands::ImportWarc { paths } => ao3fti_command_scrape::warc::run(conf, paths).await?,
+        Commands::Duplicates { min_similarity } => {
+            ao3fti_command_scrape::duplicates::run(conf, min_similarity).await?
+        }
         Commands::Serve => ao3fti_command_serve::run(conf).await?,
     }
 
@@ -101,6 +101,7 @@ impl LogLevel for ErrorLevel {
 }
 
 #[derive(Copy, Clone, Debug, Default)]
+#[allow(dead_code)]
 pub struct WarnLevel;
 
 impl LogLevel for WarnLevel {
@@ -110,6 +111,7 @@ impl LogLevel for WarnLevel {
 }
 
 #[derive(Copy, Clone, Debug, Default)]
+#[allow(dead_code)]
 pub struct InfoLevel;
 
 impl LogLevel for InfoLevel {